@@ -0,0 +1,155 @@
+use anyhow::{Context as AnyhowContext, Result};
+use sevenz_rust::{Archive, BlockDecoder};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use super::{read_to_vec, ArchiveBackend};
+
+pub struct SevenZipBackend {
+    path: PathBuf,
+    archive: Arc<Archive>,
+    names: Vec<String>,
+    // Position of each listed entry in the 7z file table.
+    file_indices: Vec<usize>,
+    cursor: Option<BlockCursor>,
+}
+
+// A thread decoding one block front to back and handing over its entries
+// one at a time. Blocks may be solid, so an entry can only be reached by
+// decoding everything before it; keeping the decoder going means reading
+// pages in order decodes the block once. Dropping the cursor stops the
+// thread after the entry it is on.
+struct BlockCursor {
+    folder: usize,
+    // File table index of the entry the thread hands over next.
+    next: usize,
+    entries: Receiver<Result<Vec<u8>>>,
+}
+
+impl BlockCursor {
+    fn start(path: &Path, archive: &Arc<Archive>, folder: usize) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let next = archive.stream_map.folder_first_file_index[folder];
+        let archive = Arc::clone(archive);
+        // Nothing is decoded ahead of the entry being asked for.
+        let (sender, entries) = mpsc::sync_channel(0);
+        thread::Builder::new().name("7z-block".to_string()).spawn(move || {
+            let result = BlockDecoder::new(folder, &archive, &[], &mut file).for_each_entries(&mut |entry, reader| {
+                let data = read_to_vec(reader, entry.size()).map_err(anyhow::Error::from);
+                let failed = data.is_err();
+                Ok(sender.send(data).is_ok() && !failed)
+            });
+            if let Err(e) = result {
+                let _ = sender.send(Err(anyhow::anyhow!("Failed to decode 7z block: {}", e)));
+            }
+        })?;
+        Ok(Self {
+            folder,
+            next,
+            entries,
+        })
+    }
+
+    fn read(&mut self, file_index: usize) -> Result<Vec<u8>> {
+        loop {
+            let data = self.entries.recv().context("7z block ended early")??;
+            self.next += 1;
+            if self.next > file_index {
+                return Ok(data);
+            }
+        }
+    }
+}
+
+impl SevenZipBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let archive = Archive::read(&mut file, len, &[])
+            .with_context(|| format!("Failed to read 7z archive: {}", path.display()))?;
+
+        let mut names = Vec::new();
+        let mut file_indices = Vec::new();
+        for (i, entry) in archive.files.iter().enumerate() {
+            if !entry.is_directory() {
+                names.push(entry.name().to_owned());
+                file_indices.push(i);
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            archive: Arc::new(archive),
+            names,
+            file_indices,
+            cursor: None,
+        })
+    }
+}
+
+impl ArchiveBackend for SevenZipBackend {
+    fn entry_names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read_entry(&mut self, index: usize) -> Result<Vec<u8>> {
+        let file_index = self.file_indices[index];
+        let Some(folder) = self.archive.stream_map.file_folder_index[file_index] else {
+            // Entries without a stream are empty files.
+            return Ok(Vec::new());
+        };
+
+        // Carry on from the last read when the entry is further along the
+        // same block, otherwise decode the block from its start.
+        let mut cursor = match self.cursor.take() {
+            Some(cursor) if cursor.folder == folder && cursor.next <= file_index => cursor,
+            _ => BlockCursor::start(&self.path, &self.archive, folder)?,
+        };
+        let data = cursor.read(file_index)?;
+        self.cursor = Some(cursor);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sevenz_rust::{SeqReader, SevenZArchiveEntry, SevenZWriter, SourceReader};
+
+    // A 7z archive with all of `entries` in one solid block.
+    fn write_solid(path: &Path, entries: &[(&str, Vec<u8>)]) {
+        let mut writer = SevenZWriter::create(path).unwrap();
+        let archive_entries = entries
+            .iter()
+            .map(|(name, _)| {
+                let mut entry = SevenZArchiveEntry::new();
+                entry.name = name.to_string();
+                entry.has_stream = true;
+                entry
+            })
+            .collect();
+        let readers = entries.iter().map(|(_, data)| SourceReader::new(&data[..])).collect();
+        writer.push_archive_entries(archive_entries, SeqReader::new(readers)).unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn reads_solid_entries_in_any_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cb7");
+        let entries: Vec<(&str, Vec<u8>)> = ["01.png", "02.png", "03.png", "04.png"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, vec![i as u8; 1000 * (i + 1)]))
+            .collect();
+        write_solid(&path, &entries);
+
+        let mut backend = SevenZipBackend::open(&path).unwrap();
+        assert_eq!(backend.entry_names(), ["01.png", "02.png", "03.png", "04.png"]);
+        for index in [0, 1, 3, 2, 2, 0] {
+            assert_eq!(backend.read_entry(index).unwrap(), entries[index].1, "entry {}", index);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use anyhow::{Context as AnyhowContext, Result};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use super::ArchiveBackend;
+
+// There is no pure Rust RAR decompressor, so RAR archives (v4 and v5) are
+// read through whichever extraction tool is installed on the system. The
+// first few reads, which are all that opening a volume or drawing its cover
+// takes, extract just their entry. After that the whole archive is
+// extracted to a temporary folder once, since RAR archives are usually solid
+// and each single entry would be decompressed from the start.
+#[derive(Debug, Clone, Copy)]
+enum Tool {
+    Unrar,
+    SevenZip(&'static str),
+    Bsdtar(&'static str),
+}
+
+const SINGLE_ENTRY_READS: usize = 2;
+
+const CANDIDATES: &[Tool] = &[
+    Tool::Unrar,
+    Tool::SevenZip("7z"),
+    Tool::SevenZip("7zz"),
+    Tool::Bsdtar("bsdtar"),
+    #[cfg(windows)]
+    Tool::Bsdtar("tar"),
+];
+
+impl Tool {
+    fn program(self) -> &'static str {
+        match self {
+            Tool::Unrar => "unrar",
+            Tool::SevenZip(program) | Tool::Bsdtar(program) => program,
+        }
+    }
+
+    fn is_installed(self) -> bool {
+        match Command::new(self.program()).arg("--help").output() {
+            Ok(_) => true,
+            Err(e) => e.kind() != ErrorKind::NotFound,
+        }
+    }
+
+    // Whether the tool would take `name` for exactly that entry rather than
+    // a pattern or a list file. 7z is told to match names literally.
+    fn matches_literally(self, name: &str) -> bool {
+        let special: &[char] = match self {
+            Tool::Unrar => &['*', '?'],
+            Tool::SevenZip(_) => &[],
+            Tool::Bsdtar(_) => &['*', '?', '[', '\\'],
+        };
+        !name.contains(special) && !name.starts_with(['@', '-'])
+    }
+}
+
+fn find_tool() -> Result<Tool> {
+    static TOOL: OnceLock<Option<Tool>> = OnceLock::new();
+    TOOL.get_or_init(|| CANDIDATES.iter().copied().find(|tool| tool.is_installed()))
+        .ok_or_else(|| anyhow::anyhow!("Opening RAR archives requires unrar, 7z or bsdtar to be installed"))
+}
+
+fn run(tool: Tool, args: &[&OsStr]) -> Result<Output> {
+    let output = Command::new(tool.program())
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", tool.program()))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{} failed: {}",
+            tool.program(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output)
+}
+
+// A temporary folder that is deleted with everything in it when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("manga_reader-rar-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        fs::create_dir(&path).with_context(|| format!("Failed to create folder: {}", path.display()))?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+struct Extracted {
+    dir: TempDir,
+    // Why the tool failed, if it did. Entries it managed to write are still
+    // readable.
+    error: Option<String>,
+}
+
+pub struct RarBackend {
+    tool: Tool,
+    path: PathBuf,
+    names: Vec<String>,
+    extracted: Option<Extracted>,
+    reads: usize,
+}
+
+impl RarBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_with(find_tool()?, path)
+    }
+
+    fn open_with(tool: Tool, path: &Path) -> Result<Self> {
+        let archive = path.as_os_str();
+        // `--` ends the switches, so an archive named `-x.cbr` is not taken
+        // for one. bsdtar reads the archive name as the argument of -f.
+        let output = match tool {
+            Tool::Unrar => run(tool, &["lb".as_ref(), "-p-".as_ref(), "--".as_ref(), archive])?,
+            Tool::SevenZip(_) => run(tool, &["l".as_ref(), "-slt".as_ref(), "-p".as_ref(), "--".as_ref(), archive])?,
+            Tool::Bsdtar(_) => run(tool, &["-tf".as_ref(), archive])?,
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        let names = match tool {
+            Tool::SevenZip(_) => parse_7z_listing(&stdout),
+            _ => stdout
+                .lines()
+                .filter(|line| !line.is_empty() && !line.ends_with('/'))
+                .map(str::to_owned)
+                .collect(),
+        };
+        Ok(Self {
+            tool,
+            path: path.to_path_buf(),
+            names: names.into_iter().filter(|name| is_contained(name)).collect(),
+            extracted: None,
+            reads: 0,
+        })
+    }
+
+    // Print a single entry to stdout, or None if the tool cannot be trusted
+    // to pick out just that entry by its name.
+    fn extract_entry(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        if !self.tool.matches_literally(name) {
+            return Ok(None);
+        }
+        let archive = self.path.as_os_str();
+        let output = match self.tool {
+            Tool::Unrar => run(
+                self.tool,
+                &["p".as_ref(), "-inul".as_ref(), "-p-".as_ref(), "--".as_ref(), archive, name.as_ref()],
+            )?,
+            Tool::SevenZip(_) => run(
+                self.tool,
+                &["e".as_ref(), "-so".as_ref(), "-spd".as_ref(), "-p".as_ref(), "--".as_ref(), archive, name.as_ref()],
+            )?,
+            Tool::Bsdtar(_) => run(self.tool, &["-xOf".as_ref(), archive, name.as_ref()])?,
+        };
+        Ok(Some(output.stdout))
+    }
+
+    fn extract(&self) -> Result<Extracted> {
+        let dir = TempDir::new()?;
+        let archive = self.path.as_os_str();
+        let result = match self.tool {
+            Tool::Unrar => {
+                // unrar takes the destination as a path ending in a separator.
+                let mut destination = dir.0.clone().into_os_string();
+                destination.push(std::path::MAIN_SEPARATOR_STR);
+                run(
+                    self.tool,
+                    &["x".as_ref(), "-p-".as_ref(), "-o+".as_ref(), "-inul".as_ref(), "--".as_ref(), archive, &destination],
+                )
+            }
+            Tool::SevenZip(_) => {
+                let mut output = OsString::from("-o");
+                output.push(&dir.0);
+                run(self.tool, &["x".as_ref(), "-p".as_ref(), "-y".as_ref(), &output, "--".as_ref(), archive])
+            }
+            Tool::Bsdtar(_) => run(self.tool, &["-xf".as_ref(), archive, "-C".as_ref(), dir.0.as_os_str()]),
+        };
+        Ok(Extracted {
+            dir,
+            error: result.err().map(|e| format!("{:#}", e)),
+        })
+    }
+}
+
+impl ArchiveBackend for RarBackend {
+    fn entry_names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read_entry(&mut self, index: usize) -> Result<Vec<u8>> {
+        let name = &self.names[index];
+        self.reads += 1;
+        if self.extracted.is_none() && self.reads <= SINGLE_ENTRY_READS {
+            if let Some(data) = self.extract_entry(name).with_context(|| format!("Failed to extract {}", name))? {
+                return Ok(data);
+            }
+        }
+
+        let extracted = match &self.extracted {
+            Some(extracted) => extracted,
+            None => self.extracted.insert(self.extract()?),
+        };
+        let path = extracted.dir.0.join(name);
+        match (fs::read(&path), &extracted.error) {
+            (Ok(data), _) => Ok(data),
+            (Err(_), Some(error)) => Err(anyhow::anyhow!("Failed to extract {}: {}", name, error)),
+            (Err(e), None) => Err(e).with_context(|| format!("Failed to extract {}", name)),
+        }
+    }
+}
+
+// Whether an entry stays inside the folder it is extracted to. Names that
+// are absolute or climb out with `..` are left out of the listing.
+fn is_contained(name: &str) -> bool {
+    let normalized = name.replace('\\', "/");
+    let has_drive = normalized.as_bytes().get(1) == Some(&b':');
+    !has_drive
+        && Path::new(&normalized)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+// `7z l -slt` prints one `Key = Value` block per entry after a dashed
+// separator line; the block before the separator describes the archive.
+fn parse_7z_listing(listing: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut current: Option<String> = None;
+    let mut is_folder = false;
+
+    for line in listing.lines().skip_while(|line| !line.starts_with("----------")).skip(1) {
+        if let Some(path) = line.strip_prefix("Path = ") {
+            if let Some(name) = current.take() {
+                if !is_folder {
+                    names.push(name);
+                }
+            }
+            current = Some(path.to_owned());
+            is_folder = false;
+        } else if line == "Folder = +" || (line.starts_with("Attributes = ") && line.contains('D')) {
+            is_folder = true;
+        }
+    }
+    if let Some(name) = current {
+        if !is_folder {
+            names.push(name);
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::testing;
+
+    // bsdtar reads zips too, which stands in for a RAR the tests cannot create.
+    #[test]
+    #[ignore = "needs bsdtar installed"]
+    fn reads_names_that_look_like_patterns_or_switches() {
+        let tool = Tool::Bsdtar("bsdtar");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbr");
+        let names = ["01.png", "[Group] 02.png", "-03.png", "sub/*04?.png"];
+        let entries: Vec<_> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (*name, testing::png(i as u32 + 1, 1)))
+            .collect();
+        testing::write_zip(
+            &path,
+            &entries
+                .iter()
+                .map(|(name, data)| (*name, &data[..], zip::CompressionMethod::Stored))
+                .collect::<Vec<_>>(),
+        );
+
+        let mut backend = RarBackend::open_with(tool, &path).unwrap();
+        assert_eq!(backend.entry_names(), names);
+        // The first reads extract only their entry.
+        assert_eq!(backend.read_entry(0).unwrap(), entries[0].1);
+        assert!(backend.extracted.is_none());
+        for (index, (_, data)) in entries.iter().enumerate() {
+            assert_eq!(&backend.read_entry(index).unwrap(), data);
+        }
+
+        let extracted = backend.extracted.as_ref().unwrap().dir.0.clone();
+        drop(backend);
+        assert!(!extracted.exists());
+    }
+
+    #[test]
+    fn leaves_out_names_outside_the_archive() {
+        for name in ["01.png", "sub/02.png", "./03.png", "sub\\04.png", "..png", "a..b/05.png"] {
+            assert!(is_contained(name), "{}", name);
+        }
+        let outside = ["/etc/passwd", "\\server\\share", "C:\\x.png", "c:x.png", "../01.png", "sub/../../02.png", "sub\\..\\..\\03.png"];
+        for name in outside {
+            assert!(!is_contained(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn parses_7z_listings() {
+        let listing = "Path = volume.cbr\nType = Rar\n\n----------\n\
+            Path = sub\nFolder = +\n\n\
+            Path = sub/01.png\nFolder = -\n\n\
+            Path = 02.png\nAttributes = A\n";
+        assert_eq!(parse_7z_listing(listing), ["sub/01.png", "02.png"]);
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tar::Archive;
+use xz2::read::XzDecoder;
+
+use super::{read_to_vec, ArchiveBackend, Compression};
+
+pub struct TarBackend {
+    path: PathBuf,
+    compression: Compression,
+    names: Vec<String>,
+    // Byte range of each entry's data within the uncompressed tar.
+    ranges: Vec<(u64, u64)>,
+    // A compressed stream left where the last read stopped, and its offset
+    // into the uncompressed tar. Pages are mostly read in order, so the next
+    // one usually only needs the stream to move forward.
+    cursor: Option<(Box<dyn Read + Send>, u64)>,
+}
+
+impl TarBackend {
+    pub fn open(path: &Path, compression: Compression) -> Result<Self> {
+        let mut archive = Archive::new(open_stream(path, compression)?);
+        let mut names = Vec::new();
+        let mut ranges = Vec::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            names.push(entry.path()?.to_string_lossy().into_owned());
+            ranges.push((entry.raw_file_position(), entry.size()));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            compression,
+            names,
+            ranges,
+            cursor: None,
+        })
+    }
+}
+
+fn open_stream(path: &Path, compression: Compression) -> Result<Box<dyn Read + Send>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(GzDecoder::new(file)),
+        Compression::Xz => Box::new(XzDecoder::new(file)),
+    })
+}
+
+impl ArchiveBackend for TarBackend {
+    fn entry_names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read_entry(&mut self, index: usize) -> Result<Vec<u8>> {
+        if self.compression == Compression::None {
+            let (offset, size) = self.ranges[index];
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            return Ok(read_to_vec(&mut file.take(size), size)?);
+        }
+
+        // Compressed streams cannot seek: go forward from the last read, or
+        // start over for an earlier entry.
+        let (offset, size) = self.ranges[index];
+        let (mut stream, position) = match self.cursor.take() {
+            Some((stream, position)) if position <= offset => (stream, position),
+            _ => (open_stream(&self.path, self.compression)?, 0),
+        };
+        let skip = offset - position;
+        if io::copy(&mut (&mut stream).take(skip), &mut io::sink())? < skip {
+            return Err(anyhow::anyhow!("Entry not found in archive: {}", self.names[index]));
+        }
+        let data = read_to_vec(&mut (&mut stream).take(size), size)?;
+        self.cursor = Some((stream, offset + data.len() as u64));
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+
+    #[test]
+    fn reads_compressed_entries_in_any_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbt");
+        let entries: Vec<(&str, Vec<u8>)> = ["01.png", "02.png", "03.png"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, vec![i as u8; 700 * (i + 1)]))
+            .collect();
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&path).unwrap(), Default::default()));
+        for (name, data) in &entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, &data[..]).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let mut backend = TarBackend::open(&path, Compression::Gzip).unwrap();
+        assert_eq!(backend.entry_names(), ["01.png", "02.png", "03.png"]);
+        for index in [0, 2, 1, 1, 2, 0] {
+            assert_eq!(backend.read_entry(index).unwrap(), entries[index].1, "entry {}", index);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use zip::ZipArchive;
+
+use super::{read_to_vec, ArchiveBackend};
+
+pub struct ZipBackend {
+    archive: ZipArchive<BufReader<File>>,
+    names: Vec<String>,
+    // Position of each listed entry in the zip central directory.
+    zip_indices: Vec<usize>,
+}
+
+impl ZipBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+        let mut names = Vec::new();
+        let mut zip_indices = Vec::new();
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if !file.is_dir() {
+                names.push(file.name().to_owned());
+                zip_indices.push(i);
+            }
+        }
+        Ok(Self { archive, names, zip_indices })
+    }
+}
+
+impl ArchiveBackend for ZipBackend {
+    fn entry_names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read_entry(&mut self, index: usize) -> Result<Vec<u8>> {
+        let mut file = self.archive.by_index(self.zip_indices[index])?;
+        let size = file.size();
+        Ok(read_to_vec(&mut file, size)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::testing;
+
+    #[test]
+    fn oversized_entry_header_does_not_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.cbz");
+        testing::write_zip_with_oversized_entry(&path);
+
+        let mut backend = ZipBackend::open(&path).unwrap();
+        let data = backend.read_entry(0).unwrap();
+        assert_eq!(data, testing::png(4, 4));
+    }
+}
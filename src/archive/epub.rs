@@ -0,0 +1,288 @@
+use anyhow::{Context as AnyhowContext, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::cbz::ZipBackend;
+use super::{has_image_extension, read_entry_by_name, ArchiveBackend};
+use crate::ReadingDirection;
+
+// A fixed-layout EPUB: a zip whose page order comes from the OPF spine
+// rather than from file names.
+pub struct EpubBackend {
+    zip: ZipBackend,
+    order: Vec<usize>,
+    direction: Option<ReadingDirection>,
+}
+
+struct ManifestItem {
+    href: String,
+    media_type: String,
+}
+
+impl EpubBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut zip = ZipBackend::open(path)?;
+
+        let container = read_text(&mut zip, "META-INF/container.xml")?;
+        let opf_path = find_rootfile(&container)
+            .ok_or_else(|| anyhow::anyhow!("EPUB container.xml has no rootfile: {}", path.display()))?;
+        let opf = read_text(&mut zip, &opf_path)?;
+        let opf_dir = parent_dir(&opf_path);
+
+        let (manifest, spine, direction) = parse_opf(&opf);
+
+        let mut order = Vec::new();
+        for idref in spine {
+            let Some(item) = manifest.get(&idref) else {
+                continue;
+            };
+            let item_path = resolve_href(opf_dir, &item.href);
+
+            let image_path = if item.media_type.starts_with("image/") {
+                Some(item_path)
+            } else {
+                // Fixed-layout pages wrap a single image in an XHTML or SVG document.
+                read_text(&mut zip, &item_path)
+                    .ok()
+                    .and_then(|page| find_page_image(&page))
+                    .map(|href| resolve_href(parent_dir(&item_path), &href))
+            };
+
+            if let Some(index) = image_path
+                .filter(|image| has_image_extension(image))
+                .and_then(|image| zip.entry_names().iter().position(|name| *name == image))
+            {
+                order.push(index);
+            }
+        }
+
+        Ok(Self { zip, order, direction })
+    }
+}
+
+impl ArchiveBackend for EpubBackend {
+    fn entry_names(&self) -> &[String] {
+        self.zip.entry_names()
+    }
+
+    fn read_entry(&mut self, index: usize) -> Result<Vec<u8>> {
+        self.zip.read_entry(index)
+    }
+
+    fn reading_order(&self) -> Option<Vec<usize>> {
+        // Fall back to file name order if the spine had no usable pages.
+        (!self.order.is_empty()).then(|| self.order.clone())
+    }
+
+    fn reading_direction(&self) -> Option<ReadingDirection> {
+        self.direction
+    }
+}
+
+fn read_text(zip: &mut ZipBackend, name: &str) -> Result<String> {
+    let bytes = read_entry_by_name(zip, name).with_context(|| format!("Missing EPUB entry: {}", name))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == name)
+        .map(|attr| match attr.unescape_value() {
+            Ok(value) => value.into_owned(),
+            Err(_) => String::from_utf8_lossy(&attr.value).into_owned(),
+        })
+}
+
+fn find_rootfile(container: &str) -> Option<String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"rootfile" => {
+                return attribute(&e, b"full-path");
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+fn parse_opf(opf: &str) -> (HashMap<String, ManifestItem>, Vec<String>, Option<ReadingDirection>) {
+    let mut manifest = HashMap::new();
+    let mut spine = Vec::new();
+    let mut direction = None;
+
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"item" => {
+                    if let (Some(id), Some(href)) = (attribute(&e, b"id"), attribute(&e, b"href")) {
+                        let media_type = attribute(&e, b"media-type").unwrap_or_default();
+                        manifest.insert(id, ManifestItem { href, media_type });
+                    }
+                }
+                b"spine" => {
+                    direction = match attribute(&e, b"page-progression-direction").as_deref() {
+                        Some("rtl") => Some(ReadingDirection::RightToLeft),
+                        Some("ltr") => Some(ReadingDirection::LeftToRight),
+                        _ => None,
+                    };
+                }
+                b"itemref" => {
+                    if let Some(idref) = attribute(&e, b"idref") {
+                        spine.push(idref);
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    (manifest, spine, direction)
+}
+
+// The first image referenced by a page document, via `<img src>` or an SVG
+// `<image href>`/`<image xlink:href>`.
+fn find_page_image(page: &str) -> Option<String> {
+    let mut reader = Reader::from_str(page);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"img" => return attribute(&e, b"src"),
+                b"image" => return attribute(&e, b"href"),
+                _ => {}
+            },
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+// Resolve a relative, percent-encoded href against a directory inside the zip.
+fn resolve_href(base_dir: &str, href: &str) -> String {
+    let href = href.split(['#', '?']).next().unwrap_or_default();
+    let mut parts: Vec<String> = base_dir
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if href.starts_with('/') {
+        parts.clear();
+    }
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(percent_decode(part)),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = input.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                output.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        output.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::testing;
+    use zip::CompressionMethod;
+
+    const OPF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <manifest>
+    <item id="p1" href="text/page%201.xhtml" media-type="application/xhtml+xml"/>
+    <item id="p2" href="text/page2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="cover" href="images/cover.png" media-type="image/png"/>
+  </manifest>
+  <spine page-progression-direction="rtl">
+    <itemref idref="cover"/>
+    <itemref idref="p2"/>
+    <itemref idref="missing"/>
+    <itemref idref="p1"/>
+  </spine>
+</package>"#;
+
+    #[test]
+    fn resolves_hrefs_against_their_document() {
+        assert_eq!(resolve_href("OEBPS", "images/001.png"), "OEBPS/images/001.png");
+        assert_eq!(resolve_href("OEBPS/text", "../images/001.png"), "OEBPS/images/001.png");
+        assert_eq!(resolve_href("OEBPS/text", "./001.png#page"), "OEBPS/text/001.png");
+        assert_eq!(resolve_href("OEBPS/text", "/images/001.png?v=2"), "images/001.png");
+        assert_eq!(resolve_href("", "page%201%2Ejpg"), "page 1.jpg");
+        assert_eq!(resolve_href("", "100%.png"), "100%.png");
+    }
+
+    #[test]
+    fn reads_manifest_spine_and_direction() {
+        let (manifest, spine, direction) = parse_opf(OPF);
+        assert_eq!(manifest.len(), 3);
+        assert_eq!(manifest["p1"].href, "text/page%201.xhtml");
+        assert_eq!(manifest["cover"].media_type, "image/png");
+        assert_eq!(spine, ["cover", "p2", "missing", "p1"]);
+        assert_eq!(direction, Some(ReadingDirection::RightToLeft));
+
+        let (_, _, direction) = parse_opf("<package><spine></spine></package>");
+        assert_eq!(direction, None);
+    }
+
+    #[test]
+    fn orders_pages_by_spine() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.epub");
+        let container = br#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#;
+        let page1 = br#"<html><body><img src="../images/a.png"/></body></html>"#;
+        let page2 = br#"<svg xmlns:xlink="http://www.w3.org/1999/xlink"><image xlink:href="../images/b.png"/></svg>"#;
+        let image = testing::png(4, 4);
+        testing::write_zip(
+            &path,
+            &[
+                ("mimetype", b"application/epub+zip", CompressionMethod::Stored),
+                ("META-INF/container.xml", container, CompressionMethod::Deflated),
+                ("OEBPS/content.opf", OPF.as_bytes(), CompressionMethod::Deflated),
+                ("OEBPS/text/page 1.xhtml", page1, CompressionMethod::Deflated),
+                ("OEBPS/text/page2.xhtml", page2, CompressionMethod::Deflated),
+                ("OEBPS/images/a.png", &image, CompressionMethod::Stored),
+                ("OEBPS/images/b.png", &image, CompressionMethod::Stored),
+                ("OEBPS/images/cover.png", &image, CompressionMethod::Stored),
+            ],
+        );
+
+        let backend = EpubBackend::open(&path).unwrap();
+        let order: Vec<&str> = backend
+            .reading_order()
+            .unwrap()
+            .into_iter()
+            .map(|index| backend.entry_names()[index].as_str())
+            .collect();
+        assert_eq!(order, ["OEBPS/images/cover.png", "OEBPS/images/b.png", "OEBPS/images/a.png"]);
+        assert_eq!(backend.reading_direction(), Some(ReadingDirection::RightToLeft));
+    }
+}
@@ -0,0 +1,331 @@
+use anyhow::{Context as AnyhowContext, Result};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::comicinfo::{self, ComicInfo};
+use crate::{natural_sort, platform, ReadingDirection};
+
+mod cb7;
+mod cbr;
+mod cbt;
+mod cbz;
+mod epub;
+mod pack;
+mod pdf;
+mod rewrite;
+#[cfg(test)]
+pub(crate) mod testing;
+
+pub use pack::{default_pack_path, pack_folder, PackOptions};
+pub use rewrite::{rewrite_zip, EntryEdit, NewEntry};
+
+const ZIP_MAGIC: &[&[u8]] = &[b"PK\x03\x04", b"PK\x05\x06", b"PK\x07\x08"];
+const RAR4_MAGIC: &[u8] = b"Rar!\x1a\x07\x00";
+const RAR5_MAGIC: &[u8] = b"Rar!\x1a\x07\x01\x00";
+const SEVEN_ZIP_MAGIC: &[u8] = b"7z\xbc\xaf\x27\x1c";
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const XZ_MAGIC: &[u8] = b"\xfd7zXZ\x00";
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+const PDF_MAGIC: &[u8] = b"%PDF-";
+// EPUB requires an uncompressed `mimetype` file as the first zip entry.
+const EPUB_MIMETYPE_OFFSET: usize = 30;
+const EPUB_MIMETYPE: &[u8] = b"mimetypeapplication/epub+zip";
+
+// Entry sizes come from archive headers, which a damaged archive can get
+// wildly wrong, so at most this much is reserved before reading.
+const MAX_PREALLOCATION: u64 = 64 << 20;
+
+pub const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "gif"];
+pub const ARCHIVE_EXTENSIONS: &[&str] = &["cbz", "zip", "cbr", "rar", "cb7", "7z", "cbt", "tar", "pdf", "epub"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Epub,
+    Rar,
+    SevenZip,
+    Tar(Compression),
+    Pdf,
+}
+
+// A readable comic archive. Each format lists its file entries once when
+// opened and then hands back the bytes of any entry by its index in that list.
+pub trait ArchiveBackend: Send {
+    // Names of all file entries, in the order the archive stores them.
+    fn entry_names(&self) -> &[String];
+
+    fn read_entry(&mut self, index: usize) -> Result<Vec<u8>>;
+
+    // Entry indices of the pages in reading order, for formats that define
+    // one. Other formats have their images sorted by name.
+    fn reading_order(&self) -> Option<Vec<usize>> {
+        None
+    }
+
+    fn reading_direction(&self) -> Option<ReadingDirection> {
+        None
+    }
+}
+
+// Read an entry whose header claims `size` bytes.
+pub fn read_to_vec<R: Read + ?Sized>(reader: &mut R, size: u64) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize);
+    reader.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+pub fn has_image_extension(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+pub fn has_archive_extension(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ARCHIVE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+// Sniff the archive format from the first bytes of the file. The extension
+// is not trusted because `.cbr` files are frequently zips and vice versa.
+pub fn detect_kind(path: &Path) -> Result<ArchiveKind> {
+    let mut header = Vec::with_capacity(512);
+    File::open(path)
+        .with_context(|| format!("Failed to open archive: {}", path.display()))?
+        .take(512)
+        .read_to_end(&mut header)?;
+
+    if ZIP_MAGIC.iter().any(|magic| header.starts_with(magic)) {
+        let is_epub = header.get(EPUB_MIMETYPE_OFFSET..EPUB_MIMETYPE_OFFSET + EPUB_MIMETYPE.len())
+            == Some(EPUB_MIMETYPE);
+        Ok(if is_epub { ArchiveKind::Epub } else { ArchiveKind::Zip })
+    } else if header.starts_with(RAR4_MAGIC) || header.starts_with(RAR5_MAGIC) {
+        Ok(ArchiveKind::Rar)
+    } else if header.starts_with(SEVEN_ZIP_MAGIC) {
+        Ok(ArchiveKind::SevenZip)
+    } else if header.starts_with(PDF_MAGIC) {
+        Ok(ArchiveKind::Pdf)
+    } else if header.starts_with(GZIP_MAGIC) {
+        Ok(ArchiveKind::Tar(Compression::Gzip))
+    } else if header.starts_with(XZ_MAGIC) {
+        Ok(ArchiveKind::Tar(Compression::Xz))
+    } else if header.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) == Some(TAR_MAGIC) {
+        Ok(ArchiveKind::Tar(Compression::None))
+    } else {
+        // Pre-POSIX tar files carry no magic, so fall back to the extension.
+        let ext = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        match ext.as_deref() {
+            Some("cbt") | Some("tar") => Ok(ArchiveKind::Tar(Compression::None)),
+            _ => Err(anyhow::anyhow!("Unrecognized archive format: {}", path.display())),
+        }
+    }
+}
+
+pub fn open(path: &Path) -> Result<Box<dyn ArchiveBackend>> {
+    let backend: Box<dyn ArchiveBackend> = match detect_kind(path)? {
+        ArchiveKind::Zip => Box::new(cbz::ZipBackend::open(path)?),
+        ArchiveKind::Epub => Box::new(epub::EpubBackend::open(path)?),
+        ArchiveKind::Rar => Box::new(cbr::RarBackend::open(path)?),
+        ArchiveKind::SevenZip => Box::new(cb7::SevenZipBackend::open(path)?),
+        ArchiveKind::Tar(compression) => Box::new(cbt::TarBackend::open(path, compression)?),
+        ArchiveKind::Pdf => Box::new(pdf::PdfBackend::open(path)?),
+    };
+    Ok(backend)
+}
+
+// Indices of the entries that are pages, skipping junk files.
+pub fn image_entries(backend: &dyn ArchiveBackend) -> Vec<usize> {
+    backend
+        .entry_names()
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| !platform::is_junk_archive_entry(name) && has_image_extension(name))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+// Indices of the pages in reading order.
+pub fn page_entries(backend: &dyn ArchiveBackend) -> Vec<usize> {
+    if let Some(order) = backend.reading_order() {
+        return order;
+    }
+    let names = backend.entry_names();
+    let mut entries = image_entries(backend);
+    entries.sort_by(|&a, &b| natural_sort(&names[a], &names[b]));
+    entries
+}
+
+pub fn read_entry_by_name(backend: &mut dyn ArchiveBackend, name: &str) -> Result<Vec<u8>> {
+    let index = backend
+        .entry_names()
+        .iter()
+        .position(|entry| entry == name)
+        .ok_or_else(|| anyhow::anyhow!("Entry not found in archive: {}", name))?;
+    backend.read_entry(index)
+}
+
+// Entry index of the volume's ComicInfo.xml, preferring the shallowest one.
+pub fn comic_info_entry(backend: &dyn ArchiveBackend) -> Option<usize> {
+    backend
+        .entry_names()
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| {
+            !platform::is_junk_archive_entry(name)
+                && name
+                    .rsplit(['/', '\\'])
+                    .next()
+                    .is_some_and(|file| file.eq_ignore_ascii_case(comicinfo::FILE_NAME))
+        })
+        .min_by_key(|(_, name)| name.matches(['/', '\\']).count())
+        .map(|(index, _)| index)
+}
+
+// Parse the volume's ComicInfo.xml. A broken file is reported and ignored.
+pub fn read_comic_info(backend: &mut dyn ArchiveBackend) -> Option<ComicInfo> {
+    let index = comic_info_entry(backend)?;
+    let parsed = backend
+        .read_entry(index)
+        .and_then(|bytes| comicinfo::parse(&String::from_utf8_lossy(&bytes)));
+    match parsed {
+        Ok(info) => Some(info),
+        Err(e) => {
+            eprintln!("Ignoring {}: {:#}", backend.entry_names()[index], e);
+            None
+        }
+    }
+}
+
+// Store `info` as the ComicInfo.xml of a CBZ, replacing the existing one or
+// adding it at the archive root.
+pub fn write_comic_info(path: &Path, info: &ComicInfo) -> Result<()> {
+    let existing = {
+        let backend = open(path)?;
+        comic_info_entry(backend.as_ref()).map(|index| backend.entry_names()[index].clone())
+    };
+    let xml = info.to_xml().into_bytes();
+    match existing {
+        Some(name) => rewrite_zip(
+            path,
+            false,
+            |entry| Ok(if entry == name { EntryEdit::Replace(xml.clone()) } else { EntryEdit::Keep }),
+            Vec::new(),
+        ),
+        None => rewrite_zip(
+            path,
+            false,
+            |_| Ok(EntryEdit::Keep),
+            vec![NewEntry {
+                name: comicinfo::FILE_NAME.to_string(),
+                data: xml,
+                compression: zip::CompressionMethod::Deflated,
+            }],
+        ),
+    }
+}
+
+// Remove the page stored as entry `name` from a CBZ, renumbering the page
+// list of its ComicInfo.xml to match.
+pub fn remove_page(path: &Path, name: &str, backup: bool) -> Result<()> {
+    let (comic_info_name, comic_info) = {
+        let mut backend = open(path)?;
+        let page = page_entries(backend.as_ref())
+            .into_iter()
+            .position(|index| backend.entry_names()[index] == name)
+            .ok_or_else(|| anyhow::anyhow!("Page not found in archive: {}", name))?;
+        let comic_info_name = comic_info_entry(backend.as_ref()).map(|index| backend.entry_names()[index].clone());
+        let comic_info = read_comic_info(backend.as_mut()).map(|mut info| {
+            info.remove_page(page);
+            info.to_xml().into_bytes()
+        });
+        (comic_info_name, comic_info)
+    };
+    let mut comic_info = comic_info;
+
+    rewrite_zip(
+        path,
+        backup,
+        |entry| {
+            Ok(if entry == name {
+                EntryEdit::Remove
+            } else if Some(entry) == comic_info_name.as_deref() {
+                match comic_info.take() {
+                    Some(xml) => EntryEdit::Replace(xml),
+                    None => EntryEdit::Keep,
+                }
+            } else {
+                EntryEdit::Keep
+            })
+        },
+        Vec::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zip::CompressionMethod;
+
+    #[test]
+    fn remove_page_renumbers_comic_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo>
+  <Series>Series</Series>
+  <PageCount>3</PageCount>
+  <Pages>
+    <Page Image="0" Type="FrontCover" />
+    <Page Image="1" Type="Story" />
+    <Page Image="2" Type="BackCover" />
+  </Pages>
+</ComicInfo>"#;
+        testing::write_zip(
+            &path,
+            &[
+                ("ComicInfo.xml", xml.as_bytes(), CompressionMethod::Deflated),
+                ("001.png", b"first", CompressionMethod::Stored),
+                ("002.png", b"second", CompressionMethod::Stored),
+                ("003.png", b"third", CompressionMethod::Stored),
+            ],
+        );
+
+        remove_page(&path, "002.png", false).unwrap();
+
+        let mut backend = open(&path).unwrap();
+        assert_eq!(backend.entry_names(), ["ComicInfo.xml", "001.png", "003.png"]);
+        let info = read_comic_info(backend.as_mut()).unwrap();
+        assert_eq!(info.get("Series"), Some("Series"));
+        assert_eq!(info.get("PageCount"), Some("2"));
+        let pages: Vec<(Option<usize>, Option<&str>)> =
+            info.pages.iter().map(|page| (page.image(), page.page_type())).collect();
+        assert_eq!(pages, [(Some(0), Some("FrontCover")), (Some(1), Some("BackCover"))]);
+    }
+
+    #[test]
+    fn remove_page_without_comic_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        testing::write_zip(
+            &path,
+            &[
+                ("b.png", b"second", CompressionMethod::Stored),
+                ("a.png", b"first", CompressionMethod::Stored),
+            ],
+        );
+
+        remove_page(&path, "a.png", true).unwrap();
+        assert_eq!(open(&path).unwrap().entry_names(), ["b.png"]);
+        assert!(remove_page(&path, "a.png", true).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{Context as AnyhowContext, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::rewrite::{sync_parent_dir, temp_path_for};
+use crate::comicinfo::{self, ComicInfo, PageInfo};
+use crate::image_files_in_directory;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PackOptions {
+    // Name pages 001.jpg, 002.jpg, ... instead of keeping their file names.
+    pub pad_names: bool,
+    // Embed a ComicInfo.xml built from the folder name and the pages, merged
+    // into the folder's own ComicInfo.xml when it has one.
+    pub comic_info: bool,
+    pub overwrite: bool,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            pad_names: false,
+            comic_info: true,
+            overwrite: false,
+        }
+    }
+}
+
+// `<folder>.cbz` next to the folder.
+pub fn default_pack_path(dir: &Path) -> PathBuf {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".cbz");
+    dir.with_file_name(name)
+}
+
+// Pack the images of a folder into a CBZ in reading order. Images are
+// stored without compression since they are already compressed. Returns the
+// number of pages written.
+pub fn pack_folder(dir: &Path, output: &Path, options: &PackOptions) -> Result<usize> {
+    let pages = image_files_in_directory(dir);
+    if pages.is_empty() {
+        anyhow::bail!("No images found in directory: {}", dir.display());
+    }
+    if output.exists() && !options.overwrite {
+        anyhow::bail!("Output already exists: {}", output.display());
+    }
+
+    let temp_path = temp_path_for(output);
+    let temp_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .with_context(|| format!("Failed to create temporary file: {}", temp_path.display()))?;
+
+    let result = write_pack(dir, &pages, temp_file, options)
+        .and_then(|file| Ok(file.sync_all()?))
+        .and_then(|_| fs::rename(&temp_path, output).with_context(|| format!("Failed to write {}", output.display())));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    sync_parent_dir(output);
+    Ok(pages.len())
+}
+
+fn page_name(page: usize, path: &Path, count: usize, pad_names: bool) -> String {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    if !pad_names {
+        return file_name;
+    }
+    let width = count.to_string().len().max(3);
+    match path.extension() {
+        Some(ext) => format!("{:0width$}.{}", page + 1, ext.to_string_lossy().to_lowercase()),
+        None => format!("{:0width$}", page + 1),
+    }
+}
+
+fn write_pack(dir: &Path, pages: &[PathBuf], file: File, options: &PackOptions) -> Result<File> {
+    let mut writer = ZipWriter::new(BufWriter::new(file));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    if options.comic_info {
+        let info = generate_comic_info(dir, pages);
+        writer.start_file(comicinfo::FILE_NAME, SimpleFileOptions::default())?;
+        writer.write_all(info.to_xml().as_bytes())?;
+    }
+
+    for (page, path) in pages.iter().enumerate() {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let entry_options = stored.large_file(data.len() as u64 >= u32::MAX as u64);
+        writer.start_file(page_name(page, path, pages.len(), options.pad_names), entry_options)?;
+        writer.write_all(&data)?;
+    }
+
+    let buffered = writer.finish()?;
+    buffered.into_inner().map_err(|e| e.into_error().into())
+}
+
+fn generate_comic_info(dir: &Path, pages: &[PathBuf]) -> ComicInfo {
+    let mut info = comicinfo::load_from_dir(dir).unwrap_or_default();
+    if info.get("Title").is_none() {
+        info.set("Title", &dir.file_name().unwrap_or_default().to_string_lossy());
+    }
+    info.set("PageCount", &pages.len().to_string());
+    if info.pages.is_empty() {
+        info.pages = pages
+            .iter()
+            .enumerate()
+            .map(|(page, path)| {
+                let mut attributes = vec![("Image".to_string(), page.to_string())];
+                if page == 0 {
+                    attributes.push(("Type".to_string(), "FrontCover".to_string()));
+                }
+                if let Ok(metadata) = fs::metadata(path) {
+                    attributes.push(("ImageSize".to_string(), metadata.len().to_string()));
+                }
+                if let Ok((width, height)) = image::image_dimensions(path) {
+                    attributes.push(("ImageWidth".to_string(), width.to_string()));
+                    attributes.push(("ImageHeight".to_string(), height.to_string()));
+                }
+                PageInfo { attributes }
+            })
+            .collect();
+    }
+    info
+}
@@ -0,0 +1,647 @@
+use anyhow::{Context as AnyhowContext, Result};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::imageops::{self, FilterType as ResizeFilter};
+use image::{DynamicImage, GrayImage, ImageEncoder, ImageFormat, RgbImage, Rgba, RgbaImage};
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::path::Path;
+
+use super::ArchiveBackend;
+
+// Rasterized pages are never made larger than this on their long side.
+const MAX_RASTER_SIZE: f32 = 8000.0;
+// Resolution used when a page has no image to take its resolution from.
+const DEFAULT_DPI: f32 = 150.0;
+const MAX_FORM_DEPTH: usize = 4;
+// Page trees are shallow; a deeper chain of parents is a reference loop.
+const MAX_PAGE_TREE_DEPTH: usize = 64;
+
+// Exposes every page of a PDF as one archive entry. Pages that are a single
+// embedded JPEG or JPEG 2000 image are handed out as-is; everything else is
+// rasterized on the CPU by compositing the page's images onto a canvas of
+// the page size.
+pub struct PdfBackend {
+    document: Document,
+    pages: Vec<ObjectId>,
+    names: Vec<String>,
+}
+
+// A 2D affine transform in PDF order: [a b c d e f].
+#[derive(Debug, Clone, Copy)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn from_operands(operands: &[Object]) -> Option<Matrix> {
+        let values: Vec<f32> = operands.iter().filter_map(|o| o.as_float().ok()).collect();
+        values.try_into().ok().map(Matrix)
+    }
+
+    // `self` applied first, then `other`.
+    fn then(self, other: Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Matrix([
+            a * a2 + b * c2,
+            a * b2 + b * d2,
+            c * a2 + d * c2,
+            c * b2 + d * d2,
+            e * a2 + f * c2 + e2,
+            e * b2 + f * d2 + f2,
+        ])
+    }
+
+    fn apply(self, x: f32, y: f32) -> (f32, f32) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * x + c * y + e, b * x + d * y + f)
+    }
+}
+
+// An image drawn on a page, with the bounding box of the unit square it is
+// mapped to in page space.
+struct Placement {
+    image: DynamicImage,
+    min: (f32, f32),
+    max: (f32, f32),
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl PdfBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        let document = Document::load(path)
+            .with_context(|| format!("Failed to read PDF: {}", path.display()))?;
+        let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+        let names = pages
+            .iter()
+            .enumerate()
+            .map(|(i, &page)| {
+                let extension = single_image(&document, page).map_or("png", |(_, extension)| extension);
+                format!("page-{:04}.{}", i + 1, extension)
+            })
+            .collect();
+        Ok(Self { document, pages, names })
+    }
+}
+
+impl ArchiveBackend for PdfBackend {
+    fn entry_names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read_entry(&mut self, index: usize) -> Result<Vec<u8>> {
+        let page = self.pages[index];
+        if let Some((stream, _)) = single_image(&self.document, page) {
+            return decode_to_last_filter(stream);
+        }
+
+        let canvas = rasterize_page(&self.document, page)?;
+        let mut buffer = Vec::new();
+        PngEncoder::new_with_quality(&mut buffer, CompressionType::Fast, FilterType::NoFilter)
+            .write_image(canvas.as_raw(), canvas.width(), canvas.height(), image::ExtendedColorType::Rgba8)?;
+        Ok(buffer)
+    }
+
+    fn reading_order(&self) -> Option<Vec<usize>> {
+        Some((0..self.pages.len()).collect())
+    }
+}
+
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    document.dereference(object).ok().map(|(_, object)| object)
+}
+
+fn dict_entry<'a>(document: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    dict.get(key).ok().and_then(|object| resolve(document, object))
+}
+
+// The resource dictionaries visible to a page, innermost first.
+fn page_resources(document: &Document, page: ObjectId) -> Vec<&Dictionary> {
+    let Ok((direct, inherited)) = document.get_page_resources(page) else {
+        return Vec::new();
+    };
+    direct
+        .into_iter()
+        .chain(inherited.into_iter().filter_map(|id| document.get_dictionary(id).ok()))
+        .collect()
+}
+
+fn find_xobject<'a>(document: &'a Document, resources: &[&'a Dictionary], name: &[u8]) -> Option<&'a Stream> {
+    resources.iter().find_map(|dict| {
+        let xobjects = dict_entry(document, dict, b"XObject")?.as_dict().ok()?;
+        dict_entry(document, xobjects, name)?.as_stream().ok()
+    })
+}
+
+fn is_image(stream: &Stream) -> bool {
+    stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image")
+}
+
+fn last_filter(stream: &Stream) -> Option<String> {
+    stream.filters().ok().and_then(|filters| filters.last().cloned())
+}
+
+// A page that consists of exactly one JPEG or JPEG 2000 image can be handed
+// out without rasterizing, which keeps the original quality and is much
+// faster. Returns the image and the extension of its format.
+fn single_image(document: &Document, page: ObjectId) -> Option<(&Stream, &'static str)> {
+    let resources = page_resources(document, page);
+    let mut images = resources.iter().flat_map(|dict| {
+        dict_entry(document, dict, b"XObject")
+            .and_then(|xobjects| xobjects.as_dict().ok())
+            .into_iter()
+            .flat_map(|xobjects| xobjects.iter())
+            .filter_map(|(_, object)| resolve(document, object)?.as_stream().ok())
+            .filter(|stream| is_image(stream))
+    });
+
+    let first = images.next()?;
+    if images.next().is_some() {
+        return None;
+    }
+    match last_filter(first).as_deref() {
+        Some("DCTDecode") => Some((first, "jpg")),
+        Some("JPXDecode") => Some((first, "jp2")),
+        _ => None,
+    }
+}
+
+// Undo every filter except the last one, which is the image codec itself.
+fn decode_to_last_filter(stream: &Stream) -> Result<Vec<u8>> {
+    let filters = stream.filters().unwrap_or_default();
+    if filters.len() <= 1 {
+        return Ok(stream.content.clone());
+    }
+
+    let mut outer = stream.clone();
+    // lopdf refuses to decompress image streams, so present it as plain data.
+    outer.dict.remove(b"Subtype");
+    let outer_filters: Vec<Object> = filters[..filters.len() - 1]
+        .iter()
+        .map(|name| Object::Name(name.as_bytes().to_vec()))
+        .collect();
+    outer.dict.set("Filter", Object::Array(outer_filters));
+    outer
+        .decompressed_content()
+        .map_err(|e| anyhow::anyhow!("Failed to decompress PDF image: {}", e))
+}
+
+fn media_box(document: &Document, page: ObjectId) -> (f32, f32, f32, f32) {
+    let mut node = document.get_dictionary(page).ok();
+    for _ in 0..MAX_PAGE_TREE_DEPTH {
+        let Some(dict) = node else {
+            break;
+        };
+        if let Some(Ok(values)) = dict_entry(document, dict, b"MediaBox").map(Object::as_array) {
+            let values: Vec<f32> = values.iter().filter_map(|v| v.as_float().ok()).collect();
+            if let [x0, y0, x1, y1] = values[..] {
+                return (x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1));
+            }
+        }
+        node = dict_entry(document, dict, b"Parent").and_then(|parent| parent.as_dict().ok());
+    }
+    // US Letter, the PDF default.
+    (0.0, 0.0, 612.0, 792.0)
+}
+
+fn rasterize_page(document: &Document, page: ObjectId) -> Result<RgbaImage> {
+    let content = document
+        .get_and_decode_page_content(page)
+        .map_err(|e| anyhow::anyhow!("Failed to read PDF page content: {}", e))?;
+    let resources = page_resources(document, page);
+
+    let mut placements = Vec::new();
+    collect_images(document, &content, &resources, Matrix::IDENTITY, 0, &mut placements)?;
+    if placements.is_empty() {
+        return Err(anyhow::anyhow!("PDF page contains no images to display"));
+    }
+
+    let (x0, y0, x1, y1) = media_box(document, page);
+    let (page_width, page_height) = (x1 - x0, y1 - y0);
+
+    // Render at the resolution of the sharpest image on the page.
+    let mut scale = placements
+        .iter()
+        .map(|p| {
+            let width = (p.max.0 - p.min.0).max(1.0);
+            let height = (p.max.1 - p.min.1).max(1.0);
+            (p.image.width() as f32 / width).max(p.image.height() as f32 / height)
+        })
+        .fold(DEFAULT_DPI / 72.0, f32::max);
+    scale = scale.min(MAX_RASTER_SIZE / page_width.max(page_height));
+
+    let canvas_width = (page_width * scale).round().max(1.0) as u32;
+    let canvas_height = (page_height * scale).round().max(1.0) as u32;
+    let mut canvas = RgbaImage::from_pixel(canvas_width, canvas_height, Rgba([255, 255, 255, 255]));
+
+    for placement in placements {
+        let left = ((placement.min.0 - x0) * scale).round();
+        let top = ((y1 - placement.max.1) * scale).round();
+        let width = ((placement.max.0 - placement.min.0) * scale).round().max(1.0) as u32;
+        let height = ((placement.max.1 - placement.min.1) * scale).round().max(1.0) as u32;
+
+        let mut image = if placement.image.width() == width && placement.image.height() == height {
+            placement.image.to_rgba8()
+        } else {
+            imageops::resize(&placement.image.to_rgba8(), width, height, ResizeFilter::Triangle)
+        };
+        // Images are stored top row first, but the PDF unit square is
+        // bottom-up, so an unflipped placement already draws upright.
+        if placement.flip_x {
+            imageops::flip_horizontal_in_place(&mut image);
+        }
+        if placement.flip_y {
+            imageops::flip_vertical_in_place(&mut image);
+        }
+        imageops::overlay(&mut canvas, &image, left as i64, top as i64);
+    }
+
+    Ok(canvas)
+}
+
+fn collect_images(
+    document: &Document,
+    content: &Content,
+    resources: &[&Dictionary],
+    base: Matrix,
+    depth: usize,
+    placements: &mut Vec<Placement>,
+) -> Result<()> {
+    let mut ctm = base;
+    let mut stack = Vec::new();
+
+    for operation in &content.operations {
+        match operation.operator.as_str() {
+            "q" => stack.push(ctm),
+            "Q" => ctm = stack.pop().unwrap_or(base),
+            "cm" => {
+                if let Some(matrix) = Matrix::from_operands(&operation.operands) {
+                    ctm = matrix.then(ctm);
+                }
+            }
+            "Do" => {
+                let Some(name) = operation.operands.first().and_then(|o| o.as_name().ok()) else {
+                    continue;
+                };
+                let Some(stream) = find_xobject(document, resources, name) else {
+                    continue;
+                };
+
+                if is_image(stream) {
+                    if stream.dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false) {
+                        continue;
+                    }
+                    placements.push(place_image(document, stream, ctm)?);
+                } else if depth < MAX_FORM_DEPTH {
+                    let form_matrix = dict_entry(document, &stream.dict, b"Matrix")
+                        .and_then(|m| m.as_array().ok())
+                        .and_then(|m| Matrix::from_operands(m))
+                        .unwrap_or(Matrix::IDENTITY);
+                    let mut form_resources: Vec<&Dictionary> = dict_entry(document, &stream.dict, b"Resources")
+                        .and_then(|r| r.as_dict().ok())
+                        .into_iter()
+                        .collect();
+                    form_resources.extend_from_slice(resources);
+
+                    let data = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+                    if let Ok(form) = Content::decode(&data) {
+                        collect_images(document, &form, &form_resources, form_matrix.then(ctm), depth + 1, placements)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn place_image(document: &Document, stream: &Stream, ctm: Matrix) -> Result<Placement> {
+    let image = decode_image(document, stream)?;
+    let corners = [ctm.apply(0.0, 0.0), ctm.apply(1.0, 0.0), ctm.apply(0.0, 1.0), ctm.apply(1.0, 1.0)];
+    let min = corners.iter().fold((f32::MAX, f32::MAX), |m, c| (m.0.min(c.0), m.1.min(c.1)));
+    let max = corners.iter().fold((f32::MIN, f32::MIN), |m, c| (m.0.max(c.0), m.1.max(c.1)));
+    let [a, _, _, d, _, _] = ctm.0;
+    Ok(Placement {
+        image,
+        min,
+        max,
+        flip_x: a < 0.0,
+        flip_y: d < 0.0,
+    })
+}
+
+fn decode_image(document: &Document, stream: &Stream) -> Result<DynamicImage> {
+    let data = decode_to_last_filter(stream)?;
+    match last_filter(stream).as_deref() {
+        Some("DCTDecode") => Ok(image::load_from_memory_with_format(&data, ImageFormat::Jpeg)?),
+        Some("JPXDecode") => {
+            let image = crate::jpeg2000::decode(&data)?;
+            // Codestreams without a JP2 header leave CMYK to the dictionary.
+            let cmyk = matches!(
+                ColorSpace::parse(document, stream.dict.get(b"ColorSpace").ok())?,
+                ColorSpace::Cmyk
+            );
+            match image {
+                DynamicImage::ImageRgba8(pixels) if cmyk => {
+                    let (width, height) = pixels.dimensions();
+                    let rgb = pixels.pixels().flat_map(|p| cmyk_to_rgb(p[0], p[1], p[2], p[3])).collect();
+                    Ok(DynamicImage::ImageRgb8(
+                        RgbImage::from_raw(width, height, rgb).context("Invalid PDF image size")?,
+                    ))
+                }
+                image => Ok(image),
+            }
+        }
+        Some("FlateDecode") | Some("LZWDecode") | Some("ASCII85Decode") | None => {
+            let mut plain = stream.clone();
+            plain.dict.remove(b"Subtype");
+            let samples = if plain.filters().is_ok() {
+                plain
+                    .decompressed_content()
+                    .map_err(|e| anyhow::anyhow!("Failed to decompress PDF image: {}", e))?
+            } else {
+                plain.content
+            };
+            decode_samples(document, &stream.dict, &samples)
+        }
+        Some(other) => Err(anyhow::anyhow!("Unsupported PDF image filter: {}", other)),
+    }
+}
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    Indexed(Box<ColorSpace>, Vec<u8>),
+}
+
+impl ColorSpace {
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed(..) => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+
+    fn parse(document: &Document, object: Option<&Object>) -> Result<ColorSpace> {
+        let Some(object) = object.and_then(|o| resolve(document, o)) else {
+            return Ok(ColorSpace::Gray);
+        };
+        if let Ok(name) = object.as_name() {
+            return Ok(match name {
+                b"DeviceRGB" | b"CalRGB" | b"RGB" => ColorSpace::Rgb,
+                b"DeviceCMYK" | b"CMYK" => ColorSpace::Cmyk,
+                _ => ColorSpace::Gray,
+            });
+        }
+
+        let array = object.as_array().map_err(|_| anyhow::anyhow!("Invalid PDF color space"))?;
+        let family = array.first().and_then(|o| o.as_name().ok()).unwrap_or_default();
+        match family {
+            b"ICCBased" => {
+                let components = array
+                    .get(1)
+                    .and_then(|o| resolve(document, o))
+                    .and_then(|o| o.as_stream().ok())
+                    .and_then(|s| s.dict.get(b"N").and_then(Object::as_i64).ok())
+                    .unwrap_or(3);
+                Ok(match components {
+                    1 => ColorSpace::Gray,
+                    4 => ColorSpace::Cmyk,
+                    _ => ColorSpace::Rgb,
+                })
+            }
+            b"Indexed" | b"I" => {
+                let base = ColorSpace::parse(document, array.get(1))?;
+                let lookup = match array.get(3).and_then(|o| resolve(document, o)) {
+                    Some(Object::String(bytes, _)) => bytes.clone(),
+                    Some(Object::Stream(stream)) => stream.get_plain_content().unwrap_or_default(),
+                    _ => return Err(anyhow::anyhow!("Invalid PDF color palette")),
+                };
+                Ok(ColorSpace::Indexed(Box::new(base), lookup))
+            }
+            b"CalRGB" | b"Lab" => Ok(ColorSpace::Rgb),
+            _ => Ok(ColorSpace::Gray),
+        }
+    }
+}
+
+fn cmyk_to_rgb(c: u8, m: u8, y: u8, k: u8) -> [u8; 3] {
+    let k = 255 - k as u32;
+    [
+        ((255 - c as u32) * k / 255) as u8,
+        ((255 - m as u32) * k / 255) as u8,
+        ((255 - y as u32) * k / 255) as u8,
+    ]
+}
+
+// Convert raw PDF image samples into an image, expanding packed bit depths
+// to 8 bits per component.
+fn decode_samples(document: &Document, dict: &Dictionary, data: &[u8]) -> Result<DynamicImage> {
+    let dimension = |key: &[u8]| -> Result<u32> {
+        let value = dict.get(key).and_then(Object::as_i64)?;
+        u32::try_from(value)
+            .ok()
+            .filter(|&value| value > 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid PDF image size: {}", value))
+    };
+    let width = dimension(b"Width")?;
+    let height = dimension(b"Height")?;
+    let bits = dict.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8) as usize;
+    let color_space = ColorSpace::parse(document, dict.get(b"ColorSpace").ok())?;
+    let components = color_space.components();
+
+    if !matches!(bits, 1 | 2 | 4 | 8) {
+        return Err(anyhow::anyhow!("Unsupported PDF image bit depth: {}", bits));
+    }
+
+    let row_bytes = (width as usize)
+        .checked_mul(components * bits)
+        .map(|row_bits| row_bits.div_ceil(8))
+        .context("PDF image is too large")?;
+    let size = row_bytes.checked_mul(height as usize).context("PDF image is too large")?;
+    if data.len() < size {
+        return Err(anyhow::anyhow!("PDF image data is truncated"));
+    }
+
+    let max_value = (1u32 << bits) - 1;
+    let mut samples = Vec::with_capacity(width as usize * height as usize * components);
+    for row in data.chunks(row_bytes).take(height as usize) {
+        for i in 0..width as usize * components {
+            let bit_offset = i * bits;
+            let byte = row[bit_offset / 8];
+            let shift = 8 - bits - bit_offset % 8;
+            let value = (byte >> shift) as u32 & max_value;
+            samples.push(value);
+        }
+    }
+
+    // 1-bit images are commonly stored with an inverted Decode array.
+    let inverted = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|decode| decode.first())
+        .and_then(|first| first.as_float().ok())
+        .is_some_and(|first| first > 0.5);
+
+    let scale = |value: u32| -> u8 {
+        let value = (value * 255 / max_value) as u8;
+        if inverted { 255 - value } else { value }
+    };
+
+    Ok(match &color_space {
+        ColorSpace::Gray => {
+            let pixels = samples.into_iter().map(scale).collect();
+            DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, pixels).context("Invalid PDF image size")?)
+        }
+        ColorSpace::Rgb => {
+            let pixels = samples.into_iter().map(scale).collect();
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels).context("Invalid PDF image size")?)
+        }
+        ColorSpace::Cmyk => {
+            let pixels = samples
+                .chunks_exact(4)
+                .flat_map(|cmyk| cmyk_to_rgb(scale(cmyk[0]), scale(cmyk[1]), scale(cmyk[2]), scale(cmyk[3])))
+                .collect();
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels).context("Invalid PDF image size")?)
+        }
+        ColorSpace::Indexed(base, lookup) => {
+            let stride = base.components();
+            let pixels = samples
+                .into_iter()
+                .flat_map(|index| {
+                    let start = index as usize * stride;
+                    let entry = lookup.get(start..start + stride).unwrap_or(&[0, 0, 0, 0][..stride]);
+                    match stride {
+                        1 => [entry[0], entry[0], entry[0]],
+                        4 => cmyk_to_rgb(entry[0], entry[1], entry[2], entry[3]),
+                        _ => [entry[0], entry[1], entry[2]],
+                    }
+                })
+                .collect();
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels).context("Invalid PDF image size")?)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jpeg2000;
+    use lopdf::content::Operation;
+    use lopdf::dictionary;
+
+    // A one-page PDF that draws `image` over the whole page.
+    fn write_pdf(path: &Path, image: Stream) {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let image_id = document.add_object(image);
+        let content = Content {
+            operations: vec![
+                Operation::new("q", vec![]),
+                Operation::new("cm", vec![100.into(), 0.into(), 0.into(), 100.into(), 0.into(), 0.into()]),
+                Operation::new("Do", vec![Object::Name(b"Im0".to_vec())]),
+                Operation::new("Q", vec![]),
+            ],
+        };
+        let content_id = document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        document.trailer.set("Root", catalog_id);
+        document.save(path).unwrap();
+    }
+
+    fn gray_image(width: i64, height: i64, data: Vec<u8>) -> Stream {
+        Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width,
+                "Height" => height,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+            },
+            data,
+        )
+    }
+
+    fn read_page(image: Stream) -> Result<Vec<u8>> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.pdf");
+        write_pdf(&path, image);
+        PdfBackend::open(&path)?.read_entry(0)
+    }
+
+    #[test]
+    fn rasterizes_raw_samples() {
+        let page = read_page(gray_image(2, 2, vec![0, 255, 255, 0])).unwrap();
+        assert!(image::load_from_memory_with_format(&page, ImageFormat::Png).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_image_sizes() {
+        for (width, height) in [(0, 2), (2, 0), (-2, 2), (2, -2), (1 << 40, 1 << 40)] {
+            assert!(read_page(gray_image(width, height, vec![0; 4])).is_err(), "{}x{}", width, height);
+        }
+    }
+
+    fn jpeg_2000_image() -> (DynamicImage, Stream) {
+        let pixels = DynamicImage::ImageLuma8(GrayImage::from_fn(12, 9, |x, y| image::Luma([(x * 20 + y) as u8])));
+        let options = jpeg2000::testing::Options {
+            levels: 2,
+            block_exponent: 3,
+            tile_size: None,
+            progression: 0,
+            irreversible: false,
+        };
+        let mut image = gray_image(12, 9, jpeg2000::testing::encode(&pixels, &options));
+        image.dict.set("Filter", "JPXDecode");
+        (pixels, image)
+    }
+
+    #[test]
+    fn passes_jpeg_2000_pages_through() {
+        let (pixels, image) = jpeg_2000_image();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.pdf");
+        write_pdf(&path, image);
+
+        let mut backend = PdfBackend::open(&path).unwrap();
+        assert_eq!(backend.entry_names(), ["page-0001.jp2"]);
+        let page = backend.read_entry(0).unwrap();
+        assert_eq!(crate::decoder::decode_image_bytes("page-0001.jp2", &page).unwrap(), pixels);
+    }
+
+    #[test]
+    fn decodes_jpeg_2000_images() {
+        let (pixels, image) = jpeg_2000_image();
+        assert_eq!(decode_image(&Document::with_version("1.5"), &image).unwrap(), pixels);
+    }
+
+    #[test]
+    fn media_box_survives_parent_loop() {
+        let mut document = Document::with_version("1.5");
+        let page_id = document.new_object_id();
+        document
+            .objects
+            .insert(page_id, Object::Dictionary(dictionary! { "Type" => "Page", "Parent" => page_id }));
+        assert_eq!(media_box(&document, page_id), (0.0, 0.0, 612.0, 792.0));
+    }
+}
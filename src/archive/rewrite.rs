@@ -0,0 +1,251 @@
+use anyhow::{Context as AnyhowContext, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{detect_kind, ArchiveKind};
+
+// What to do with an existing entry while rewriting an archive.
+pub enum EntryEdit {
+    Keep,
+    Remove,
+    Replace(Vec<u8>),
+    // Replace the data and store it under a new name.
+    Rename(String, Vec<u8>),
+}
+
+pub struct NewEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+    pub compression: CompressionMethod,
+}
+
+// Rewrite a CBZ in place. Kept entries are copied without recompressing,
+// replaced or renamed entries keep their compression method, and the new archive is
+// written to a temporary file, synced and renamed over the original so a
+// crash never leaves a half-written archive. With `backup` the original is
+// first copied to `<name>.bak`, unless an older backup already exists.
+pub fn rewrite_zip(
+    path: &Path,
+    backup: bool,
+    mut edit: impl FnMut(&str) -> Result<EntryEdit>,
+    append: Vec<NewEntry>,
+) -> Result<()> {
+    if detect_kind(path)? != ArchiveKind::Zip {
+        anyhow::bail!("Only CBZ/ZIP archives can be modified: {}", path.display());
+    }
+
+    let source = File::open(path).with_context(|| format!("Failed to open archive: {}", path.display()))?;
+    let permissions = source.metadata()?.permissions();
+    let mut archive = ZipArchive::new(BufReader::new(source))
+        .with_context(|| format!("Failed to read ZIP archive: {}", path.display()))?;
+
+    let temp_path = temp_path_for(path);
+    let temp_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .with_context(|| format!("Failed to create temporary file: {}", temp_path.display()))?;
+
+    let result = write_archive(&mut archive, temp_file, &mut edit, append);
+    let temp_file = match result {
+        Ok(file) => file,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+    temp_file.sync_all()?;
+    drop(temp_file);
+    drop(archive);
+
+    if backup {
+        let backup_path = backup_path_for(path);
+        if !backup_path.exists() {
+            if let Err(e) = fs::copy(path, &backup_path) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e).with_context(|| format!("Failed to create backup: {}", backup_path.display()));
+            }
+        }
+    }
+
+    let _ = fs::set_permissions(&temp_path, permissions);
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e).with_context(|| format!("Failed to replace archive: {}", path.display()));
+    }
+    sync_parent_dir(path);
+    Ok(())
+}
+
+fn write_archive(
+    archive: &mut ZipArchive<BufReader<File>>,
+    temp_file: File,
+    edit: &mut impl FnMut(&str) -> Result<EntryEdit>,
+    append: Vec<NewEntry>,
+) -> Result<File> {
+    let mut writer = ZipWriter::new(BufWriter::new(temp_file));
+    writer.set_raw_comment(archive.comment().into());
+
+    for index in 0..archive.len() {
+        let name = archive.by_index_raw(index)?.name().to_string();
+        let (name, data) = match edit(&name)? {
+            EntryEdit::Keep => {
+                writer.raw_copy_file(archive.by_index_raw(index)?)?;
+                continue;
+            }
+            EntryEdit::Remove => continue,
+            EntryEdit::Replace(data) => (name, data),
+            EntryEdit::Rename(new_name, data) => (new_name, data),
+        };
+
+        let entry = archive.by_index_raw(index)?;
+        let mut options = SimpleFileOptions::default()
+            .compression_method(entry.compression())
+            .large_file(data.len() as u64 >= u32::MAX as u64);
+        if let Some(modified) = entry.last_modified() {
+            options = options.last_modified_time(modified);
+        }
+        if let Some(mode) = entry.unix_mode() {
+            options = options.unix_permissions(mode);
+        }
+        drop(entry);
+        writer.start_file(name, options)?;
+        std::io::Write::write_all(&mut writer, &data)?;
+    }
+
+    for entry in append {
+        let options = SimpleFileOptions::default()
+            .compression_method(entry.compression)
+            .large_file(entry.data.len() as u64 >= u32::MAX as u64);
+        writer.start_file(entry.name, options)?;
+        std::io::Write::write_all(&mut writer, &entry.data)?;
+    }
+
+    let buffered = writer.finish()?;
+    buffered.into_inner().map_err(|e| e.into_error().into())
+}
+
+pub(super) fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+fn backup_path_for(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.bak", name))
+}
+
+// Make the rename itself durable.
+#[cfg(unix)]
+pub(super) fn sync_parent_dir(path: &Path) {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(super) fn sync_parent_dir(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::testing;
+    use std::io::Read;
+
+    fn read_zip(path: &Path) -> Vec<(String, CompressionMethod, Vec<u8>)> {
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        (0..archive.len())
+            .map(|index| {
+                let mut entry = archive.by_index(index).unwrap();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                (entry.name().to_string(), entry.compression(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_entries_and_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        testing::write_zip(
+            &path,
+            &[
+                ("001.png", b"first page", CompressionMethod::Stored),
+                ("002.png", b"second page", CompressionMethod::Deflated),
+                ("notes.txt", b"old notes", CompressionMethod::Deflated),
+                ("003.png", b"third page", CompressionMethod::Stored),
+            ],
+        );
+
+        rewrite_zip(
+            &path,
+            false,
+            |name| {
+                Ok(match name {
+                    "notes.txt" => EntryEdit::Replace(b"new notes".to_vec()),
+                    "003.png" => EntryEdit::Rename("003.webp".into(), b"third page, smaller".to_vec()),
+                    "002.png" => EntryEdit::Remove,
+                    _ => EntryEdit::Keep,
+                })
+            },
+            vec![NewEntry {
+                name: "ComicInfo.xml".into(),
+                data: b"<ComicInfo />".to_vec(),
+                compression: CompressionMethod::Deflated,
+            }],
+        )
+        .unwrap();
+
+        let entries = read_zip(&path);
+        let expected = [
+            ("001.png", CompressionMethod::Stored, &b"first page"[..]),
+            ("notes.txt", CompressionMethod::Deflated, b"new notes"),
+            ("003.webp", CompressionMethod::Stored, b"third page, smaller"),
+            ("ComicInfo.xml", CompressionMethod::Deflated, b"<ComicInfo />"),
+        ];
+        assert_eq!(entries.len(), expected.len());
+        for ((name, compression, data), (expected_name, expected_compression, expected_data)) in
+            entries.iter().zip(expected)
+        {
+            assert_eq!(name, expected_name);
+            assert_eq!(*compression, expected_compression);
+            assert_eq!(data, expected_data);
+        }
+        assert!(!temp_path_for(&path).exists());
+        assert!(!backup_path_for(&path).exists());
+    }
+
+    #[test]
+    fn backup_keeps_the_first_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        testing::write_zip(&path, &[("001.png", b"original", CompressionMethod::Stored)]);
+        let original = fs::read(&path).unwrap();
+
+        for data in [&b"first edit"[..], b"second edit"] {
+            rewrite_zip(&path, true, |_| Ok(EntryEdit::Replace(data.to_vec())), Vec::new()).unwrap();
+        }
+
+        assert_eq!(fs::read(backup_path_for(&path)).unwrap(), original);
+        assert_eq!(read_zip(&path)[0].2, b"second edit");
+    }
+
+    #[test]
+    fn failed_edit_leaves_the_archive_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        testing::write_zip(&path, &[("001.png", b"original", CompressionMethod::Stored)]);
+        let original = fs::read(&path).unwrap();
+
+        assert!(rewrite_zip(&path, true, |_| anyhow::bail!("no"), Vec::new()).is_err());
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert!(!temp_path_for(&path).exists());
+        assert!(!backup_path_for(&path).exists());
+    }
+}
@@ -0,0 +1,45 @@
+// Builders for the archives the tests run against.
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// A small valid PNG of the given size.
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    let image = image::RgbImage::from_pixel(width, height, image::Rgb([200, 40, 40]));
+    let mut data = Cursor::new(Vec::new());
+    image.write_to(&mut data, image::ImageFormat::Png).unwrap();
+    data.into_inner()
+}
+
+pub fn write_zip(path: &Path, entries: &[(&str, &[u8], CompressionMethod)]) {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data, compression) in entries {
+        writer
+            .start_file(*name, SimpleFileOptions::default().compression_method(*compression))
+            .unwrap();
+        writer.write_all(data).unwrap();
+    }
+    fs::write(path, writer.finish().unwrap().into_inner()).unwrap();
+}
+
+// A CBZ whose only page claims to be a terabyte in its zip64 central
+// directory record while holding a few bytes.
+pub fn write_zip_with_oversized_entry(path: &Path) {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+    writer.start_file("001.png", options).unwrap();
+    writer.write_all(&png(4, 4)).unwrap();
+    let mut bytes = writer.finish().unwrap().into_inner();
+
+    let header = bytes.windows(4).position(|window| window == b"PK\x01\x02").unwrap();
+    let name_len = u16::from_le_bytes([bytes[header + 28], bytes[header + 29]]) as usize;
+    // The zip64 extra field follows the name: tag, length, then the
+    // uncompressed size.
+    let size = header + 46 + name_len + 4;
+    bytes[size..size + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    fs::write(path, bytes).unwrap();
+}
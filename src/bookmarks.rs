@@ -0,0 +1,130 @@
+use anyhow::{Context as AnyhowContext, Result};
+use eframe::egui::{self, Ui};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::progress::VolumeKey;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    // The volume as it is opened: an archive or a folder of images.
+    pub volume: PathBuf,
+    // Path and content fingerprint of the volume, used to find it after a
+    // rename.
+    pub volume_key: Option<VolumeKey>,
+    pub page: usize,
+    // Name of the page file, so folders that gain files still find the page.
+    pub page_name: String,
+    #[serde(default)]
+    pub note: String,
+    // Seconds since the Unix epoch.
+    pub created: u64,
+}
+
+pub enum BookmarkAction {
+    Add,
+    Jump(Bookmark),
+    Export,
+    // A note was edited or a bookmark removed.
+    Changed,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BookmarkStore {
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+}
+
+impl BookmarkStore {
+    pub const FILE_NAME: &'static str = "bookmarks.json";
+
+    pub fn add(&mut self, volume: PathBuf, volume_key: Option<VolumeKey>, page: usize, page_name: String) {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        self.bookmarks.push(Bookmark {
+            volume,
+            volume_key,
+            page,
+            page_name,
+            note: String::new(),
+            created,
+        });
+    }
+
+    pub fn contains(&self, volume: &Path, page: usize) -> bool {
+        self.bookmarks
+            .iter()
+            .any(|bookmark| bookmark.volume == volume && bookmark.page == page)
+    }
+
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(&self.bookmarks)?;
+        fs::write(path, json).with_context(|| format!("Failed to export bookmarks to: {}", path.display()))
+    }
+
+    // The bookmarks side panel, listing bookmarks of every volume.
+    pub fn show_panel(&mut self, ui: &mut Ui, can_add: bool) -> Option<BookmarkAction> {
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(can_add, egui::Button::new("Bookmark Page")).clicked() {
+                action = Some(BookmarkAction::Add);
+            }
+            if ui.add_enabled(!self.bookmarks.is_empty(), egui::Button::new("Export...")).clicked() {
+                action = Some(BookmarkAction::Export);
+            }
+        });
+        ui.separator();
+
+        if self.bookmarks.is_empty() {
+            ui.label("No bookmarks yet.");
+            return action;
+        }
+
+        let mut remove = None;
+        egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+            for (index, bookmark) in self.bookmarks.iter_mut().enumerate() {
+                ui.push_id(index, |ui| {
+                    let volume_name = bookmark
+                        .volume
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| bookmark.volume.display().to_string());
+                    ui.horizontal(|ui| {
+                        let label = format!("{} - page {}", volume_name, bookmark.page + 1);
+                        if ui
+                            .link(label)
+                            .on_hover_text(bookmark.volume.display().to_string())
+                            .clicked()
+                        {
+                            action = Some(BookmarkAction::Jump(bookmark.clone()));
+                        }
+                        if ui.small_button("x").on_hover_text("Remove bookmark").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                    let note = ui.add(
+                        egui::TextEdit::multiline(&mut bookmark.note)
+                            .hint_text("Note")
+                            .desired_rows(1)
+                            .desired_width(f32::INFINITY),
+                    );
+                    if note.lost_focus() {
+                        action = Some(BookmarkAction::Changed);
+                    }
+                    ui.separator();
+                });
+            }
+        });
+
+        if let Some(index) = remove {
+            self.bookmarks.remove(index);
+            action = Some(BookmarkAction::Changed);
+        }
+        action
+    }
+}
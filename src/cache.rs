@@ -0,0 +1,249 @@
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::texture::PageTexture;
+
+// Identifies a page independently of its position in the current list: a
+// loose image file, or an entry of an archive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PageKey {
+    pub source: PathBuf,
+    pub entry: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct CachedPage {
+    pub image: Arc<DynamicImage>,
+    pub texture: PageTexture,
+}
+
+impl CachedPage {
+    // Decoded pixels plus the RGBA texture uploaded to the GPU.
+    fn size_bytes(&self) -> usize {
+        self.image.as_bytes().len() + self.texture.size_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+struct Slot {
+    page: CachedPage,
+    size: usize,
+    last_used: u64,
+}
+
+// Least-recently-used cache of decoded pages bounded by a memory budget.
+pub struct PageCache {
+    slots: HashMap<PageKey, Slot>,
+    budget_bytes: usize,
+    used_bytes: usize,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl PageCache {
+    pub fn new(budget_mb: usize) -> Self {
+        Self {
+            slots: HashMap::new(),
+            budget_bytes: budget_mb * 1024 * 1024,
+            used_bytes: 0,
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &PageKey) -> Option<CachedPage> {
+        self.clock += 1;
+        match self.slots.get_mut(key) {
+            Some(slot) => {
+                slot.last_used = self.clock;
+                self.stats.hits += 1;
+                Some(slot.page.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Fetch a page that is being redrawn rather than loaded. It still counts
+    // as recently used, but not as a hit or miss, so drawing every frame does
+    // not skew the stats.
+    pub fn peek(&mut self, key: &PageKey) -> Option<CachedPage> {
+        let slot = self.slots.get_mut(key)?;
+        self.clock += 1;
+        slot.last_used = self.clock;
+        Some(slot.page.clone())
+    }
+
+    // Check for a page without counting it as a use.
+    pub fn contains(&self, key: &PageKey) -> bool {
+        self.slots.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: PageKey, page: CachedPage) {
+        self.clock += 1;
+        let size = page.size_bytes();
+        let slot = Slot {
+            page,
+            size,
+            last_used: self.clock,
+        };
+        if let Some(old) = self.slots.insert(key, slot) {
+            self.used_bytes -= old.size;
+        }
+        self.used_bytes += size;
+        self.evict_to_budget();
+    }
+
+    pub fn remove(&mut self, key: &PageKey) {
+        if let Some(slot) = self.slots.remove(key) {
+            self.used_bytes -= slot.size;
+        }
+    }
+
+    // Drop every page of an archive or image file that changed on disk.
+    pub fn remove_source(&mut self, source: &Path) {
+        let used_bytes = &mut self.used_bytes;
+        self.slots.retain(|key, slot| {
+            let keep = key.source != source;
+            if !keep {
+                *used_bytes -= slot.size;
+            }
+            keep
+        });
+    }
+
+    pub fn set_budget_mb(&mut self, budget_mb: usize) {
+        self.budget_bytes = budget_mb * 1024 * 1024;
+        self.evict_to_budget();
+    }
+
+    pub fn budget_mb(&self) -> usize {
+        self.budget_bytes / (1024 * 1024)
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    // Free the tiles of large pages that were not drawn recently.
+    pub fn release_unused_tiles(&self, pass: u64) {
+        for slot in self.slots.values() {
+            slot.page.texture.release_unused_tiles(pass);
+        }
+    }
+
+    // Drop least recently used pages until the cache fits its budget. The
+    // most recent page is always kept so a single oversized page still shows.
+    fn evict_to_budget(&mut self) {
+        while self.used_bytes > self.budget_bytes && self.slots.len() > 1 {
+            let Some(oldest) = self
+                .slots
+                .iter()
+                .min_by_key(|(_, slot)| slot.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui;
+    use image::RgbaImage;
+
+    // A page holding `side * side * 8` bytes: its pixels plus its texture.
+    fn page(ctx: &egui::Context, side: u32) -> CachedPage {
+        let image = RgbaImage::new(side, side);
+        let texture = PageTexture::new(ctx, "page".to_string(), crate::texture::color_image(&image), Vec::new());
+        CachedPage {
+            image: Arc::new(DynamicImage::ImageRgba8(image)),
+            texture,
+        }
+    }
+
+    fn key(source: &str, entry: usize) -> PageKey {
+        PageKey {
+            source: PathBuf::from(source),
+            entry: Some(entry),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let ctx = egui::Context::default();
+        // Room for two 512 KiB pages.
+        let mut cache = PageCache::new(1);
+        cache.insert(key("a.cbz", 0), page(&ctx, 256));
+        cache.insert(key("a.cbz", 1), page(&ctx, 256));
+        assert!(cache.get(&key("a.cbz", 0)).is_some());
+
+        cache.insert(key("a.cbz", 2), page(&ctx, 256));
+        assert!(cache.contains(&key("a.cbz", 0)));
+        assert!(!cache.contains(&key("a.cbz", 1)));
+        assert!(cache.contains(&key("a.cbz", 2)));
+
+        // Peeking keeps a page around without counting as a hit.
+        assert!(cache.peek(&key("a.cbz", 0)).is_some());
+        assert!(cache.peek(&key("a.cbz", 1)).is_none());
+        cache.insert(key("a.cbz", 3), page(&ctx, 256));
+        assert!(cache.contains(&key("a.cbz", 0)));
+        assert!(!cache.contains(&key("a.cbz", 2)));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 0, 2));
+        assert_eq!(cache.used_bytes(), 2 * 256 * 256 * 8);
+    }
+
+    #[test]
+    fn keeps_a_single_page_over_budget() {
+        let ctx = egui::Context::default();
+        let mut cache = PageCache::new(1);
+        cache.insert(key("a.cbz", 0), page(&ctx, 256));
+        cache.insert(key("a.cbz", 1), page(&ctx, 512));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains(&key("a.cbz", 1)));
+        assert_eq!(cache.used_bytes(), 512 * 512 * 8);
+
+        // Shrinking the budget still leaves the most recent page.
+        cache.set_budget_mb(0);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn removes_every_page_of_a_source() {
+        let ctx = egui::Context::default();
+        let mut cache = PageCache::new(16);
+        cache.insert(key("a.cbz", 0), page(&ctx, 64));
+        cache.insert(key("a.cbz", 1), page(&ctx, 64));
+        cache.insert(key("b.cbz", 0), page(&ctx, 64));
+
+        cache.remove_source(Path::new("a.cbz"));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains(&key("b.cbz", 0)));
+        assert_eq!(cache.used_bytes(), 64 * 64 * 8);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+}
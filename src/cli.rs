@@ -0,0 +1,418 @@
+use anyhow::{Context as AnyhowContext, Result};
+use clap::{Args, Parser, Subcommand};
+use image::ImageFormat;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive::{self, ArchiveBackend, ArchiveKind, Compression, PackOptions};
+use crate::comicinfo::{self, ComicInfo};
+use crate::{decoder, image_files_in_directory, library, verify, ReadingDirection};
+
+// Without a subcommand the GUI starts and opens `path`.
+#[derive(Parser)]
+#[command(name = "manga_reader", version, about = "A manga and comic reader")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Archive, folder or image to open in the reader
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Show the format, page count and ComicInfo metadata of a volume
+    Info(VolumeArgs),
+    /// List the pages of a volume in reading order
+    List {
+        #[command(flatten)]
+        volume: VolumeArgs,
+        /// Include pages ComicInfo marks as deleted
+        #[arg(long)]
+        all: bool,
+    },
+    /// Write the pages of a volume to a folder
+    Extract {
+        #[command(flatten)]
+        volume: VolumeArgs,
+        /// Folder to write the pages to [default: the volume name]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Include pages ComicInfo marks as deleted
+        #[arg(long)]
+        all: bool,
+    },
+    /// Write the cover thumbnail the library shows for a volume
+    Thumb {
+        #[command(flatten)]
+        volume: VolumeArgs,
+        /// Image file to write [default: <volume name>.jpg]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value_t = library::THUMBNAIL_WIDTH)]
+        width: u32,
+        #[arg(long, default_value_t = library::THUMBNAIL_HEIGHT)]
+        height: u32,
+    },
+    /// Check every archive under a folder for truncated, corrupt or undecodable pages
+    Verify(VolumeArgs),
+    /// Pack folders of images into CBZ archives
+    Pack {
+        /// Folders of images
+        #[arg(required = true)]
+        folders: Vec<PathBuf>,
+        /// Folder to write the archives to [default: next to each folder]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Name pages 001.jpg, 002.jpg, ... in reading order
+        #[arg(long)]
+        pad: bool,
+        /// Do not embed a generated ComicInfo.xml
+        #[arg(long)]
+        no_comic_info: bool,
+        /// Replace archives that already exist
+        #[arg(long)]
+        force: bool,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct VolumeArgs {
+    /// Comic archive or folder of images
+    pub path: PathBuf,
+    /// Print JSON instead of text
+    #[arg(long)]
+    pub json: bool,
+}
+
+// A volume opened without the GUI: an archive, or a folder of images.
+struct Volume {
+    format: &'static str,
+    pages: Vec<String>,
+    source: PageSource,
+    comic_info: Option<ComicInfo>,
+    direction: Option<ReadingDirection>,
+}
+
+enum PageSource {
+    // Archive entry index of each page.
+    Archive(Box<dyn ArchiveBackend>, Vec<usize>),
+    Folder(Vec<PathBuf>),
+}
+
+impl Volume {
+    fn open(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            let files = image_files_in_directory(path);
+            let comic_info = comicinfo::load_from_dir(path);
+            return Ok(Self {
+                format: "Folder",
+                pages: files
+                    .iter()
+                    .map(|file| file.file_name().unwrap_or_default().to_string_lossy().into_owned())
+                    .collect(),
+                direction: comic_info.as_ref().and_then(ComicInfo::reading_direction),
+                comic_info,
+                source: PageSource::Folder(files),
+            });
+        }
+
+        let format = format_name(archive::detect_kind(path)?);
+        let mut backend = archive::open(path).with_context(|| format!("Failed to open archive: {}", path.display()))?;
+        let comic_info = archive::read_comic_info(backend.as_mut());
+        let entries = archive::page_entries(backend.as_ref());
+        Ok(Self {
+            format,
+            pages: entries.iter().map(|&index| backend.entry_names()[index].clone()).collect(),
+            direction: backend
+                .reading_direction()
+                .or_else(|| comic_info.as_ref().and_then(ComicInfo::reading_direction)),
+            comic_info,
+            source: PageSource::Archive(backend, entries),
+        })
+    }
+
+    fn read_page(&mut self, page: usize) -> Result<Vec<u8>> {
+        match &mut self.source {
+            PageSource::Archive(backend, entries) => backend.read_entry(entries[page]),
+            PageSource::Folder(files) => {
+                fs::read(&files[page]).with_context(|| format!("Failed to read {}", files[page].display()))
+            }
+        }
+    }
+
+    fn page_type(&self, page: usize) -> Option<String> {
+        self.comic_info
+            .as_ref()
+            .and_then(|info| info.page_type(page))
+            .map(str::to_owned)
+    }
+
+    // The pages the reader shows, leaving out those ComicInfo marks as
+    // deleted unless `all` is set.
+    fn shown_pages(&self, all: bool) -> Vec<usize> {
+        (0..self.pages.len())
+            .filter(|&page| all || !self.comic_info.as_ref().is_some_and(|info| info.is_deleted(page)))
+            .collect()
+    }
+}
+
+fn format_name(kind: ArchiveKind) -> &'static str {
+    match kind {
+        ArchiveKind::Zip => "CBZ",
+        ArchiveKind::Epub => "EPUB",
+        ArchiveKind::Rar => "CBR",
+        ArchiveKind::SevenZip => "CB7",
+        ArchiveKind::Tar(Compression::None) => "CBT",
+        ArchiveKind::Tar(Compression::Gzip) => "CBT (gzip)",
+        ArchiveKind::Tar(Compression::Xz) => "CBT (xz)",
+        ArchiveKind::Pdf => "PDF",
+    }
+}
+
+fn direction_name(direction: ReadingDirection) -> &'static str {
+    match direction {
+        ReadingDirection::LeftToRight => "left-to-right",
+        ReadingDirection::RightToLeft => "right-to-left",
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct InfoOutput {
+    path: PathBuf,
+    format: &'static str,
+    pages: usize,
+    reading_direction: Option<&'static str>,
+    comic_info: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Serialize)]
+struct PackOutput {
+    folder: PathBuf,
+    output: PathBuf,
+    pages: Option<usize>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PageOutput {
+    index: usize,
+    name: String,
+    page_type: Option<String>,
+}
+
+// Run a subcommand. Returns the process exit code.
+pub fn run(command: Command) -> Result<i32> {
+    match command {
+        Command::Info(args) => info(&args),
+        Command::List { volume, all } => list(&volume, all),
+        Command::Extract { volume, output, all } => extract(&volume, output, all),
+        Command::Thumb { volume, output, width, height } => thumb(&volume, output, width, height),
+        Command::Verify(args) => verify(&args),
+        Command::Pack { folders, output, pad, no_comic_info, force, json } => {
+            let options = PackOptions {
+                pad_names: pad,
+                comic_info: !no_comic_info,
+                overwrite: force,
+            };
+            pack(&folders, output, &options, json)
+        }
+    }
+}
+
+fn info(args: &VolumeArgs) -> Result<i32> {
+    let volume = Volume::open(&args.path)?;
+    let output = InfoOutput {
+        path: args.path.clone(),
+        format: volume.format,
+        pages: volume.pages.len(),
+        reading_direction: volume.direction.map(direction_name),
+        comic_info: volume.comic_info.as_ref().map(|info| {
+            info.fields
+                .iter()
+                .map(|(name, value)| (name.clone(), serde_json::Value::from(value.as_str())))
+                .collect()
+        }),
+    };
+    if args.json {
+        print_json(&output)?;
+        return Ok(0);
+    }
+
+    println!("Path:      {}", output.path.display());
+    println!("Format:    {}", output.format);
+    println!("Pages:     {}", output.pages);
+    if let Some(direction) = output.reading_direction {
+        println!("Direction: {}", direction);
+    }
+    match &volume.comic_info {
+        Some(info) => {
+            println!("ComicInfo:");
+            for (name, value) in info.fields.iter().filter(|(_, value)| !value.is_empty()) {
+                println!("  {}: {}", name, value);
+            }
+        }
+        None => println!("ComicInfo: none"),
+    }
+    Ok(0)
+}
+
+// Pages are numbered as the reader numbers them, after deleted pages are
+// left out.
+fn list(args: &VolumeArgs, all: bool) -> Result<i32> {
+    let volume = Volume::open(&args.path)?;
+    let pages: Vec<PageOutput> = volume
+        .shown_pages(all)
+        .into_iter()
+        .enumerate()
+        .map(|(index, page)| PageOutput {
+            index,
+            name: volume.pages[page].clone(),
+            page_type: volume.page_type(page),
+        })
+        .collect();
+    if args.json {
+        print_json(&pages)?;
+        return Ok(0);
+    }
+
+    let width = pages.len().to_string().len();
+    for page in &pages {
+        match &page.page_type {
+            Some(page_type) => println!("{:>width$}  {}  [{}]", page.index + 1, page.name, page_type),
+            None => println!("{:>width$}  {}", page.index + 1, page.name),
+        }
+    }
+    Ok(0)
+}
+
+// Pages are written as `<page number>_<file name>` so they sort in reading
+// order and entries with the same name in different folders do not clash.
+fn extract(args: &VolumeArgs, output: Option<PathBuf>, all: bool) -> Result<i32> {
+    let mut volume = Volume::open(&args.path)?;
+    let output = output.unwrap_or_else(|| PathBuf::from(args.path.file_stem().unwrap_or_default()));
+    fs::create_dir_all(&output).with_context(|| format!("Failed to create folder: {}", output.display()))?;
+
+    let pages = volume.shown_pages(all);
+    let width = pages.len().to_string().len().max(3);
+    let mut written = Vec::new();
+    for (number, page) in pages.into_iter().enumerate() {
+        let name = volume.pages[page].rsplit(['/', '\\']).next().unwrap_or_default().to_string();
+        let target = output.join(format!("{:0width$}_{}", number + 1, name));
+        let data = volume.read_page(page)?;
+        fs::write(&target, data).with_context(|| format!("Failed to write {}", target.display()))?;
+        if !args.json {
+            println!("{}", target.display());
+        }
+        written.push(target);
+    }
+    if args.json {
+        print_json(&written)?;
+    }
+    Ok(0)
+}
+
+fn thumb(args: &VolumeArgs, output: Option<PathBuf>, width: u32, height: u32) -> Result<i32> {
+    let output = output.unwrap_or_else(|| {
+        let mut name = args.path.file_stem().unwrap_or_default().to_os_string();
+        name.push(".jpg");
+        PathBuf::from(name)
+    });
+    let image = library::thumbnail(&args.path, width, height)?;
+    let format = ImageFormat::from_path(&output).unwrap_or(ImageFormat::Jpeg);
+    let image = decoder::convert_for_format(image, format);
+    image
+        .save_with_format(&output, format)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+
+    if args.json {
+        print_json(&serde_json::json!({
+            "path": output,
+            "width": image.width(),
+            "height": image.height(),
+        }))?;
+    } else {
+        println!("{} ({}x{})", output.display(), image.width(), image.height());
+    }
+    Ok(0)
+}
+
+fn verify(args: &VolumeArgs) -> Result<i32> {
+    let clean = verify::print_reports(&args.path, args.json)?;
+    Ok(if clean { 0 } else { 1 })
+}
+
+// Pack each folder, carrying on past failures.
+fn pack(folders: &[PathBuf], output: Option<PathBuf>, options: &PackOptions, json: bool) -> Result<i32> {
+    let mut results = Vec::new();
+    for folder in folders {
+        let target = match &output {
+            Some(dir) => dir.join(archive::default_pack_path(folder).file_name().unwrap_or_default()),
+            None => archive::default_pack_path(folder),
+        };
+        let packed = archive::pack_folder(folder, &target, options);
+        if !json {
+            match &packed {
+                Ok(pages) => println!("{} -> {} ({} pages)", folder.display(), target.display(), pages),
+                Err(e) => eprintln!("{}: {:#}", folder.display(), e),
+            }
+        }
+        results.push(PackOutput {
+            folder: folder.clone(),
+            output: target,
+            pages: packed.as_ref().ok().copied(),
+            error: packed.err().map(|e| format!("{:#}", e)),
+        });
+    }
+    if json {
+        print_json(&results)?;
+    }
+    Ok(if results.iter().all(|result| result.error.is_none()) { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::testing;
+    use zip::CompressionMethod;
+
+    #[test]
+    fn leaves_out_deleted_pages_unless_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        let page = testing::png(4, 4);
+        let comic_info = br#"<ComicInfo><Pages><Page Image="1" Type="Deleted"/></Pages></ComicInfo>"#;
+        testing::write_zip(
+            &path,
+            &[
+                ("ComicInfo.xml", comic_info, CompressionMethod::Deflated),
+                ("001.png", &page, CompressionMethod::Stored),
+                ("002.png", &page, CompressionMethod::Stored),
+                ("003.png", &page, CompressionMethod::Stored),
+            ],
+        );
+
+        let volume = Volume::open(&path).unwrap();
+        assert_eq!(volume.shown_pages(false), [0, 2]);
+        assert_eq!(volume.shown_pages(true), [0, 1, 2]);
+
+        let output = dir.path().join("pages");
+        let args = VolumeArgs { path, json: true };
+        extract(&args, Some(output.clone()), false).unwrap();
+        let mut written: Vec<String> = fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        written.sort();
+        assert_eq!(written, ["001_001.png", "002_003.png"]);
+    }
+}
@@ -0,0 +1,325 @@
+use anyhow::{Context as AnyhowContext, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fs;
+use std::path::Path;
+
+use crate::ReadingDirection;
+
+pub const FILE_NAME: &str = "ComicInfo.xml";
+
+// Element order of the ComicInfo 2.1 schema, including `Pages`. Some readers
+// validate against it, so elements are written back in this order.
+const ELEMENT_ORDER: &[&str] = &[
+    "Title", "Series", "Number", "Count", "Volume", "AlternateSeries", "AlternateNumber",
+    "AlternateCount", "Summary", "Notes", "Year", "Month", "Day", "Writer", "Penciller",
+    "Inker", "Colorist", "Letterer", "CoverArtist", "Editor", "Translator", "Publisher",
+    "Imprint", "Genre", "Tags", "Web", "PageCount", "LanguageISO", "Format", "BlackAndWhite",
+    "Manga", "Characters", "Teams", "Locations", "ScanInformation", "StoryArc",
+    "StoryArcNumber", "SeriesGroup", "AgeRating", "Pages", "CommunityRating",
+    "MainCharacterOrTeam", "Review", "GTIN",
+];
+
+// One `<Page>` element. All attributes are kept so nothing is lost when the
+// file is written back.
+#[derive(Debug, Clone, Default)]
+pub struct PageInfo {
+    pub attributes: Vec<(String, String)>,
+}
+
+impl PageInfo {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Zero-based index into the volume's sorted page list.
+    pub fn image(&self) -> Option<usize> {
+        self.attribute("Image").and_then(|image| image.trim().parse().ok())
+    }
+
+    pub fn page_type(&self) -> Option<&str> {
+        self.attribute("Type")
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: String) {
+        match self.attributes.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((name.to_string(), value)),
+        }
+    }
+}
+
+// Metadata from a `ComicInfo.xml` file. Simple fields such as Series or
+// Writer are kept in document order.
+#[derive(Debug, Clone, Default)]
+pub struct ComicInfo {
+    pub fields: Vec<(String, String)>,
+    pub pages: Vec<PageInfo>,
+}
+
+impl ComicInfo {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    // Set a simple field. An empty value removes it.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            self.fields.retain(|(key, _)| key != name);
+        } else if let Some((_, existing)) = self.fields.iter_mut().find(|(key, _)| key == name) {
+            *existing = value.to_string();
+        } else {
+            self.fields.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    // `Manga=Yes` says nothing about the direction, so it leaves the choice
+    // to the series setting.
+    pub fn reading_direction(&self) -> Option<ReadingDirection> {
+        match self.get("Manga") {
+            Some("YesAndRightToLeft") => Some(ReadingDirection::RightToLeft),
+            Some("No") => Some(ReadingDirection::LeftToRight),
+            _ => None,
+        }
+    }
+
+    pub fn page_type(&self, page: usize) -> Option<&str> {
+        self.pages
+            .iter()
+            .find(|info| info.image() == Some(page))
+            .and_then(PageInfo::page_type)
+    }
+
+    pub fn is_deleted(&self, page: usize) -> bool {
+        self.page_type(page) == Some("Deleted")
+    }
+
+    // Drop page `page` after it was removed from the volume and renumber the
+    // pages after it.
+    pub fn remove_page(&mut self, page: usize) {
+        self.pages.retain(|info| info.image() != Some(page));
+        for info in &mut self.pages {
+            if let Some(image) = info.image().filter(|&image| image > page) {
+                info.set_attribute("Image", (image - 1).to_string());
+            }
+        }
+        if let Some(count) = self.get("PageCount").and_then(|count| count.parse::<usize>().ok()) {
+            self.set("PageCount", &count.saturating_sub(1).to_string());
+        }
+    }
+
+    // Refresh the recorded size of a page whose image was re-encoded. Only
+    // attributes the file already has are touched.
+    pub fn update_image(&mut self, page: usize, size: usize, width: u32, height: u32) {
+        let Some(info) = self.pages.iter_mut().find(|info| info.image() == Some(page)) else {
+            return;
+        };
+        for (name, value) in [("ImageSize", size.to_string()), ("ImageWidth", width.to_string()), ("ImageHeight", height.to_string())] {
+            if info.attribute(name).is_some() {
+                info.set_attribute(name, value);
+            }
+        }
+    }
+
+    pub fn front_cover(&self) -> Option<usize> {
+        self.pages
+            .iter()
+            .find(|info| info.page_type() == Some("FrontCover"))
+            .and_then(PageInfo::image)
+    }
+
+    pub fn to_xml(&self) -> String {
+        let position = |name: &str| ELEMENT_ORDER.iter().position(|known| *known == name).unwrap_or(ELEMENT_ORDER.len());
+        let mut fields: Vec<&(String, String)> = self.fields.iter().filter(|(_, value)| !value.is_empty()).collect();
+        // Unknown fields keep their order after the schema ones.
+        fields.sort_by_key(|(name, _)| position(name));
+        let pages_at = fields.partition_point(|(name, _)| position(name) < position("Pages"));
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str(
+            "<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+        );
+        let write_field = |xml: &mut String, (name, value): &(String, String)| {
+            xml.push_str(&format!("  <{name}>{}</{name}>\n", escape(value)));
+        };
+        for field in &fields[..pages_at] {
+            write_field(&mut xml, field);
+        }
+        if !self.pages.is_empty() {
+            xml.push_str("  <Pages>\n");
+            for page in &self.pages {
+                xml.push_str("    <Page");
+                for (key, value) in &page.attributes {
+                    xml.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+                }
+                xml.push_str(" />\n");
+            }
+            xml.push_str("  </Pages>\n");
+        }
+        for field in &fields[pages_at..] {
+            write_field(&mut xml, field);
+        }
+        xml.push_str("</ComicInfo>\n");
+        xml
+    }
+}
+
+fn escape(value: &str) -> String {
+    quick_xml::escape::escape(value).into_owned()
+}
+
+fn local_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).into_owned()
+}
+
+fn page_info(element: &BytesStart) -> PageInfo {
+    let attributes = element
+        .attributes()
+        .flatten()
+        .map(|attr| {
+            let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+            let value = match attr.unescape_value() {
+                Ok(value) => value.into_owned(),
+                Err(_) => String::from_utf8_lossy(&attr.value).into_owned(),
+            };
+            (key, value)
+        })
+        .collect();
+    PageInfo { attributes }
+}
+
+pub fn parse(xml: &str) -> Result<ComicInfo> {
+    let mut reader = Reader::from_str(xml);
+    let mut info = ComicInfo::default();
+    let mut depth = 0;
+    let mut field: Option<String> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event().context("Invalid ComicInfo.xml")? {
+            Event::Start(e) => {
+                depth += 1;
+                let name = local_name(&e);
+                match depth {
+                    1 if name != "ComicInfo" => anyhow::bail!("Not a ComicInfo document"),
+                    2 if name != "Pages" => {
+                        field = Some(name);
+                        text.clear();
+                    }
+                    3 if name == "Page" => info.pages.push(page_info(&e)),
+                    _ => {}
+                }
+            }
+            Event::Empty(e) => {
+                let name = local_name(&e);
+                match depth {
+                    1 if name != "Pages" => info.fields.push((name, String::new())),
+                    2 if name == "Page" => info.pages.push(page_info(&e)),
+                    _ => {}
+                }
+            }
+            Event::Text(e) if field.is_some() => {
+                text.push_str(&e.unescape().context("Invalid ComicInfo.xml")?);
+            }
+            Event::CData(e) if field.is_some() => {
+                text.push_str(&String::from_utf8_lossy(&e.into_inner()));
+            }
+            Event::End(_) => {
+                if depth == 2 {
+                    if let Some(name) = field.take() {
+                        info.fields.push((name, text.trim().to_string()));
+                    }
+                }
+                depth -= 1;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(info)
+}
+
+// ComicInfo.xml stored next to the images of a folder volume.
+pub fn load_from_dir(dir: &Path) -> Option<ComicInfo> {
+    let path = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(FILE_NAME))
+        })?;
+    let parsed = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))
+        .and_then(|xml| parse(&xml));
+    match parsed {
+        Ok(info) => Some(info),
+        Err(e) => {
+            eprintln!("Ignoring {}: {:#}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_elements_in_schema_order() {
+        let mut info = ComicInfo::default();
+        let fields = [
+            ("Custom", "x"),
+            ("CommunityRating", "4.5"),
+            ("Tags", "action"),
+            ("Web", "https://example.com"),
+            ("Genre", "Drama"),
+            ("Title", "A & B"),
+        ];
+        for (name, value) in fields {
+            info.set(name, value);
+        }
+        info.pages.push(PageInfo {
+            attributes: vec![("Image".into(), "0".into()), ("Type".into(), "FrontCover".into())],
+        });
+
+        let xml = info.to_xml();
+        let elements = ["<Title>A &amp; B", "<Genre>", "<Tags>", "<Web>", "<Pages>", "<CommunityRating>", "<Custom>"];
+        let order: Vec<usize> = elements
+            .iter()
+            .map(|element| xml.find(element).unwrap_or_else(|| panic!("{} missing from {}", element, xml)))
+            .collect();
+        assert!(order.windows(2).all(|pair| pair[0] < pair[1]), "{}", xml);
+
+        let parsed = parse(&xml).unwrap();
+        assert_eq!(parsed.get("Title"), Some("A & B"));
+        assert_eq!(parsed.get("CommunityRating"), Some("4.5"));
+        assert_eq!(parsed.front_cover(), Some(0));
+    }
+
+    #[test]
+    fn reads_direction_from_manga_field() {
+        let direction = |manga: Option<&str>| {
+            let mut info = ComicInfo::default();
+            if let Some(manga) = manga {
+                info.set("Manga", manga);
+            }
+            info.reading_direction()
+        };
+        assert_eq!(direction(Some("YesAndRightToLeft")), Some(ReadingDirection::RightToLeft));
+        assert_eq!(direction(Some("No")), Some(ReadingDirection::LeftToRight));
+        assert_eq!(direction(Some("Yes")), None);
+        assert_eq!(direction(Some("Unknown")), None);
+        assert_eq!(direction(None), None);
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Context as AnyhowContext, Result};
+use eframe::egui::{self, ColorImage};
+use image::{DynamicImage, ImageFormat};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::archive::ArchiveBackend;
+use crate::texture;
+use crate::workers::WorkerPool;
+
+// The open archive of the current volume, shared with the decoding workers.
+pub type SharedArchive = Arc<Mutex<Box<dyn ArchiveBackend>>>;
+
+#[derive(Clone)]
+pub enum PageSource {
+    File(PathBuf),
+    Archive {
+        archive: SharedArchive,
+        entry: usize,
+        name: String,
+    },
+}
+
+pub struct DecodedPage {
+    pub image: DynamicImage,
+    pub color_image: ColorImage,
+    // Downsampled copies, only built for pages too large for one texture.
+    pub mips: Vec<ColorImage>,
+}
+
+pub struct DecodeResult {
+    pub generation: u64,
+    pub page: usize,
+    pub result: Result<DecodedPage, String>,
+}
+
+struct Job {
+    generation: u64,
+    page: usize,
+    source: PageSource,
+}
+
+// A pool of threads that decode pages off the UI thread. Jobs are tagged
+// with the page index and a generation counter so the reader can drop
+// results that belong to a volume it has since closed.
+pub struct PageDecoder {
+    pool: WorkerPool<Job, DecodeResult>,
+}
+
+impl PageDecoder {
+    pub fn new(workers: usize) -> Self {
+        let pool = WorkerPool::new("page-decoder", workers, |job: Job, ctx| {
+            let max_texture_side = ctx.map(|ctx| ctx.input(|i| i.max_texture_side)).unwrap_or(usize::MAX);
+            DecodeResult {
+                generation: job.generation,
+                page: job.page,
+                result: decode_source(&job.source, max_texture_side).map_err(|e| format!("{:#}", e)),
+            }
+        });
+        Self { pool }
+    }
+
+    // Wake the UI whenever a page finishes decoding.
+    pub fn set_repaint_context(&self, ctx: egui::Context) {
+        self.pool.set_repaint_context(ctx);
+    }
+
+    // Queue a page. Urgent requests jump ahead of queued prefetches.
+    pub fn request(&self, generation: u64, page: usize, source: PageSource, urgent: bool) {
+        self.pool.push(Job { generation, page, source }, urgent);
+    }
+
+    // Move an already queued page to the front of the queue.
+    pub fn prioritize(&self, generation: u64, page: usize) {
+        self.pool.move_to_front(|job| job.generation == generation && job.page == page);
+    }
+
+    // Drop every queued job; pages already being decoded still finish.
+    pub fn cancel_pending(&self) {
+        self.pool.clear();
+    }
+
+    pub fn try_recv(&self) -> Option<DecodeResult> {
+        self.pool.try_recv()
+    }
+}
+
+fn decode_source(source: &PageSource, max_texture_side: usize) -> Result<DecodedPage> {
+    let image = match source {
+        PageSource::File(path) => decode_image_file(path)?,
+        PageSource::Archive { archive, entry, name } => {
+            // Hold the archive only while reading so other workers can decode.
+            let buffer = archive.lock().unwrap().read_entry(*entry)?;
+            decode_image_bytes(name, &buffer)?
+        }
+    };
+
+    let image_buffer = image.to_rgba8();
+    let color_image = texture::color_image(&image_buffer);
+    let mips = if image.width().max(image.height()) as usize > max_texture_side {
+        texture::build_mip_levels(&image_buffer)
+    } else {
+        Vec::new()
+    };
+
+    Ok(DecodedPage { image, color_image, mips })
+}
+
+pub fn decode_image_file(path: &Path) -> Result<DynamicImage> {
+    image::ImageReader::open(path)
+        .with_context(|| format!("Failed to open image file: {}", path.display()))?
+        .with_guessed_format()
+        .with_context(|| format!("Failed to determine image format: {}", path.display()))?
+        .decode()
+        .with_context(|| format!("Failed to decode image: {}", path.display()))
+}
+
+// Convert an image so it can be saved as `format`. JPEG has no alpha
+// channel, so those images are flattened to RGB.
+pub fn convert_for_format(image: DynamicImage, format: ImageFormat) -> DynamicImage {
+    if format == ImageFormat::Jpeg && image.color() != image::ColorType::Rgb8 {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    } else {
+        image
+    }
+}
+
+// Decode an archive entry using the format implied by its name.
+pub fn decode_image_bytes(name: &str, buffer: &[u8]) -> Result<DynamicImage> {
+    let extension = Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let format = match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => ImageFormat::Jpeg,
+        Some("png") => ImageFormat::Png,
+        Some("webp") => ImageFormat::WebP,
+        Some("gif") => ImageFormat::Gif,
+        Some("jp2") | Some("j2k") | Some("j2c") | Some("jpx") | Some("jpf") => {
+            return crate::jpeg2000::decode(buffer).with_context(|| format!("Failed to decode image: {}", name));
+        }
+        _ => return Err(anyhow::anyhow!("Unsupported image format")),
+    };
+
+    image::load_from_memory_with_format(buffer, format)
+        .with_context(|| format!("Failed to decode image: {}", name))
+}
@@ -0,0 +1,594 @@
+use anyhow::{Context as AnyhowContext, Result};
+use std::collections::BTreeMap;
+
+const SOC: u16 = 0xFF4F;
+const SIZ: u16 = 0xFF51;
+const COD: u16 = 0xFF52;
+const COC: u16 = 0xFF53;
+const QCD: u16 = 0xFF5C;
+const QCC: u16 = 0xFF5D;
+const RGN: u16 = 0xFF5E;
+const POC: u16 = 0xFF5F;
+const PPM: u16 = 0xFF60;
+const PPT: u16 = 0xFF61;
+const SOT: u16 = 0xFF90;
+const SOD: u16 = 0xFF93;
+const EOC: u16 = 0xFFD9;
+
+// The decomposition level and code-block limits of the standard.
+const MAX_LEVELS: u8 = 32;
+const MAX_BLOCK_EXPONENT: u8 = 10;
+// Addressable by the 16-bit tile index of SOT.
+const MAX_TILES: u64 = 65535;
+
+// Signed components are displayed like unsigned ones, centred on mid-gray.
+pub(super) struct Component {
+    pub depth: u8,
+    pub dx: u32,
+    pub dy: u32,
+}
+
+// The image and tile grid from SIZ, all on the reference grid.
+pub(super) struct Size {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub tile_x0: u32,
+    pub tile_y0: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub components: Vec<Component>,
+}
+
+impl Size {
+    pub fn tiles_wide(&self) -> u32 {
+        (self.x1 - self.tile_x0).div_ceil(self.tile_width)
+    }
+
+    pub fn tiles_high(&self) -> u32 {
+        (self.y1 - self.tile_y0).div_ceil(self.tile_height)
+    }
+
+    // The area of tile `index`, clipped to the image.
+    pub fn tile_rect(&self, index: u32) -> (u32, u32, u32, u32) {
+        let p = index % self.tiles_wide();
+        let q = index / self.tiles_wide();
+        let x0 = self.tile_x0 as u64 + p as u64 * self.tile_width as u64;
+        let y0 = self.tile_y0 as u64 + q as u64 * self.tile_height as u64;
+        (
+            x0.max(self.x0 as u64) as u32,
+            y0.max(self.y0 as u64) as u32,
+            (x0 + self.tile_width as u64).min(self.x1 as u64) as u32,
+            (y0 + self.tile_height as u64).min(self.y1 as u64) as u32,
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Progression {
+    Lrcp,
+    Rlcp,
+    Rpcl,
+    Pcrl,
+    Cprl,
+}
+
+// SPcod/SPcoc: how one component is transformed and split into blocks.
+#[derive(Clone)]
+pub(super) struct CodingStyle {
+    pub levels: u8,
+    pub block_width: u8,
+    pub block_height: u8,
+    pub block_style: u8,
+    pub reversible: bool,
+    // Precinct size exponents for each resolution, lowest first.
+    pub precincts: Vec<(u8, u8)>,
+}
+
+#[derive(Clone)]
+pub(super) struct Cod {
+    pub sop: bool,
+    pub eph: bool,
+    pub progression: Progression,
+    pub layers: u16,
+    pub mct: bool,
+    pub style: CodingStyle,
+}
+
+#[derive(Clone)]
+pub(super) struct Quantization {
+    // 0: none, 1: scalar derived from the LL band, 2: scalar expounded.
+    pub style: u8,
+    pub guard_bits: u8,
+    // (exponent, mantissa) per band: LL, then HL, LH, HH of each level.
+    pub steps: Vec<(u16, u16)>,
+}
+
+impl Quantization {
+    // The exponent and mantissa of band `orientation` at `resolution`.
+    pub fn step(&self, resolution: u8, orientation: u8) -> Option<(u16, u16)> {
+        if self.style == 1 {
+            let (exponent, mantissa) = *self.steps.first()?;
+            let exponent = exponent.checked_sub(resolution.saturating_sub(1) as u16)?;
+            return Some((exponent, mantissa));
+        }
+        let index = if resolution == 0 {
+            0
+        } else {
+            3 * (resolution as usize - 1) + orientation as usize
+        };
+        self.steps.get(index).copied()
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct ProgressionChange {
+    pub resolution_start: u8,
+    pub component_start: u16,
+    pub layer_end: u16,
+    pub resolution_end: u8,
+    pub component_end: u16,
+    pub progression: Progression,
+}
+
+// The markers that can appear in both the main and the tile headers.
+#[derive(Clone, Default)]
+struct Markers {
+    cod: Option<Cod>,
+    coc: Vec<Option<CodingStyle>>,
+    qcd: Option<Quantization>,
+    qcc: Vec<Option<Quantization>>,
+    rgn: Vec<Option<u8>>,
+    poc: Vec<ProgressionChange>,
+}
+
+type TileParts = (Markers, Vec<u8>, Option<Vec<u8>>);
+
+impl Markers {
+    fn new(components: usize) -> Self {
+        Markers {
+            coc: vec![None; components],
+            qcc: vec![None; components],
+            rgn: vec![None; components],
+            ..Default::default()
+        }
+    }
+}
+
+pub(super) struct TileComponentCoding {
+    pub style: CodingStyle,
+    pub quantization: Quantization,
+    pub roi_shift: u8,
+}
+
+pub(super) struct TileCoding {
+    pub sop: bool,
+    pub eph: bool,
+    pub progression: Progression,
+    pub layers: u16,
+    pub mct: bool,
+    pub components: Vec<TileComponentCoding>,
+    pub changes: Vec<ProgressionChange>,
+}
+
+pub(super) struct Tile {
+    pub index: u32,
+    pub coding: TileCoding,
+    // The bodies of every tile-part, in order.
+    pub data: Vec<u8>,
+    // Packet headers moved out of the bodies by PPM or PPT.
+    pub packed_headers: Option<Vec<u8>>,
+}
+
+pub(super) struct Codestream {
+    pub size: Size,
+    pub tiles: Vec<Tile>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + count)
+            .context("JPEG 2000 codestream is truncated")?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // A marker segment: the marker, then its body without the length.
+    fn segment(&mut self) -> Result<(u16, &'a [u8])> {
+        let marker = self.u16()?;
+        let length = self.u16()? as usize;
+        if length < 2 {
+            return Err(anyhow::anyhow!("Invalid JPEG 2000 marker segment length"));
+        }
+        Ok((marker, self.bytes(length - 2)?))
+    }
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<Codestream> {
+    let mut reader = Reader::new(data);
+    if reader.u16()? != SOC {
+        return Err(anyhow::anyhow!("Not a JPEG 2000 codestream"));
+    }
+    let (marker, body) = reader.segment()?;
+    if marker != SIZ {
+        return Err(anyhow::anyhow!("JPEG 2000 codestream does not start with SIZ"));
+    }
+    let size = parse_size(body)?;
+    let components = size.components.len();
+
+    let mut main = Markers::new(components);
+    let mut packed: Vec<(u8, &[u8])> = Vec::new();
+    loop {
+        if reader.remaining() < 2 {
+            return Err(anyhow::anyhow!("JPEG 2000 codestream has no tiles"));
+        }
+        if u16::from_be_bytes([data[reader.pos], data[reader.pos + 1]]) == SOT {
+            break;
+        }
+        let (marker, body) = reader.segment()?;
+        if marker == PPM {
+            let (&index, headers) = body.split_first().context("Invalid PPM marker")?;
+            packed.push((index, headers));
+        } else {
+            parse_marker(&mut main, marker, body, components)?;
+        }
+    }
+    if main.cod.is_none() || main.qcd.is_none() {
+        return Err(anyhow::anyhow!("JPEG 2000 codestream has no COD or QCD marker"));
+    }
+
+    // PPM holds the headers of every tile-part in codestream order, each
+    // run prefixed with its length.
+    packed.sort_by_key(|(index, _)| *index);
+    let packed: Vec<u8> = packed.into_iter().flat_map(|(_, headers)| headers.iter().copied()).collect();
+    let mut packed = (!packed.is_empty()).then(|| Reader::new(&packed[..]));
+
+    let tile_count = size.tiles_wide() as u64 * size.tiles_high() as u64;
+    if tile_count > MAX_TILES {
+        return Err(anyhow::anyhow!("JPEG 2000 image has too many tiles"));
+    }
+
+    // Each tile's markers, packet data and packed packet headers.
+    let mut tiles: BTreeMap<u16, TileParts> = BTreeMap::new();
+    while reader.remaining() >= 2 {
+        let start = reader.pos;
+        let marker = reader.u16()?;
+        if marker == EOC {
+            break;
+        }
+        if marker != SOT {
+            return Err(anyhow::anyhow!("Expected a JPEG 2000 tile-part, found marker {:04X}", marker));
+        }
+        let _length = reader.u16()?;
+        let index = reader.u16()?;
+        let part_length = reader.u32()? as usize;
+        let part = reader.u8()?;
+        let _parts = reader.u8()?;
+        if index as u64 >= tile_count {
+            return Err(anyhow::anyhow!("Invalid JPEG 2000 tile index: {}", index));
+        }
+        let end = if part_length == 0 {
+            // Runs to the end of the codestream.
+            let end = data.len();
+            if data.ends_with(&EOC.to_be_bytes()) { end - 2 } else { end }
+        } else {
+            (start + part_length).min(data.len())
+        };
+
+        let (markers, body, headers) = tiles
+            .entry(index)
+            .or_insert_with(|| (Markers::new(components), Vec::new(), None));
+        loop {
+            if reader.u16()? == SOD {
+                break;
+            }
+            reader.pos -= 2;
+            let (marker, segment) = reader.segment()?;
+            match marker {
+                PPT => {
+                    let headers = headers.get_or_insert_with(Vec::new);
+                    headers.extend_from_slice(segment.get(1..).context("Invalid PPT marker")?);
+                }
+                // Only the first tile-part may change the coding.
+                _ if part == 0 => parse_marker(markers, marker, segment, components)?,
+                _ => {}
+            }
+            if reader.pos > end {
+                return Err(anyhow::anyhow!("JPEG 2000 tile-part header overruns the tile-part"));
+            }
+        }
+        if let Some(packed) = packed.as_mut() {
+            let length = packed.u32()? as usize;
+            headers.get_or_insert_with(Vec::new).extend_from_slice(packed.bytes(length)?);
+        }
+        body.extend_from_slice(&data[reader.pos.min(end)..end]);
+        reader.pos = end;
+    }
+
+    let tiles = tiles
+        .into_iter()
+        .map(|(index, (markers, data, packed_headers))| {
+            Ok(Tile {
+                index: index as u32,
+                coding: resolve_coding(&main, &markers, components)?,
+                data,
+                packed_headers,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Codestream { size, tiles })
+}
+
+fn parse_size(body: &[u8]) -> Result<Size> {
+    let mut reader = Reader::new(body);
+    let _capabilities = reader.u16()?;
+    let x1 = reader.u32()?;
+    let y1 = reader.u32()?;
+    let x0 = reader.u32()?;
+    let y0 = reader.u32()?;
+    let tile_width = reader.u32()?;
+    let tile_height = reader.u32()?;
+    let tile_x0 = reader.u32()?;
+    let tile_y0 = reader.u32()?;
+    let count = reader.u16()?;
+
+    let mut components = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let depth = reader.u8()?;
+        let dx = reader.u8()? as u32;
+        let dy = reader.u8()? as u32;
+        if dx == 0 || dy == 0 || depth & 0x7F > 37 {
+            return Err(anyhow::anyhow!("Invalid JPEG 2000 component"));
+        }
+        components.push(Component {
+            depth: (depth & 0x7F) + 1,
+            dx,
+            dy,
+        });
+    }
+
+    if x0 >= x1 || y0 >= y1 || tile_width == 0 || tile_height == 0 || components.is_empty() {
+        return Err(anyhow::anyhow!("Invalid JPEG 2000 image size"));
+    }
+    if tile_x0 > x0 || tile_y0 > y0 || tile_x0 as u64 + tile_width as u64 <= x0 as u64 || tile_y0 as u64 + tile_height as u64 <= y0 as u64 {
+        return Err(anyhow::anyhow!("Invalid JPEG 2000 tile grid"));
+    }
+    Ok(Size {
+        x0,
+        y0,
+        x1,
+        y1,
+        tile_x0,
+        tile_y0,
+        tile_width,
+        tile_height,
+        components,
+    })
+}
+
+fn parse_marker(markers: &mut Markers, marker: u16, body: &[u8], components: usize) -> Result<()> {
+    let mut reader = Reader::new(body);
+    let component = |reader: &mut Reader| -> Result<usize> {
+        let index = if components < 257 { reader.u8()? as usize } else { reader.u16()? as usize };
+        if index >= components {
+            return Err(anyhow::anyhow!("Invalid JPEG 2000 component index: {}", index));
+        }
+        Ok(index)
+    };
+
+    match marker {
+        COD => {
+            let flags = reader.u8()?;
+            let progression = progression(reader.u8()?)?;
+            let layers = reader.u16()?;
+            let mct = reader.u8()? != 0;
+            if layers == 0 {
+                return Err(anyhow::anyhow!("JPEG 2000 image has no quality layers"));
+            }
+            markers.cod = Some(Cod {
+                sop: flags & 0x02 != 0,
+                eph: flags & 0x04 != 0,
+                progression,
+                layers,
+                mct,
+                style: coding_style(&mut reader, flags & 0x01 != 0)?,
+            });
+        }
+        COC => {
+            let index = component(&mut reader)?;
+            let flags = reader.u8()?;
+            markers.coc[index] = Some(coding_style(&mut reader, flags & 0x01 != 0)?);
+        }
+        QCD => markers.qcd = Some(quantization(&mut reader)?),
+        QCC => {
+            let index = component(&mut reader)?;
+            markers.qcc[index] = Some(quantization(&mut reader)?);
+        }
+        RGN => {
+            let index = component(&mut reader)?;
+            if reader.u8()? != 0 {
+                return Err(anyhow::anyhow!("Unsupported JPEG 2000 region of interest"));
+            }
+            markers.rgn[index] = Some(reader.u8()?);
+        }
+        POC => {
+            let entry_length = if components < 257 { 7 } else { 9 };
+            while reader.remaining() >= entry_length {
+                let resolution_start = reader.u8()?;
+                let component_start = if components < 257 { reader.u8()? as u16 } else { reader.u16()? };
+                let layer_end = reader.u16()?;
+                let resolution_end = reader.u8()?;
+                let component_end = match components < 257 {
+                    // Zero stands for 256 in the one byte form.
+                    true => match reader.u8()? {
+                        0 => 256,
+                        end => end as u16,
+                    },
+                    false => reader.u16()?,
+                };
+                markers.poc.push(ProgressionChange {
+                    resolution_start,
+                    component_start,
+                    layer_end,
+                    resolution_end,
+                    component_end,
+                    progression: progression(reader.u8()?)?,
+                });
+            }
+        }
+        // Lengths, comments and the like are not needed to decode.
+        _ => {}
+    }
+    Ok(())
+}
+
+fn progression(value: u8) -> Result<Progression> {
+    Ok(match value {
+        0 => Progression::Lrcp,
+        1 => Progression::Rlcp,
+        2 => Progression::Rpcl,
+        3 => Progression::Pcrl,
+        4 => Progression::Cprl,
+        _ => return Err(anyhow::anyhow!("Invalid JPEG 2000 progression order: {}", value)),
+    })
+}
+
+fn coding_style(reader: &mut Reader, custom_precincts: bool) -> Result<CodingStyle> {
+    let levels = reader.u8()?;
+    let block_width = reader.u8()? + 2;
+    let block_height = reader.u8()? + 2;
+    let block_style = reader.u8()?;
+    let transform = reader.u8()?;
+    if levels > MAX_LEVELS
+        || block_width > MAX_BLOCK_EXPONENT
+        || block_height > MAX_BLOCK_EXPONENT
+        || block_width + block_height > 12
+    {
+        return Err(anyhow::anyhow!("Invalid JPEG 2000 coding style"));
+    }
+    if block_style & 0x40 != 0 {
+        return Err(anyhow::anyhow!("High-throughput JPEG 2000 is not supported"));
+    }
+    let reversible = match transform {
+        0 => false,
+        1 => true,
+        _ => return Err(anyhow::anyhow!("Unsupported JPEG 2000 wavelet transform: {}", transform)),
+    };
+
+    let precincts = if custom_precincts {
+        let sizes = reader.bytes(levels as usize + 1)?;
+        let precincts: Vec<(u8, u8)> = sizes.iter().map(|size| (size & 0x0F, size >> 4)).collect();
+        // Only the lowest resolution can have one-sample precincts.
+        if precincts[1..].iter().any(|&(x, y)| x == 0 || y == 0) {
+            return Err(anyhow::anyhow!("Invalid JPEG 2000 precinct size"));
+        }
+        precincts
+    } else {
+        vec![(15, 15); levels as usize + 1]
+    };
+
+    Ok(CodingStyle {
+        levels,
+        block_width,
+        block_height,
+        block_style,
+        reversible,
+        precincts,
+    })
+}
+
+fn quantization(reader: &mut Reader) -> Result<Quantization> {
+    let flags = reader.u8()?;
+    let style = flags & 0x1F;
+    let mut steps = Vec::new();
+    match style {
+        0 => {
+            while reader.remaining() > 0 {
+                steps.push(((reader.u8()? >> 3) as u16, 0));
+            }
+        }
+        1 | 2 => {
+            while reader.remaining() >= 2 {
+                let value = reader.u16()?;
+                steps.push((value >> 11, value & 0x7FF));
+            }
+        }
+        _ => return Err(anyhow::anyhow!("Invalid JPEG 2000 quantization style: {}", style)),
+    }
+    if steps.is_empty() {
+        return Err(anyhow::anyhow!("JPEG 2000 quantization has no step sizes"));
+    }
+    Ok(Quantization {
+        style,
+        guard_bits: flags >> 5,
+        steps,
+    })
+}
+
+// Tile-part markers win over the main header, and markers for one
+// component win over those for all of them.
+fn resolve_coding(main: &Markers, tile: &Markers, components: usize) -> Result<TileCoding> {
+    let cod = tile.cod.as_ref().or(main.cod.as_ref()).context("JPEG 2000 tile has no COD marker")?;
+    let components = (0..components)
+        .map(|c| {
+            let style = tile.coc[c]
+                .as_ref()
+                .or(tile.cod.as_ref().map(|cod| &cod.style))
+                .or(main.coc[c].as_ref())
+                .unwrap_or(&cod.style)
+                .clone();
+            let quantization = tile.qcc[c]
+                .as_ref()
+                .or(tile.qcd.as_ref())
+                .or(main.qcc[c].as_ref())
+                .or(main.qcd.as_ref())
+                .context("JPEG 2000 tile has no QCD marker")?
+                .clone();
+            Ok(TileComponentCoding {
+                style,
+                quantization,
+                roi_shift: tile.rgn[c].or(main.rgn[c]).unwrap_or(0),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(TileCoding {
+        sop: cod.sop,
+        eph: cod.eph,
+        progression: cod.progression,
+        layers: cod.layers,
+        mct: cod.mct,
+        components,
+        changes: if tile.poc.is_empty() { main.poc.clone() } else { tile.poc.clone() },
+    })
+}
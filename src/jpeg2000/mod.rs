@@ -0,0 +1,357 @@
+// A JPEG 2000 decoder for the JPXDecode images of PDFs, which the image
+// crate cannot read. Pages that are a single such image are handed out as
+// .jp2 entries and decoded here too. It handles JP2 files and raw
+// codestreams: tiles, precincts, progression changes, packed packet
+// headers, both wavelets and palettes. High-throughput (Part 15) blocks
+// are refused.
+use anyhow::{Context as AnyhowContext, Result};
+use image::{DynamicImage, ImageBuffer};
+
+mod codestream;
+mod packets;
+#[cfg(test)]
+pub(crate) mod testing;
+mod tier1;
+mod tile;
+mod wavelet;
+
+use codestream::{Component, Size};
+
+const JP2_SIGNATURE: &[u8] = b"\x00\x00\x00\x0cjP  \r\n\x87\n";
+const CODESTREAM_MAGIC: &[u8] = b"\xff\x4f\xff\x51";
+// Larger images are refused rather than allocated.
+const MAX_PIXELS: u64 = 1 << 28;
+
+// Enumerated color spaces of the JP2 colr box.
+const CMYK: u32 = 12;
+const SYCC: u32 = 18;
+
+// The decoded samples of one component over the whole image.
+#[derive(Clone)]
+struct Plane {
+    x0: u32,
+    y0: u32,
+    width: usize,
+    height: usize,
+    dx: u32,
+    dy: u32,
+    depth: u8,
+    samples: Vec<u16>,
+}
+
+impl Plane {
+    fn new(size: &Size, component: &Component) -> Self {
+        let x0 = size.x0.div_ceil(component.dx);
+        let y0 = size.y0.div_ceil(component.dy);
+        let width = (size.x1.div_ceil(component.dx) - x0) as usize;
+        let height = (size.y1.div_ceil(component.dy) - y0) as usize;
+        Plane {
+            x0,
+            y0,
+            width,
+            height,
+            dx: component.dx,
+            dy: component.dy,
+            depth: component.depth.min(16),
+            samples: vec![0; width * height],
+        }
+    }
+
+    // The sample covering image column `x`, for subsampled components.
+    fn column(&self, x: u32) -> usize {
+        ((x / self.dx).max(self.x0) - self.x0).min(self.width as u32 - 1) as usize
+    }
+
+    fn row(&self, y: u32) -> usize {
+        ((y / self.dy).max(self.y0) - self.y0).min(self.height as u32 - 1) as usize
+    }
+}
+
+#[derive(Default)]
+struct Jp2Header {
+    colour: Option<u32>,
+    // Each palette column's bit depth and entries.
+    palette: Vec<(u8, Vec<u16>)>,
+    // Per output channel: the component, and the palette column it indexes.
+    mapping: Vec<(usize, Option<usize>)>,
+}
+
+pub fn decode(data: &[u8]) -> Result<DynamicImage> {
+    let (codestream, header) = if data.starts_with(JP2_SIGNATURE) {
+        parse_jp2(data)?
+    } else if data.starts_with(CODESTREAM_MAGIC) {
+        (data, Jp2Header::default())
+    } else {
+        return Err(anyhow::anyhow!("Not a JPEG 2000 image"));
+    };
+
+    let codestream = codestream::parse(codestream)?;
+    let size = &codestream.size;
+    if (size.x1 - size.x0) as u64 * (size.y1 - size.y0) as u64 > MAX_PIXELS {
+        return Err(anyhow::anyhow!("JPEG 2000 image is too large"));
+    }
+
+    let mut planes: Vec<Plane> = size.components.iter().map(|component| Plane::new(size, component)).collect();
+    for tile in &codestream.tiles {
+        tile::decode_tile(size, tile, &mut planes)?;
+    }
+    let planes = apply_palette(planes, &header)?;
+    to_image(&planes, header.colour, size)
+}
+
+// Split a run of JP2 boxes into their types and contents.
+fn boxes(data: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as u64;
+        let kind = &data[pos + 4..pos + 8];
+        let (start, end) = match length {
+            0 => (pos + 8, data.len() as u64),
+            1 => {
+                let length = data
+                    .get(pos + 8..pos + 16)
+                    .context("JP2 box is truncated")?
+                    .try_into()
+                    .map(u64::from_be_bytes)?;
+                (pos + 16, pos as u64 + length)
+            }
+            _ => (pos + 8, pos as u64 + length),
+        };
+        if end < start as u64 {
+            return Err(anyhow::anyhow!("Invalid JP2 box length"));
+        }
+        // A truncated last box keeps what is there.
+        let end = end.min(data.len() as u64) as usize;
+        boxes.push((kind, &data[start..end]));
+        pos = end;
+    }
+    Ok(boxes)
+}
+
+fn parse_jp2(data: &[u8]) -> Result<(&[u8], Jp2Header)> {
+    let mut header = Jp2Header::default();
+    for (kind, contents) in boxes(data)? {
+        match kind {
+            b"jp2h" => {
+                for (kind, contents) in boxes(contents)? {
+                    match kind {
+                        // Only the first colr box counts, and only enumerated
+                        // spaces are understood.
+                        b"colr" if header.colour.is_none() && contents.len() >= 7 && contents[0] == 1 => {
+                            header.colour = Some(u32::from_be_bytes(contents[3..7].try_into().unwrap()));
+                        }
+                        b"pclr" => header.palette = parse_palette(contents)?,
+                        b"cmap" => {
+                            header.mapping = contents
+                                .chunks_exact(4)
+                                .map(|entry| {
+                                    let component = u16::from_be_bytes([entry[0], entry[1]]) as usize;
+                                    (component, (entry[2] == 1).then_some(entry[3] as usize))
+                                })
+                                .collect();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            b"jp2c" => return Ok((contents, header)),
+            _ => {}
+        }
+    }
+    Err(anyhow::anyhow!("JP2 file has no codestream"))
+}
+
+fn parse_palette(contents: &[u8]) -> Result<Vec<(u8, Vec<u16>)>> {
+    let truncated = || anyhow::anyhow!("JP2 palette is truncated");
+    let entries = u16::from_be_bytes([*contents.first().ok_or_else(truncated)?, *contents.get(1).ok_or_else(truncated)?]) as usize;
+    let columns = *contents.get(2).ok_or_else(truncated)? as usize;
+    let depths: Vec<u8> = contents.get(3..3 + columns).ok_or_else(truncated)?.iter().map(|d| (d & 0x7F) + 1).collect();
+
+    let mut palette: Vec<(u8, Vec<u16>)> = depths.iter().map(|&depth| (depth.min(16), Vec::with_capacity(entries))).collect();
+    let mut pos = 3 + columns;
+    for _ in 0..entries {
+        for (column, &depth) in depths.iter().enumerate() {
+            let length = (depth as usize).div_ceil(8);
+            let bytes = contents.get(pos..pos + length).ok_or_else(truncated)?;
+            let value = bytes.iter().fold(0u64, |value, &byte| (value << 8) | byte as u64);
+            palette[column].1.push((value >> depth.saturating_sub(16)) as u16);
+            pos += length;
+        }
+    }
+    Ok(palette)
+}
+
+// Replace palette indices with the colors they stand for.
+fn apply_palette(planes: Vec<Plane>, header: &Jp2Header) -> Result<Vec<Plane>> {
+    if header.palette.is_empty() || header.mapping.is_empty() {
+        return Ok(planes);
+    }
+    header
+        .mapping
+        .iter()
+        .map(|&(component, column)| {
+            let plane = planes.get(component).context("JP2 component mapping is invalid")?;
+            let Some(column) = column else {
+                return Ok(plane.clone());
+            };
+            let (depth, entries) = header.palette.get(column).context("JP2 component mapping is invalid")?;
+            let last = entries.len().checked_sub(1).context("JP2 palette is empty")?;
+            Ok(Plane {
+                depth: *depth,
+                samples: plane.samples.iter().map(|&index| entries[(index as usize).min(last)]).collect(),
+                ..plane.clone()
+            })
+        })
+        .collect()
+}
+
+fn to_image(planes: &[Plane], colour: Option<u32>, size: &Size) -> Result<DynamicImage> {
+    let width = (size.x1 - size.x0) as usize;
+    let height = (size.y1 - size.y0) as usize;
+    let cmyk = colour == Some(CMYK) && planes.len() >= 4;
+    let ycc = colour == Some(SYCC) && planes.len() >= 3;
+    let inputs = if cmyk { 4 } else { planes.len().min(4) };
+    let channels = if cmyk { 3 } else { inputs };
+    if channels == 0 {
+        return Err(anyhow::anyhow!("JPEG 2000 image has no components"));
+    }
+
+    // Each image column's sample in every plane.
+    let columns: Vec<[usize; 4]> = (0..width as u32)
+        .map(|x| {
+            let mut column = [0; 4];
+            for (i, plane) in planes[..inputs].iter().enumerate() {
+                column[i] = plane.column(size.x0 + x);
+            }
+            column
+        })
+        .collect();
+    let scales: Vec<f32> = planes[..inputs].iter().map(|plane| ((1u32 << plane.depth) - 1) as f32).collect();
+
+    let mut pixels = Vec::with_capacity(width * height * channels);
+    for y in 0..height as u32 {
+        let rows: Vec<usize> = planes[..inputs].iter().map(|plane| plane.row(size.y0 + y) * plane.width).collect();
+        for column in &columns {
+            let mut values = [0.0f32; 4];
+            for (i, plane) in planes[..inputs].iter().enumerate() {
+                values[i] = plane.samples[rows[i] + column[i]] as f32 / scales[i];
+            }
+            if cmyk {
+                let k = 1.0 - values[3];
+                pixels.extend([(1.0 - values[0]) * k, (1.0 - values[1]) * k, (1.0 - values[2]) * k]);
+            } else if ycc {
+                let [y, cb, cr, alpha] = values;
+                let (cb, cr) = (cb - 0.5, cr - 0.5);
+                pixels.extend([y + 1.402 * cr, y - 0.344_136 * cb - 0.714_136 * cr, y + 1.772 * cb]);
+                if inputs == 4 {
+                    pixels.push(alpha);
+                }
+            } else {
+                pixels.extend_from_slice(&values[..inputs]);
+            }
+        }
+    }
+
+    let (width, height) = (width as u32, height as u32);
+    let invalid = || anyhow::anyhow!("Invalid JPEG 2000 image size");
+    if planes[..inputs].iter().all(|plane| plane.depth <= 8) {
+        let pixels: Vec<u8> = pixels.iter().map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+        Ok(match channels {
+            1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+            2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+            3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+            _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+        })
+    } else {
+        let pixels: Vec<u16> = pixels.iter().map(|v| (v.clamp(0.0, 1.0) * 65535.0).round() as u16).collect();
+        Ok(match channels {
+            1 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+            2 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+            3 => DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+            _ => DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{self, Options};
+    use super::*;
+    use image::{GenericImageView, GrayImage, RgbImage};
+
+    fn rgb(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 7 + y * 3) as u8, (x * y) as u8, (x * 5) as u8 ^ (y * 11) as u8])
+        }))
+    }
+
+    fn gray(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| image::Luma([((x * 31) ^ (y * 17)) as u8])))
+    }
+
+    #[test]
+    fn decodes_lossless_codestreams() {
+        let image = rgb(37, 29);
+        let options = Options {
+            levels: 3,
+            block_exponent: 3,
+            tile_size: None,
+            progression: 0,
+            irreversible: false,
+        };
+        assert_eq!(decode(&testing::encode(&image, &options)).unwrap(), image);
+    }
+
+    #[test]
+    fn decodes_every_progression_order() {
+        let image = rgb(21, 18);
+        for progression in 0..5 {
+            let options = Options {
+                levels: 2,
+                block_exponent: 2,
+                tile_size: None,
+                progression,
+                irreversible: false,
+            };
+            assert_eq!(decode(&testing::encode(&image, &options)).unwrap(), image, "progression {}", progression);
+        }
+    }
+
+    #[test]
+    fn decodes_tiled_jp2_files() {
+        let image = gray(40, 23);
+        let options = Options {
+            levels: 2,
+            block_exponent: 4,
+            tile_size: Some(16),
+            progression: 4,
+            irreversible: false,
+        };
+        let file = testing::jp2(&testing::encode(&image, &options), 40, 23, 1, 17);
+        assert_eq!(decode(&file).unwrap(), image);
+    }
+
+    #[test]
+    fn decodes_lossy_codestreams() {
+        let image = rgb(33, 26);
+        let options = Options {
+            levels: 3,
+            block_exponent: 3,
+            tile_size: None,
+            progression: 2,
+            irreversible: true,
+        };
+        let decoded = decode(&testing::encode(&image, &options)).unwrap();
+        let difference = decoded.as_bytes().iter().zip(image.as_bytes()).map(|(a, b)| a.abs_diff(*b)).max();
+        assert_eq!(decoded.dimensions(), image.dimensions());
+        assert!(difference <= Some(3), "{:?}", difference);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        assert!(decode(b"\x89PNG\r\n\x1a\n").is_err());
+        assert!(decode(JP2_SIGNATURE).is_err());
+        assert!(decode(&[0xFF, 0x4F, 0xFF, 0x51, 0, 2]).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+
+// Packet header bits, with a zero bit stuffed after every 0xFF byte.
+pub(super) struct BitReader<'a> {
+    data: &'a [u8],
+    pub pos: usize,
+    byte: u8,
+    bits: u8,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8], pos: usize) -> Self {
+        BitReader { data, pos, byte: 0, bits: 0 }
+    }
+
+    pub fn bit(&mut self) -> Result<u32> {
+        if self.bits == 0 {
+            self.bits = if self.byte == 0xFF { 7 } else { 8 };
+            self.byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| anyhow::anyhow!("JPEG 2000 packet header is truncated"))?;
+            self.pos += 1;
+        }
+        self.bits -= 1;
+        Ok((self.byte >> self.bits) as u32 & 1)
+    }
+
+    pub fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()?;
+        }
+        Ok(value)
+    }
+
+    // Skip to the end of the header, which never ends on 0xFF: the stuffed
+    // bit after one spills into another byte.
+    pub fn align(&mut self) {
+        if self.byte == 0xFF {
+            self.pos += 1;
+        }
+        self.bits = 0;
+        self.byte = 0;
+    }
+
+    // The number of coding passes a code-block adds in this packet.
+    pub fn pass_count(&mut self) -> Result<u32> {
+        if self.bit()? == 0 {
+            return Ok(1);
+        }
+        if self.bit()? == 0 {
+            return Ok(2);
+        }
+        let value = self.bits(2)?;
+        if value < 3 {
+            return Ok(3 + value);
+        }
+        let value = self.bits(5)?;
+        if value < 31 {
+            return Ok(6 + value);
+        }
+        Ok(37 + self.bits(7)?)
+    }
+}
+
+// A quadtree of minimums over a grid of code-blocks, decoded a little at a
+// time as each packet needs to know more about a block.
+pub(super) struct TagTree {
+    // Width and height of every level, leaves first.
+    levels: Vec<(usize, usize)>,
+    offsets: Vec<usize>,
+    values: Vec<u32>,
+    lows: Vec<u32>,
+}
+
+impl TagTree {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut levels = vec![(width, height)];
+        while levels.last().is_some_and(|&(w, h)| w > 1 || h > 1) {
+            let (w, h) = *levels.last().unwrap();
+            levels.push((w.div_ceil(2), h.div_ceil(2)));
+        }
+        let mut offsets = Vec::with_capacity(levels.len());
+        let mut nodes = 0;
+        for &(w, h) in &levels {
+            offsets.push(nodes);
+            nodes += w * h;
+        }
+        TagTree {
+            levels,
+            offsets,
+            values: vec![u32::MAX; nodes],
+            lows: vec![0; nodes],
+        }
+    }
+
+    // Whether the value of leaf (x, y) is below `threshold`, reading only
+    // as many bits as that takes.
+    pub fn decode(&mut self, reader: &mut BitReader, x: usize, y: usize, threshold: u32) -> Result<bool> {
+        let mut low = 0;
+        for level in (0..self.levels.len()).rev() {
+            let node = self.offsets[level] + (y >> level) * self.levels[level].0 + (x >> level);
+            low = low.max(self.lows[node]);
+            while low < threshold && low < self.values[node] {
+                if reader.bit()? == 1 {
+                    self.values[node] = low;
+                } else {
+                    low += 1;
+                }
+            }
+            self.lows[node] = low;
+        }
+        let leaf = y * self.levels[0].0 + x;
+        Ok(self.values[leaf] < threshold)
+    }
+
+    pub fn value(&self, x: usize, y: usize) -> u32 {
+        self.values[y * self.levels[0].0 + x]
+    }
+}
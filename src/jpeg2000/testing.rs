@@ -0,0 +1,747 @@
+// A small JPEG 2000 encoder the tests build images with: one quality layer
+// and one precinct per resolution, so every progression order lays packets
+// out by resolution or by component.
+use image::DynamicImage;
+
+use super::tier1::{Contexts, STATES};
+
+const GUARD_BITS: u32 = 2;
+
+pub struct Options {
+    pub levels: u32,
+    pub block_exponent: u32,
+    // Tile width and height, a multiple of 2^levels. One tile by default.
+    pub tile_size: Option<u32>,
+    // As in COD: 0 LRCP, 1 RLCP, 2 RPCL, 3 PCRL, 4 CPRL.
+    pub progression: u8,
+    // The lossy 9/7 wavelet, quantized in steps of half a sample.
+    pub irreversible: bool,
+}
+
+// A raw codestream of an 8-bit gray or RGB image. RGB images go through
+// the color transform that matches the wavelet.
+pub fn encode(image: &DynamicImage, options: &Options) -> Vec<u8> {
+    let (width, height) = (image.width(), image.height());
+    let components: Vec<Vec<f32>> = match image {
+        DynamicImage::ImageLuma8(gray) => vec![gray.pixels().map(|p| p[0] as f32).collect()],
+        DynamicImage::ImageRgb8(rgb) => (0..3).map(|c| rgb.pixels().map(|p| p[c] as f32).collect()).collect(),
+        _ => panic!("Only 8-bit gray and RGB images can be encoded"),
+    };
+    let mct = components.len() == 3;
+    if let Some(size) = options.tile_size {
+        assert_eq!(size % (1 << options.levels), 0);
+    }
+    let tile_size = options.tile_size.unwrap_or(width.max(height));
+
+    let mut out = vec![0xFF, 0x4F];
+    let mut siz = Vec::new();
+    siz.extend(0u16.to_be_bytes());
+    for value in [width, height, 0, 0, tile_size, tile_size, 0, 0] {
+        siz.extend(value.to_be_bytes());
+    }
+    siz.extend((components.len() as u16).to_be_bytes());
+    for _ in &components {
+        siz.extend([7, 1, 1]);
+    }
+    marker(&mut out, 0xFF51, &siz);
+    let mut cod = vec![0, options.progression];
+    cod.extend(1u16.to_be_bytes());
+    cod.extend([mct as u8, options.levels as u8, options.block_exponent as u8 - 2, options.block_exponent as u8 - 2, 0]);
+    cod.push(!options.irreversible as u8);
+    marker(&mut out, 0xFF52, &cod);
+    let mut qcd = vec![((GUARD_BITS as u8) << 5) | if options.irreversible { 2 } else { 0 }];
+    for band in 0..1 + 3 * options.levels {
+        let exponent = exponent(band, mct, options);
+        if options.irreversible {
+            qcd.extend(((exponent as u16) << 11).to_be_bytes());
+        } else {
+            qcd.push((exponent as u8) << 3);
+        }
+    }
+    marker(&mut out, 0xFF5C, &qcd);
+
+    let tiles_wide = width.div_ceil(tile_size);
+    for tile in 0..tiles_wide * height.div_ceil(tile_size) {
+        let x0 = tile % tiles_wide * tile_size;
+        let y0 = tile / tiles_wide * tile_size;
+        let rect = (x0, y0, (x0 + tile_size).min(width), (y0 + tile_size).min(height));
+        let mut samples: Vec<Vec<f32>> = components
+            .iter()
+            .map(|component| {
+                (rect.1..rect.3)
+                    .flat_map(|y| (rect.0..rect.2).map(move |x| component[(y * width + x) as usize] - 128.0))
+                    .collect()
+            })
+            .collect();
+        if mct {
+            for i in 0..samples[0].len() {
+                let (r, g, b) = (samples[0][i], samples[1][i], samples[2][i]);
+                [samples[0][i], samples[1][i], samples[2][i]] = if options.irreversible {
+                    [
+                        0.299 * r + 0.587 * g + 0.114 * b,
+                        -0.168_736 * r - 0.331_264 * g + 0.5 * b,
+                        0.5 * r - 0.418_688 * g - 0.081_312 * b,
+                    ]
+                } else {
+                    [((r + 2.0 * g + b) / 4.0).floor(), b - g, r - g]
+                };
+            }
+        }
+
+        // Packets of every resolution of every component.
+        let packets: Vec<Vec<Vec<u8>>> = samples
+            .iter()
+            .map(|samples| encode_component(samples, rect, options, mct))
+            .collect();
+        let mut data: Vec<u8> = Vec::new();
+        let resolutions = options.levels as usize + 1;
+        if options.progression <= 2 {
+            for r in 0..resolutions {
+                for component in &packets {
+                    data.extend(&component[r]);
+                }
+            }
+        } else {
+            for component in &packets {
+                for packet in component {
+                    data.extend(packet);
+                }
+            }
+        }
+
+        out.extend([0xFF, 0x90, 0, 10]);
+        out.extend((tile as u16).to_be_bytes());
+        out.extend((14 + data.len() as u32).to_be_bytes());
+        out.extend([0, 1, 0xFF, 0x93]);
+        out.extend(data);
+    }
+    out.extend([0xFF, 0xD9]);
+    out
+}
+
+// Wrap a codestream in a JP2 file with an enumerated color space.
+pub fn jp2(codestream: &[u8], width: u32, height: u32, components: u16, colour: u32) -> Vec<u8> {
+    let mut ihdr = Vec::new();
+    ihdr.extend(height.to_be_bytes());
+    ihdr.extend(width.to_be_bytes());
+    ihdr.extend(components.to_be_bytes());
+    ihdr.extend([7, 7, 0, 0]);
+    let mut colr = vec![1, 0, 0];
+    colr.extend(colour.to_be_bytes());
+    let mut header = Vec::new();
+    jp2_box(&mut header, b"ihdr", &ihdr);
+    jp2_box(&mut header, b"colr", &colr);
+
+    let mut out = Vec::new();
+    jp2_box(&mut out, b"jP  ", b"\r\n\x87\n");
+    jp2_box(&mut out, b"ftyp", b"jp2 \0\0\0\0jp2 ");
+    jp2_box(&mut out, b"jp2h", &header);
+    jp2_box(&mut out, b"jp2c", codestream);
+    out
+}
+
+fn jp2_box(out: &mut Vec<u8>, kind: &[u8], contents: &[u8]) {
+    out.extend((8 + contents.len() as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(contents);
+}
+
+fn marker(out: &mut Vec<u8>, marker: u16, body: &[u8]) {
+    out.extend(marker.to_be_bytes());
+    out.extend((body.len() as u16 + 2).to_be_bytes());
+    out.extend(body);
+}
+
+// Each high-pass filter can double the range of a band.
+fn gain(band: u32) -> u32 {
+    if band == 0 { 0 } else { [2, 1, 1][(band % 3) as usize] }
+}
+
+// Lossless band exponents leave room for that growth and for the color
+// transform's differences. Lossy ones make the step half a sample.
+fn exponent(band: u32, mct: bool, options: &Options) -> u32 {
+    8 + gain(band) + (mct || options.irreversible) as u32
+}
+
+// The quantization step of a band, as the decoder derives it.
+fn step(band: u32, mct: bool, options: &Options) -> f32 {
+    if options.irreversible {
+        2f32.powi((8 + gain(band)) as i32 - exponent(band, mct, options) as i32)
+    } else {
+        1.0
+    }
+}
+
+struct Band {
+    orientation: u32,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    data: Vec<f32>,
+}
+
+// Transform one tile-component and code each resolution as a packet.
+fn encode_component(samples: &[f32], (x0, y0, x1, y1): (u32, u32, u32, u32), options: &Options, mct: bool) -> Vec<Vec<u8>> {
+    // Forward transform, collecting the high-pass bands from the top.
+    let mut low = samples.to_vec();
+    let (mut lx0, mut ly0, mut lx1, mut ly1) = (x0, y0, x1, y1);
+    let mut levels = Vec::new();
+    for _ in 0..options.levels {
+        let (w, h) = ((lx1 - lx0) as usize, (ly1 - ly0) as usize);
+        let mut column = vec![0.0; h];
+        for x in 0..w {
+            for y in 0..h {
+                column[y] = low[y * w + x];
+            }
+            analyze(&mut column, ly0, options.irreversible);
+            for y in 0..h {
+                low[y * w + x] = column[y];
+            }
+        }
+        for row in low.chunks_mut(w) {
+            analyze(row, lx0, options.irreversible);
+        }
+
+        let mut bands: Vec<Band> = (0..4)
+            .map(|orientation| {
+                let (xo, yo) = (orientation & 1, orientation >> 1);
+                Band {
+                    orientation,
+                    x0: (lx0 + 1 - xo) / 2,
+                    y0: (ly0 + 1 - yo) / 2,
+                    x1: (lx1 + 1 - xo) / 2,
+                    y1: (ly1 + 1 - yo) / 2,
+                    data: Vec::new(),
+                }
+            })
+            .collect();
+        for band in &mut bands {
+            let (xo, yo) = (band.orientation & 1, band.orientation >> 1);
+            for by in band.y0..band.y1 {
+                for bx in band.x0..band.x1 {
+                    let (x, y) = (2 * bx + xo - lx0, 2 * by + yo - ly0);
+                    band.data.push(low[(y * (lx1 - lx0) + x) as usize]);
+                }
+            }
+        }
+        let ll = bands.remove(0);
+        (lx0, ly0, lx1, ly1) = (ll.x0, ll.y0, ll.x1, ll.y1);
+        low = ll.data;
+        levels.push(bands);
+    }
+    levels.push(vec![Band {
+        orientation: 0,
+        x0: lx0,
+        y0: ly0,
+        x1: lx1,
+        y1: ly1,
+        data: low,
+    }]);
+    levels.reverse();
+
+    levels
+        .iter()
+        .enumerate()
+        .map(|(r, bands)| {
+            let mut header = BitWriter::default();
+            let mut body = Vec::new();
+            header.bit(1);
+            for band in bands {
+                let index = if r == 0 { 0 } else { 3 * (r as u32 - 1) + band.orientation };
+                let magnitude_bits = GUARD_BITS + exponent(index, mct, options) - 1;
+                let step = step(index, mct, options);
+                let quantized: Vec<i32> = band.data.iter().map(|c| (c / step).trunc() as i32).collect();
+                encode_band(band, &quantized, options.block_exponent, magnitude_bits, &mut header, &mut body);
+            }
+            let mut packet = header.finish();
+            packet.extend(body);
+            packet
+        })
+        .collect()
+}
+
+// Wavelet analysis of a line whose first sample is at coordinate `start`.
+fn analyze(samples: &mut [f32], start: u32, irreversible: bool) {
+    let n = samples.len();
+    if n == 1 {
+        if start % 2 == 1 {
+            samples[0] *= 2.0;
+        }
+        return;
+    }
+    let period = 2 * (n as isize - 1);
+    let mut line: Vec<f32> = (-4..n as isize + 4)
+        .map(|i| {
+            let i = i.rem_euclid(period);
+            samples[if i < n as isize { i } else { period - i } as usize]
+        })
+        .collect();
+    let odd = |i: usize| !(start as usize + i).is_multiple_of(2);
+    let lift = |line: &mut Vec<f32>, odd_samples: bool, step: &dyn Fn(f32, f32) -> f32| {
+        for i in 1..line.len() - 1 {
+            if odd(i) == odd_samples {
+                line[i] += step(line[i - 1], line[i + 1]);
+            }
+        }
+    };
+    if irreversible {
+        lift(&mut line, true, &|l, r| -1.586_134_3 * (l + r));
+        lift(&mut line, false, &|l, r| -0.052_980_12 * (l + r));
+        lift(&mut line, true, &|l, r| 0.882_911_1 * (l + r));
+        lift(&mut line, false, &|l, r| 0.443_506_87 * (l + r));
+        for (i, sample) in line.iter_mut().enumerate() {
+            *sample *= if odd(i) { 1.230_174_1 } else { 1.0 / 1.230_174_1 };
+        }
+    } else {
+        lift(&mut line, true, &|l, r| -((l + r) / 2.0).floor());
+        lift(&mut line, false, &|l, r| ((l + r + 2.0) / 4.0).floor());
+    }
+    samples.copy_from_slice(&line[4..4 + n]);
+}
+
+fn encode_band(band: &Band, coefficients: &[i32], block_exponent: u32, magnitude_bits: u32, header: &mut BitWriter, body: &mut Vec<u8>) {
+    let width = (band.x1 - band.x0) as usize;
+    if band.x0 >= band.x1 || band.y0 >= band.y1 {
+        return;
+    }
+    let first_x = band.x0 >> block_exponent;
+    let first_y = band.y0 >> block_exponent;
+    let wide = (band.x1.div_ceil(1 << block_exponent) - first_x) as usize;
+    let high = (band.y1.div_ceil(1 << block_exponent) - first_y) as usize;
+
+    let mut blocks = Vec::new();
+    for j in 0..high as u32 {
+        for i in 0..wide as u32 {
+            let bx0 = ((first_x + i) << block_exponent).max(band.x0);
+            let by0 = ((first_y + j) << block_exponent).max(band.y0);
+            let bx1 = ((first_x + i + 1) << block_exponent).min(band.x1);
+            let by1 = ((first_y + j + 1) << block_exponent).min(band.y1);
+            let coefficients: Vec<i32> = (by0..by1)
+                .flat_map(|y| (bx0..bx1).map(move |x| ((y - band.y0) as usize * width) + (x - band.x0) as usize))
+                .map(|i| coefficients[i])
+                .collect();
+            blocks.push(encode_block(&coefficients, (bx1 - bx0) as usize, band.orientation));
+        }
+    }
+
+    let mut inclusion = TagTree::new(wide, high, blocks.iter().map(|b| if b.passes > 0 { 0 } else { 1 }).collect());
+    let mut zero_planes = TagTree::new(wide, high, blocks.iter().map(|b| magnitude_bits - b.planes).collect());
+    for (i, block) in blocks.iter().enumerate() {
+        inclusion.encode(header, i, 1);
+        if block.passes == 0 {
+            continue;
+        }
+        zero_planes.encode(header, i, u32::MAX);
+        let passes = block.passes;
+        match passes {
+            1 => header.bits(0, 1),
+            2 => header.bits(0b10, 2),
+            3..=5 => header.bits(0b1100 | (passes - 3), 4),
+            6..=36 => header.bits((0b1111 << 5) | (passes - 6), 9),
+            _ => header.bits((0b1_1111_1111 << 7) | (passes - 37), 16),
+        }
+        // Lblock starts at 3 and grows until the length fits.
+        let mut length_bits = 3 + passes.ilog2();
+        while block.data.len() >= 1 << length_bits {
+            header.bit(1);
+            length_bits += 1;
+        }
+        header.bit(0);
+        header.bits(block.data.len() as u32, length_bits);
+        body.extend(&block.data);
+    }
+}
+
+struct EncodedBlock {
+    planes: u32,
+    passes: u32,
+    data: Vec<u8>,
+}
+
+fn encode_block(coefficients: &[i32], width: usize, orientation: u32) -> EncodedBlock {
+    let height = coefficients.len() / width;
+    let planes = coefficients.iter().map(|c| 32 - c.unsigned_abs().leading_zeros()).max().unwrap_or(0);
+    if planes == 0 {
+        return EncodedBlock { planes: 0, passes: 0, data: Vec::new() };
+    }
+
+    let stride = width + 2;
+    let mut block = BlockState {
+        stride,
+        significant: vec![false; stride * (height + 2)],
+        negative: vec![false; stride * (height + 2)],
+        visited: vec![false; stride * (height + 2)],
+        refined: vec![false; stride * (height + 2)],
+        orientation,
+    };
+    let magnitude = |x: usize, y: usize| coefficients[y * width + x].unsigned_abs();
+    let index = |x: usize, y: usize| (y + 1) * stride + x + 1;
+    let mut encoder = MqEncoder::new();
+
+    for plane in (0..planes).rev() {
+        let bit = |x: usize, y: usize| (magnitude(x, y) >> plane) & 1;
+        let sign = |x: usize, y: usize| (coefficients[y * width + x] < 0) as u32;
+        if plane + 1 < planes {
+            // Significance propagation.
+            for y0 in (0..height).step_by(4) {
+                for x in 0..width {
+                    for y in y0..(y0 + 4).min(height) {
+                        let i = index(x, y);
+                        if block.significant[i] || block.zero_context(i) == 0 {
+                            continue;
+                        }
+                        encoder.encode(block.zero_context(i), bit(x, y));
+                        if bit(x, y) == 1 {
+                            let (cx, flip) = block.sign_context(i);
+                            encoder.encode(cx, sign(x, y) ^ flip);
+                            block.significant[i] = true;
+                            block.negative[i] = sign(x, y) == 1;
+                        }
+                        block.visited[i] = true;
+                    }
+                }
+            }
+            // Magnitude refinement.
+            for y0 in (0..height).step_by(4) {
+                for x in 0..width {
+                    for y in y0..(y0 + 4).min(height) {
+                        let i = index(x, y);
+                        if !block.significant[i] || block.visited[i] {
+                            continue;
+                        }
+                        let cx = if block.refined[i] {
+                            16
+                        } else if block.has_significant_neighbour(i) {
+                            15
+                        } else {
+                            14
+                        };
+                        encoder.encode(cx, bit(x, y));
+                        block.refined[i] = true;
+                    }
+                }
+            }
+        }
+        // Cleanup.
+        for y0 in (0..height).step_by(4) {
+            for x in 0..width {
+                let mut start = y0;
+                let run = y0 + 4 <= height
+                    && (y0..y0 + 4).all(|y| {
+                        let i = index(x, y);
+                        !block.significant[i] && !block.visited[i] && block.zero_context(i) == 0
+                    });
+                if run {
+                    let Some(offset) = (0..4).find(|&k| bit(x, y0 + k) == 1) else {
+                        encoder.encode(17, 0);
+                        continue;
+                    };
+                    encoder.encode(17, 1);
+                    encoder.encode(18, offset as u32 >> 1);
+                    encoder.encode(18, offset as u32 & 1);
+                    let i = index(x, y0 + offset);
+                    let (cx, flip) = block.sign_context(i);
+                    encoder.encode(cx, sign(x, y0 + offset) ^ flip);
+                    block.significant[i] = true;
+                    block.negative[i] = sign(x, y0 + offset) == 1;
+                    start = y0 + offset + 1;
+                }
+                for y in start..(y0 + 4).min(height) {
+                    let i = index(x, y);
+                    if block.significant[i] || block.visited[i] {
+                        continue;
+                    }
+                    encoder.encode(block.zero_context(i), bit(x, y));
+                    if bit(x, y) == 1 {
+                        let (cx, flip) = block.sign_context(i);
+                        encoder.encode(cx, sign(x, y) ^ flip);
+                        block.significant[i] = true;
+                        block.negative[i] = sign(x, y) == 1;
+                    }
+                }
+            }
+        }
+        block.visited.fill(false);
+    }
+
+    EncodedBlock {
+        planes,
+        passes: 3 * planes - 2,
+        data: encoder.finish(),
+    }
+}
+
+struct BlockState {
+    stride: usize,
+    significant: Vec<bool>,
+    negative: Vec<bool>,
+    visited: Vec<bool>,
+    refined: Vec<bool>,
+    orientation: u32,
+}
+
+impl BlockState {
+    fn counts(&self, i: usize) -> (u32, u32, u32) {
+        let s = self.stride;
+        let sig = |i: usize| self.significant[i] as u32;
+        (
+            sig(i - 1) + sig(i + 1),
+            sig(i - s) + sig(i + s),
+            sig(i - s - 1) + sig(i - s + 1) + sig(i + s - 1) + sig(i + s + 1),
+        )
+    }
+
+    fn has_significant_neighbour(&self, i: usize) -> bool {
+        let (h, v, d) = self.counts(i);
+        h + v + d > 0
+    }
+
+    fn zero_context(&self, i: usize) -> usize {
+        let (h, v, d) = self.counts(i);
+        let (h, v) = if self.orientation == 1 { (v, h) } else { (h, v) };
+        if self.orientation == 3 {
+            let hv = h + v;
+            return match d {
+                0 => [0, 1, 2][hv.min(2) as usize],
+                1 => [3, 4, 5][hv.min(2) as usize],
+                2 => [6, 7][hv.min(1) as usize],
+                _ => 8,
+            };
+        }
+        match h {
+            0 => match v {
+                0 => [0, 1, 2][d.min(2) as usize],
+                1 => 3,
+                _ => 4,
+            },
+            1 => match (v, d) {
+                (0, 0) => 5,
+                (0, _) => 6,
+                _ => 7,
+            },
+            _ => 8,
+        }
+    }
+
+    fn sign_context(&self, i: usize) -> (usize, u32) {
+        let s = self.stride;
+        let value = |i: usize| match (self.significant[i], self.negative[i]) {
+            (false, _) => 0,
+            (true, false) => 1,
+            (true, true) => -1,
+        };
+        let h = (value(i - 1) + value(i + 1)).clamp(-1, 1);
+        let v = (value(i - s) + value(i + s)).clamp(-1, 1);
+        let cx = [[13, 12, 11], [10, 9, 10], [11, 12, 13]][(1 - h) as usize][(1 - v) as usize];
+        (cx, (h < 0 || (h == 0 && v < 0)) as u32)
+    }
+}
+
+// The MQ encoder of ITU-T T.800 Annex C.
+struct MqEncoder {
+    contexts: Contexts,
+    a: u32,
+    c: u32,
+    ct: u32,
+    // Starts with a placeholder byte that carries can run into.
+    bytes: Vec<u8>,
+}
+
+impl MqEncoder {
+    fn new() -> Self {
+        MqEncoder {
+            contexts: Contexts::new(),
+            a: 0x8000,
+            c: 0,
+            ct: 12,
+            bytes: vec![0],
+        }
+    }
+
+    fn encode(&mut self, cx: usize, bit: u32) {
+        let (qe, next_mps, next_lps, switch) = STATES[self.contexts.state[cx] as usize];
+        self.a -= qe;
+        if bit == self.contexts.mps[cx] as u32 {
+            if self.a & 0x8000 != 0 {
+                self.c += qe;
+                return;
+            }
+            if self.a < qe {
+                self.a = qe;
+            } else {
+                self.c += qe;
+            }
+            self.contexts.state[cx] = next_mps;
+        } else {
+            if self.a < qe {
+                self.c += qe;
+            } else {
+                self.a = qe;
+            }
+            if switch {
+                self.contexts.mps[cx] ^= 1;
+            }
+            self.contexts.state[cx] = next_lps;
+        }
+        while self.a & 0x8000 == 0 {
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.ct == 0 {
+                self.byte_out();
+            }
+        }
+    }
+
+    fn byte_out(&mut self) {
+        let last = self.bytes.len() - 1;
+        if self.bytes[last] == 0xFF {
+            self.bytes.push((self.c >> 20) as u8);
+            self.c &= 0xFFFFF;
+            self.ct = 7;
+        } else if self.c < 0x8000000 {
+            self.bytes.push((self.c >> 19) as u8);
+            self.c &= 0x7FFFF;
+            self.ct = 8;
+        } else {
+            self.bytes[last] += 1;
+            if self.bytes[last] == 0xFF {
+                self.c &= 0x7FFFFFF;
+                self.bytes.push((self.c >> 20) as u8);
+                self.c &= 0xFFFFF;
+                self.ct = 7;
+            } else {
+                self.bytes.push((self.c >> 19) as u8);
+                self.c &= 0x7FFFF;
+                self.ct = 8;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let temp = self.c + self.a;
+        self.c |= 0xFFFF;
+        if self.c >= temp {
+            self.c -= 0x8000;
+        }
+        self.c <<= self.ct;
+        self.byte_out();
+        self.c <<= self.ct;
+        self.byte_out();
+        if self.bytes.last() == Some(&0xFF) {
+            self.bytes.pop();
+        }
+        self.bytes.remove(0);
+        self.bytes
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    value: u8,
+    left: u8,
+    open: bool,
+}
+
+impl BitWriter {
+    fn bit(&mut self, bit: u32) {
+        if self.left == 0 {
+            if self.open {
+                self.bytes.push(self.value);
+            }
+            self.open = true;
+            self.value = 0;
+            self.left = if self.bytes.last() == Some(&0xFF) { 7 } else { 8 };
+        }
+        self.left -= 1;
+        self.value |= (bit as u8) << self.left;
+    }
+
+    fn bits(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.bit((value >> i) & 1);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.open {
+            self.bytes.push(self.value);
+        }
+        if self.bytes.last() == Some(&0xFF) {
+            self.bytes.push(0);
+        }
+        self.bytes
+    }
+}
+
+struct TagTreeLevel {
+    width: usize,
+    values: Vec<u32>,
+    lows: Vec<u32>,
+    known: Vec<bool>,
+}
+
+struct TagTree {
+    // Leaves first, then each coarser level.
+    levels: Vec<TagTreeLevel>,
+}
+
+impl TagTree {
+    fn new(width: usize, height: usize, leaves: Vec<u32>) -> Self {
+        let mut levels = vec![(width, leaves)];
+        let (mut w, mut h) = (width, height);
+        while w > 1 || h > 1 {
+            let (nw, nh) = (w.div_ceil(2), h.div_ceil(2));
+            let below = &levels.last().unwrap().1;
+            let values = (0..nh)
+                .flat_map(|y| (0..nw).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let mut min = u32::MAX;
+                    for (cx, cy) in [(2 * x, 2 * y), (2 * x + 1, 2 * y), (2 * x, 2 * y + 1), (2 * x + 1, 2 * y + 1)] {
+                        if cx < w && cy < h {
+                            min = min.min(below[cy * w + cx]);
+                        }
+                    }
+                    min
+                })
+                .collect();
+            levels.push((nw, values));
+            (w, h) = (nw, nh);
+        }
+        TagTree {
+            levels: levels
+                .into_iter()
+                .map(|(width, values)| TagTreeLevel {
+                    width,
+                    lows: vec![0; values.len()],
+                    known: vec![false; values.len()],
+                    values,
+                })
+                .collect(),
+        }
+    }
+
+    fn encode(&mut self, writer: &mut BitWriter, leaf: usize, threshold: u32) {
+        let width = self.levels[0].width;
+        let (x, y) = (leaf % width, leaf / width);
+        let mut low = 0;
+        for (shift, level) in self.levels.iter_mut().enumerate().rev() {
+            let node = (y >> shift) * level.width + (x >> shift);
+            low = low.max(level.lows[node]);
+            while low < threshold {
+                if low >= level.values[node] {
+                    if !level.known[node] {
+                        writer.bit(1);
+                        level.known[node] = true;
+                    }
+                    break;
+                }
+                writer.bit(0);
+                low += 1;
+            }
+            level.lows[node] = low;
+        }
+    }
+}
@@ -0,0 +1,526 @@
+// Code-block decoding: the MQ arithmetic decoder and the three coding
+// passes that rebuild a block's coefficients one bit plane at a time.
+
+// Code-block style flags from COD/COC.
+pub(super) const BYPASS: u8 = 0x01;
+pub(super) const RESET: u8 = 0x02;
+pub(super) const TERMINATE_ALL: u8 = 0x04;
+pub(super) const VERTICALLY_CAUSAL: u8 = 0x08;
+pub(super) const SEGMENTATION_SYMBOLS: u8 = 0x20;
+
+// Coefficients are kept in half steps, so a block can use at most this
+// many bit planes before its values overflow.
+pub(super) const MAX_BIT_PLANES: u32 = 30;
+
+const CONTEXTS: usize = 19;
+const RUN_CONTEXT: usize = 17;
+const UNIFORM_CONTEXT: usize = 18;
+
+// The MQ coder's probability states: (Qe, next state after an MPS, next
+// state after an LPS, whether an LPS swaps the MPS).
+pub(super) const STATES: [(u32, u8, u8, bool); 47] = [
+    (0x5601, 1, 1, true),
+    (0x3401, 2, 6, false),
+    (0x1801, 3, 9, false),
+    (0x0AC1, 4, 12, false),
+    (0x0521, 5, 29, false),
+    (0x0221, 38, 33, false),
+    (0x5601, 7, 6, true),
+    (0x5401, 8, 14, false),
+    (0x4801, 9, 14, false),
+    (0x3801, 10, 14, false),
+    (0x3001, 11, 17, false),
+    (0x2401, 12, 18, false),
+    (0x1C01, 13, 20, false),
+    (0x1601, 29, 21, false),
+    (0x5601, 15, 14, true),
+    (0x5401, 16, 14, false),
+    (0x5101, 17, 15, false),
+    (0x4801, 18, 16, false),
+    (0x3801, 19, 17, false),
+    (0x3401, 20, 18, false),
+    (0x3001, 21, 19, false),
+    (0x2801, 22, 19, false),
+    (0x2401, 23, 20, false),
+    (0x2201, 24, 21, false),
+    (0x1C01, 25, 22, false),
+    (0x1801, 26, 23, false),
+    (0x1601, 27, 24, false),
+    (0x1401, 28, 25, false),
+    (0x1201, 29, 26, false),
+    (0x1101, 30, 27, false),
+    (0x0AC1, 31, 28, false),
+    (0x09C1, 32, 29, false),
+    (0x08A1, 33, 30, false),
+    (0x0521, 34, 31, false),
+    (0x0441, 35, 32, false),
+    (0x02A1, 36, 33, false),
+    (0x0221, 37, 34, false),
+    (0x0141, 38, 35, false),
+    (0x0111, 39, 36, false),
+    (0x0085, 40, 37, false),
+    (0x0049, 41, 38, false),
+    (0x0025, 42, 39, false),
+    (0x0015, 43, 40, false),
+    (0x0009, 44, 41, false),
+    (0x0005, 45, 42, false),
+    (0x0001, 45, 43, false),
+    (0x5601, 46, 46, false),
+];
+
+// The probability state and most probable symbol of every context.
+#[derive(Clone)]
+pub(super) struct Contexts {
+    pub state: [u8; CONTEXTS],
+    pub mps: [u8; CONTEXTS],
+}
+
+impl Contexts {
+    pub fn new() -> Self {
+        let mut state = [0; CONTEXTS];
+        state[0] = 4;
+        state[RUN_CONTEXT] = 3;
+        state[UNIFORM_CONTEXT] = 46;
+        Contexts {
+            state,
+            mps: [0; CONTEXTS],
+        }
+    }
+}
+
+struct MqDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    a: u32,
+    c: u32,
+    ct: u32,
+}
+
+impl<'a> MqDecoder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        let mut decoder = MqDecoder {
+            data,
+            pos: 0,
+            a: 0x8000,
+            c: 0,
+            ct: 0,
+        };
+        decoder.c = (decoder.byte(0) as u32) << 16;
+        decoder.byte_in();
+        decoder.c <<= 7;
+        decoder.ct -= 7;
+        decoder
+    }
+
+    // Reading past the end of a segment yields 0xFF, as the standard asks.
+    fn byte(&self, pos: usize) -> u8 {
+        self.data.get(pos).copied().unwrap_or(0xFF)
+    }
+
+    fn byte_in(&mut self) {
+        let next = self.byte(self.pos + 1) as u32;
+        if self.byte(self.pos) == 0xFF {
+            if next > 0x8F {
+                // A marker: feed ones without moving on.
+                self.c = self.c.wrapping_add(0xFF00);
+                self.ct = 8;
+            } else {
+                self.pos += 1;
+                self.c = self.c.wrapping_add(next << 9);
+                self.ct = 7;
+            }
+        } else {
+            self.pos += 1;
+            self.c = self.c.wrapping_add(next << 8);
+            self.ct = 8;
+        }
+    }
+
+    fn decode(&mut self, contexts: &mut Contexts, cx: usize) -> u32 {
+        let (qe, next_mps, next_lps, switch) = STATES[contexts.state[cx] as usize];
+        let mps = contexts.mps[cx] as u32;
+        let lps = |contexts: &mut Contexts| {
+            if switch {
+                contexts.mps[cx] ^= 1;
+            }
+            contexts.state[cx] = next_lps;
+            1 - mps
+        };
+
+        self.a -= qe;
+        if (self.c >> 16) < qe {
+            let bit = if self.a < qe {
+                contexts.state[cx] = next_mps;
+                mps
+            } else {
+                lps(contexts)
+            };
+            self.a = qe;
+            self.renormalize();
+            bit
+        } else {
+            self.c -= qe << 16;
+            if self.a & 0x8000 != 0 {
+                return mps;
+            }
+            let bit = if self.a < qe {
+                lps(contexts)
+            } else {
+                contexts.state[cx] = next_mps;
+                mps
+            };
+            self.renormalize();
+            bit
+        }
+    }
+
+    fn renormalize(&mut self) {
+        loop {
+            if self.ct == 0 {
+                self.byte_in();
+            }
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.a & 0x8000 != 0 {
+                break;
+            }
+        }
+    }
+}
+
+// The raw bits of a bypassed pass, with a zero bit stuffed after 0xFF.
+struct RawDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    c: u32,
+    ct: u32,
+}
+
+impl RawDecoder<'_> {
+    fn bit(&mut self) -> u32 {
+        if self.ct == 0 {
+            let byte = self.data.get(self.pos).copied().unwrap_or(0xFF) as u32;
+            if self.c == 0xFF {
+                if byte > 0x8F {
+                    self.ct = 8;
+                } else {
+                    self.c = byte;
+                    self.pos += 1;
+                    self.ct = 7;
+                }
+            } else {
+                self.c = byte;
+                self.pos += 1;
+                self.ct = 8;
+            }
+        }
+        self.ct -= 1;
+        (self.c >> self.ct) & 1
+    }
+}
+
+enum Coder<'a> {
+    Mq(MqDecoder<'a>),
+    Raw(RawDecoder<'a>),
+}
+
+impl Coder<'_> {
+    fn bit(&mut self, contexts: &mut Contexts, cx: usize) -> u32 {
+        match self {
+            Coder::Mq(decoder) => decoder.decode(contexts, cx),
+            Coder::Raw(decoder) => decoder.bit(),
+        }
+    }
+
+    // Raw passes store the sign as is; coded ones relative to a prediction.
+    fn sign(&mut self, contexts: &mut Contexts, (cx, flip): (usize, u32)) -> u32 {
+        match self {
+            Coder::Mq(decoder) => decoder.decode(contexts, cx) ^ flip,
+            Coder::Raw(decoder) => decoder.bit(),
+        }
+    }
+}
+
+pub(super) struct Segment {
+    pub data: Vec<u8>,
+    pub passes: u32,
+}
+
+const SIGNIFICANT: u8 = 1;
+const NEGATIVE: u8 = 2;
+// Coded by the significance pass of the current bit plane.
+const VISITED: u8 = 4;
+const REFINED: u8 = 8;
+
+// Whether pass `index` of a bypassed block skips the arithmetic coder.
+// After the first ten passes only the cleanup passes stay coded.
+pub(super) fn is_raw_pass(style: u8, index: u32) -> bool {
+    style & BYPASS != 0 && index >= 10 && !index.is_multiple_of(3)
+}
+
+// A code-block's state, with a one sample border so that neighbours never
+// need bounds checks.
+struct Block {
+    width: usize,
+    height: usize,
+    stride: usize,
+    flags: Vec<u8>,
+    magnitudes: Vec<u32>,
+    orientation: u8,
+    causal: bool,
+}
+
+impl Block {
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y + 1) * self.stride + x + 1
+    }
+
+    fn significant(&self, i: usize) -> u32 {
+        (self.flags[i] & SIGNIFICANT) as u32
+    }
+
+    // +1, -1 or 0 for a positive, negative or insignificant neighbour.
+    fn contribution(&self, i: usize) -> i32 {
+        match self.flags[i] & (SIGNIFICANT | NEGATIVE) {
+            SIGNIFICANT => 1,
+            0 => 0,
+            _ => -1,
+        }
+    }
+
+    // In vertically causal mode the stripe below is treated as unknown.
+    fn below_visible(&self, y: usize) -> bool {
+        !(self.causal && y % 4 == 3)
+    }
+
+    fn neighbour_counts(&self, i: usize, y: usize) -> (u32, u32, u32) {
+        let s = self.stride;
+        let below = self.below_visible(y);
+        let horizontal = self.significant(i - 1) + self.significant(i + 1);
+        let mut vertical = self.significant(i - s);
+        let mut diagonal = self.significant(i - s - 1) + self.significant(i - s + 1);
+        if below {
+            vertical += self.significant(i + s);
+            diagonal += self.significant(i + s - 1) + self.significant(i + s + 1);
+        }
+        (horizontal, vertical, diagonal)
+    }
+
+    fn zero_coding_context(&self, i: usize, y: usize) -> usize {
+        let (h, v, d) = self.neighbour_counts(i, y);
+        match self.orientation {
+            // Horizontally high-pass bands weigh vertical neighbours first.
+            1 => context_from_counts(v, h, d),
+            3 => match (d, h + v) {
+                (3.., _) => 8,
+                (2, 1..) => 7,
+                (2, _) => 6,
+                (1, 2..) => 5,
+                (1, 1) => 4,
+                (1, _) => 3,
+                (_, 2..) => 2,
+                (_, 1) => 1,
+                _ => 0,
+            },
+            _ => context_from_counts(h, v, d),
+        }
+    }
+
+    fn sign_context(&self, i: usize, y: usize) -> (usize, u32) {
+        let s = self.stride;
+        let below = if self.below_visible(y) { self.contribution(i + s) } else { 0 };
+        let h = (self.contribution(i - 1) + self.contribution(i + 1)).clamp(-1, 1);
+        let v = (self.contribution(i - s) + below).clamp(-1, 1);
+        match (h, v) {
+            (1, 1) => (13, 0),
+            (1, 0) => (12, 0),
+            (1, _) => (11, 0),
+            (0, 1) => (10, 0),
+            (0, 0) => (9, 0),
+            (0, _) => (10, 1),
+            (_, 1) => (11, 1),
+            (_, 0) => (12, 1),
+            _ => (13, 1),
+        }
+    }
+
+    fn make_significant(&mut self, i: usize, negative: bool, plane: u32) {
+        self.flags[i] |= SIGNIFICANT | if negative { NEGATIVE } else { 0 };
+        // The decoded bit plus half of the planes below it.
+        self.magnitudes[i] = 3 << plane;
+    }
+
+    fn significance_pass(&mut self, coder: &mut Coder, contexts: &mut Contexts, plane: u32) {
+        for y0 in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                for y in y0..(y0 + 4).min(self.height) {
+                    let i = self.index(x, y);
+                    if self.flags[i] & SIGNIFICANT != 0 {
+                        continue;
+                    }
+                    let cx = self.zero_coding_context(i, y);
+                    if cx == 0 {
+                        continue;
+                    }
+                    if coder.bit(contexts, cx) == 1 {
+                        let negative = coder.sign(contexts, self.sign_context(i, y)) == 1;
+                        self.make_significant(i, negative, plane);
+                    }
+                    self.flags[i] |= VISITED;
+                }
+            }
+        }
+    }
+
+    fn refinement_pass(&mut self, coder: &mut Coder, contexts: &mut Contexts, plane: u32) {
+        for y0 in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                for y in y0..(y0 + 4).min(self.height) {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (SIGNIFICANT | VISITED) != SIGNIFICANT {
+                        continue;
+                    }
+                    let cx = if self.flags[i] & REFINED != 0 {
+                        16
+                    } else {
+                        let (h, v, d) = self.neighbour_counts(i, y);
+                        if h + v + d > 0 { 15 } else { 14 }
+                    };
+                    let bit = coder.bit(contexts, cx);
+                    // Replace the half step guessed below the last plane.
+                    self.magnitudes[i] = (self.magnitudes[i] & !(1 << (plane + 1))) | (bit << (plane + 1)) | (1 << plane);
+                    self.flags[i] |= REFINED;
+                }
+            }
+        }
+    }
+
+    fn cleanup_pass(&mut self, coder: &mut Coder, contexts: &mut Contexts, plane: u32) {
+        for y0 in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                let mut start = y0;
+                // A full column of samples with nothing significant around
+                // them is coded as a single run.
+                let run = y0 + 4 <= self.height
+                    && (y0..y0 + 4).all(|y| {
+                        let i = self.index(x, y);
+                        self.flags[i] & (SIGNIFICANT | VISITED) == 0 && self.zero_coding_context(i, y) == 0
+                    });
+                if run {
+                    if coder.bit(contexts, RUN_CONTEXT) == 0 {
+                        continue;
+                    }
+                    let offset = (coder.bit(contexts, UNIFORM_CONTEXT) << 1) | coder.bit(contexts, UNIFORM_CONTEXT);
+                    let y = y0 + offset as usize;
+                    let i = self.index(x, y);
+                    let negative = coder.sign(contexts, self.sign_context(i, y)) == 1;
+                    self.make_significant(i, negative, plane);
+                    start = y + 1;
+                }
+
+                for y in start..(y0 + 4).min(self.height) {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (SIGNIFICANT | VISITED) != 0 {
+                        continue;
+                    }
+                    let cx = self.zero_coding_context(i, y);
+                    if coder.bit(contexts, cx) == 1 {
+                        let negative = coder.sign(contexts, self.sign_context(i, y)) == 1;
+                        self.make_significant(i, negative, plane);
+                    }
+                }
+            }
+        }
+        for flags in &mut self.flags {
+            *flags &= !VISITED;
+        }
+    }
+}
+
+fn context_from_counts(h: u32, v: u32, d: u32) -> usize {
+    match (h, v, d) {
+        (2.., _, _) => 8,
+        (1, 1.., _) => 7,
+        (1, 0, 1..) => 6,
+        (1, 0, 0) => 5,
+        (0, 2.., _) => 4,
+        (0, 1, _) => 3,
+        (0, 0, 2..) => 2,
+        (0, 0, 1) => 1,
+        _ => 0,
+    }
+}
+
+// Decode a code-block of `planes` magnitude bit planes. Coefficients are
+// returned in half steps: bit 0 is half of the lowest decoded plane.
+pub(super) fn decode_block(
+    width: usize,
+    height: usize,
+    orientation: u8,
+    planes: u32,
+    style: u8,
+    segments: &[Segment],
+) -> Vec<i32> {
+    let stride = width + 2;
+    let mut block = Block {
+        width,
+        height,
+        stride,
+        flags: vec![0; stride * (height + 2)],
+        magnitudes: vec![0; stride * (height + 2)],
+        orientation,
+        causal: style & VERTICALLY_CAUSAL != 0,
+    };
+
+    let mut contexts = Contexts::new();
+    let mut pass = 0;
+    'segments: for segment in segments {
+        let mut coder = if is_raw_pass(style, pass) {
+            Coder::Raw(RawDecoder {
+                data: &segment.data,
+                pos: 0,
+                c: 0,
+                ct: 0,
+            })
+        } else {
+            Coder::Mq(MqDecoder::new(&segment.data))
+        };
+
+        for _ in 0..segment.passes {
+            // The first pass is a cleanup of the top plane, then each lower
+            // plane gets a significance, refinement and cleanup pass.
+            let below_top = pass.div_ceil(3);
+            if below_top >= planes {
+                break 'segments;
+            }
+            let plane = planes - 1 - below_top;
+            match pass % 3 {
+                1 => block.significance_pass(&mut coder, &mut contexts, plane),
+                2 => block.refinement_pass(&mut coder, &mut contexts, plane),
+                _ => {
+                    block.cleanup_pass(&mut coder, &mut contexts, plane);
+                    if style & SEGMENTATION_SYMBOLS != 0 {
+                        for _ in 0..4 {
+                            coder.bit(&mut contexts, UNIFORM_CONTEXT);
+                        }
+                    }
+                }
+            }
+            if style & RESET != 0 {
+                contexts = Contexts::new();
+            }
+            pass += 1;
+        }
+    }
+
+    let mut coefficients = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let i = block.index(x, y);
+            let magnitude = block.magnitudes[i] as i32;
+            coefficients.push(if block.flags[i] & NEGATIVE != 0 { -magnitude } else { magnitude });
+        }
+    }
+    coefficients
+}
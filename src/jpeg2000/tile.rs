@@ -0,0 +1,692 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::thread;
+
+use super::codestream::{Component, Progression, Size, Tile, TileComponentCoding};
+use super::packets::{BitReader, TagTree};
+use super::tier1::{self, Segment, MAX_BIT_PLANES, TERMINATE_ALL};
+use super::wavelet::{self, Subband};
+use super::Plane;
+
+// Guards the allocations a damaged header could otherwise ask for.
+const MAX_PRECINCTS: usize = 1 << 20;
+const MAX_PACKETS: usize = 1 << 22;
+
+struct CodeBlock {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    included: bool,
+    length_bits: u32,
+    zero_planes: u32,
+    segments: Vec<Segment>,
+}
+
+// The code-blocks of one band that fall in one precinct.
+struct PrecinctBand {
+    width: usize,
+    blocks: Vec<usize>,
+    inclusion: TagTree,
+    zero_planes: TagTree,
+}
+
+struct Band {
+    // 0: LL, 1: HL, 2: LH, 3: HH.
+    orientation: u8,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    blocks: Vec<CodeBlock>,
+    magnitude_bits: u32,
+    step: f32,
+}
+
+struct Resolution {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    precinct_width: u8,
+    precinct_height: u8,
+    precincts_wide: u32,
+    precincts_high: u32,
+    bands: Vec<Band>,
+    // The bands of every precinct, in raster order.
+    precincts: Vec<Vec<PrecinctBand>>,
+}
+
+struct TileComponent<'a> {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    info: &'a Component,
+    coding: &'a TileComponentCoding,
+    resolutions: Vec<Resolution>,
+}
+
+fn ceil_shift(value: u32, shift: u32) -> u32 {
+    ((value as u64 + (1u64 << shift) - 1) >> shift) as u32
+}
+
+// Decode one tile into the component planes.
+pub(super) fn decode_tile(size: &Size, tile: &Tile, planes: &mut [Plane]) -> Result<()> {
+    let (tx0, ty0, tx1, ty1) = size.tile_rect(tile.index);
+    let mut components = Vec::with_capacity(size.components.len());
+    let mut precincts = 0;
+    for (info, coding) in size.components.iter().zip(&tile.coding.components) {
+        let component = TileComponent::new(info, coding, (tx0, ty0, tx1, ty1))?;
+        precincts += component.resolutions.iter().map(|r| r.precincts.len()).sum::<usize>();
+        if precincts > MAX_PRECINCTS {
+            return Err(anyhow::anyhow!("JPEG 2000 tile has too many precincts"));
+        }
+        components.push(component);
+    }
+
+    read_packets(tile, &mut components, (tx0, ty0, tx1, ty1))?;
+
+    // Components are independent until the color transform.
+    let mut samples: Vec<Vec<f32>> = thread::scope(|scope| {
+        let handles: Vec<_> = components.iter().map(|component| scope.spawn(|| component.reconstruct())).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))).collect()
+    });
+    if tile.coding.mct && samples.len() >= 3 {
+        let same_size = components[1..3]
+            .iter()
+            .all(|c| (c.x0, c.y0, c.x1, c.y1) == (components[0].x0, components[0].y0, components[0].x1, components[0].y1));
+        if !same_size {
+            return Err(anyhow::anyhow!("JPEG 2000 color transform needs same-sized components"));
+        }
+        inverse_color_transform(&mut samples, components[0].coding.style.reversible);
+    }
+
+    for ((component, samples), plane) in components.iter().zip(samples).zip(planes) {
+        component.store(&samples, plane);
+    }
+    Ok(())
+}
+
+impl<'a> TileComponent<'a> {
+    fn new(info: &'a Component, coding: &'a TileComponentCoding, (tx0, ty0, tx1, ty1): (u32, u32, u32, u32)) -> Result<Self> {
+        let x0 = tx0.div_ceil(info.dx);
+        let y0 = ty0.div_ceil(info.dy);
+        let x1 = tx1.div_ceil(info.dx);
+        let y1 = ty1.div_ceil(info.dy);
+        let style = &coding.style;
+        let levels = style.levels as u32;
+
+        let mut resolutions = Vec::with_capacity(levels as usize + 1);
+        for r in 0..=levels {
+            let shift = levels - r;
+            let (rx0, ry0, rx1, ry1) = (ceil_shift(x0, shift), ceil_shift(y0, shift), ceil_shift(x1, shift), ceil_shift(y1, shift));
+            let (ppx, ppy) = style.precincts[r as usize];
+            let (precincts_wide, precincts_high) = if rx1 > rx0 && ry1 > ry0 {
+                (
+                    ceil_shift(rx1, ppx as u32) - (rx0 >> ppx),
+                    ceil_shift(ry1, ppy as u32) - (ry0 >> ppy),
+                )
+            } else {
+                (0, 0)
+            };
+            if precincts_wide as usize * precincts_high as usize > MAX_PRECINCTS {
+                return Err(anyhow::anyhow!("JPEG 2000 tile has too many precincts"));
+            }
+
+            // Below the lowest resolution, bands are half the resolution's
+            // size, and so are their precincts.
+            let (band_ppx, band_ppy) = if r == 0 { (ppx, ppy) } else { (ppx - 1, ppy - 1) };
+            let block_width = style.block_width.min(band_ppx) as u32;
+            let block_height = style.block_height.min(band_ppy) as u32;
+
+            let orientations: &[u8] = if r == 0 { &[0] } else { &[1, 2, 3] };
+            let mut bands = Vec::new();
+            for &orientation in orientations {
+                let (xo, yo) = ((orientation & 1) as u32, (orientation >> 1) as u32);
+                let (bx0, by0, bx1, by1) = if r == 0 {
+                    (rx0, ry0, rx1, ry1)
+                } else {
+                    ((rx0 + 1 - xo) / 2, (ry0 + 1 - yo) / 2, (rx1 + 1 - xo) / 2, (ry1 + 1 - yo) / 2)
+                };
+                let (exponent, mantissa) = coding
+                    .quantization
+                    .step(r as u8, orientation)
+                    .ok_or_else(|| anyhow::anyhow!("JPEG 2000 quantization is missing a band"))?;
+                // Each high-pass filter can double the range of a band.
+                let gain = [0, 1, 1, 2][orientation as usize];
+                let step = if style.reversible {
+                    1.0
+                } else {
+                    2f32.powi(info.depth as i32 + gain - exponent as i32) * (1.0 + mantissa as f32 / 2048.0)
+                };
+                bands.push(Band {
+                    orientation,
+                    x0: bx0,
+                    y0: by0,
+                    x1: bx1,
+                    y1: by1,
+                    blocks: Vec::new(),
+                    magnitude_bits: (coding.quantization.guard_bits as u32 + exponent as u32).saturating_sub(1),
+                    step,
+                });
+            }
+
+            let mut precincts = Vec::with_capacity((precincts_wide * precincts_high) as usize);
+            for py in 0..precincts_high {
+                for px in 0..precincts_wide {
+                    let origin_x = ((rx0 >> ppx) + px) as u64;
+                    let origin_y = ((ry0 >> ppy) + py) as u64;
+                    let precinct = bands
+                        .iter_mut()
+                        .map(|band| {
+                            let x0 = (origin_x << band_ppx).max(band.x0 as u64);
+                            let y0 = (origin_y << band_ppy).max(band.y0 as u64);
+                            let x1 = ((origin_x + 1) << band_ppx).min(band.x1 as u64);
+                            let y1 = ((origin_y + 1) << band_ppy).min(band.y1 as u64);
+                            band.add_precinct((x0 as u32, y0 as u32, x1 as u32, y1 as u32), block_width, block_height)
+                        })
+                        .collect();
+                    precincts.push(precinct);
+                }
+            }
+
+            resolutions.push(Resolution {
+                x0: rx0,
+                y0: ry0,
+                x1: rx1,
+                y1: ry1,
+                precinct_width: ppx,
+                precinct_height: ppy,
+                precincts_wide,
+                precincts_high,
+                bands,
+                precincts,
+            });
+        }
+
+        Ok(TileComponent {
+            x0,
+            y0,
+            x1,
+            y1,
+            info,
+            coding,
+            resolutions,
+        })
+    }
+
+    // Decode every code-block and undo the wavelet transform.
+    fn reconstruct(&self) -> Vec<f32> {
+        let reversible = self.coding.style.reversible;
+        let bands: Vec<Vec<Vec<f32>>> = self
+            .resolutions
+            .iter()
+            .map(|resolution| resolution.bands.iter().map(|band| band.coefficients(self.coding)).collect())
+            .collect();
+
+        let mut samples = bands[0][0].clone();
+        for (pair, bands) in self.resolutions.windows(2).zip(&bands[1..]) {
+            let (below, resolution) = (&pair[0], &pair[1]);
+            let low = Subband {
+                x0: below.x0,
+                y0: below.y0,
+                x1: below.x1,
+                y1: below.y1,
+                data: &samples,
+            };
+            let high: Vec<Subband> = resolution
+                .bands
+                .iter()
+                .zip(bands)
+                .map(|(band, data)| Subband {
+                    x0: band.x0,
+                    y0: band.y0,
+                    x1: band.x1,
+                    y1: band.y1,
+                    data,
+                })
+                .collect();
+            samples = wavelet::synthesize(
+                &low,
+                [&high[0], &high[1], &high[2]],
+                (resolution.x0, resolution.y0, resolution.x1, resolution.y1),
+                reversible,
+            );
+        }
+        samples
+    }
+
+    // Undo the DC level shift and write the samples into the image plane.
+    fn store(&self, samples: &[f32], plane: &mut Plane) {
+        let width = (self.x1 - self.x0) as usize;
+        let depth = self.info.depth as u32;
+        let offset = (1u64 << (depth - 1)) as f32;
+        let max = ((1u64 << depth) - 1) as f32;
+        // Samples deeper than 16 bits keep only their top 16.
+        let shift = depth.saturating_sub(16);
+        for (row, line) in samples.chunks(width.max(1)).enumerate() {
+            let y = self.y0 as usize + row - plane.y0 as usize;
+            let start = y * plane.width + (self.x0 - plane.x0) as usize;
+            for (sample, value) in plane.samples[start..start + line.len()].iter_mut().zip(line) {
+                *sample = (((value + offset).round().clamp(0.0, max)) as u32 >> shift) as u16;
+            }
+        }
+    }
+}
+
+impl Band {
+    fn add_precinct(&mut self, (x0, y0, x1, y1): (u32, u32, u32, u32), block_width: u32, block_height: u32) -> PrecinctBand {
+        if x0 >= x1 || y0 >= y1 {
+            return PrecinctBand {
+                width: 0,
+                blocks: Vec::new(),
+                inclusion: TagTree::new(0, 0),
+                zero_planes: TagTree::new(0, 0),
+            };
+        }
+        let first_x = x0 >> block_width;
+        let first_y = y0 >> block_height;
+        let wide = (ceil_shift(x1, block_width) - first_x) as usize;
+        let high = (ceil_shift(y1, block_height) - first_y) as usize;
+        let mut blocks = Vec::with_capacity(wide * high);
+        for j in 0..high as u32 {
+            for i in 0..wide as u32 {
+                blocks.push(self.blocks.len());
+                self.blocks.push(CodeBlock {
+                    x0: ((first_x + i) << block_width).max(x0),
+                    y0: ((first_y + j) << block_height).max(y0),
+                    x1: ((first_x + i + 1) << block_width).min(x1),
+                    y1: ((first_y + j + 1) << block_height).min(y1),
+                    included: false,
+                    length_bits: 3,
+                    zero_planes: 0,
+                    segments: Vec::new(),
+                });
+            }
+        }
+        PrecinctBand {
+            width: wide,
+            blocks,
+            inclusion: TagTree::new(wide, high),
+            zero_planes: TagTree::new(wide, high),
+        }
+    }
+
+    // The dequantized coefficients of the band.
+    fn coefficients(&self, coding: &TileComponentCoding) -> Vec<f32> {
+        let width = (self.x1 - self.x0) as usize;
+        let height = (self.y1 - self.y0) as usize;
+        let mut coefficients = vec![0.0; width * height];
+        let roi_shift = coding.roi_shift as u32;
+        let style = &coding.style;
+
+        for block in &self.blocks {
+            let planes = (self.magnitude_bits + roi_shift).saturating_sub(block.zero_planes);
+            if block.segments.is_empty() || planes == 0 || planes > MAX_BIT_PLANES {
+                continue;
+            }
+            let block_width = (block.x1 - block.x0) as usize;
+            let decoded = tier1::decode_block(
+                block_width,
+                (block.y1 - block.y0) as usize,
+                self.orientation,
+                planes,
+                style.block_style,
+                &block.segments,
+            );
+            for (row, values) in decoded.chunks(block_width).enumerate() {
+                let start = (block.y0 - self.y0) as usize * width + row * width + (block.x0 - self.x0) as usize;
+                for (coefficient, &value) in coefficients[start..start + block_width].iter_mut().zip(values) {
+                    let mut magnitude = value.unsigned_abs();
+                    // Region of interest samples were scaled above the rest.
+                    if roi_shift > 0 && magnitude >= 1 << (roi_shift + 1) {
+                        magnitude >>= roi_shift;
+                    }
+                    // Values are in half steps; reversible ones drop the half.
+                    let magnitude = if style.reversible {
+                        (magnitude >> 1) as f32
+                    } else {
+                        magnitude as f32 * 0.5 * self.step
+                    };
+                    *coefficient = if value < 0 { -magnitude } else { magnitude };
+                }
+            }
+        }
+        coefficients
+    }
+}
+
+fn inverse_color_transform(samples: &mut [Vec<f32>], reversible: bool) {
+    let [y, cb, cr, ..] = samples else {
+        return;
+    };
+    for ((y, cb), cr) in y.iter_mut().zip(cb.iter_mut()).zip(cr.iter_mut()) {
+        let (r, g, b) = if reversible {
+            let g = *y - ((*cb + *cr) / 4.0).floor();
+            (*cr + g, g, *cb + g)
+        } else {
+            (
+                *y + 1.402 * *cr,
+                *y - 0.344_136 * *cb - 0.714_136 * *cr,
+                *y + 1.772 * *cb,
+            )
+        };
+        (*y, *cb, *cr) = (r, g, b);
+    }
+}
+
+// Where the next packet's header and body come from. Packed headers live
+// apart from the bodies; otherwise each header precedes its body.
+struct PacketSource<'a> {
+    data: &'a [u8],
+    pos: usize,
+    headers: Option<(&'a [u8], usize)>,
+    sop: bool,
+    eph: bool,
+}
+
+impl PacketSource<'_> {
+    fn exhausted(&self) -> bool {
+        match self.headers {
+            Some((headers, pos)) => pos >= headers.len(),
+            None => self.pos >= self.data.len(),
+        }
+    }
+}
+
+// Read the tile's packets in its progression order, filling in the data of
+// every code-block. A truncated tile keeps whatever arrived.
+fn read_packets(tile: &Tile, components: &mut [TileComponent], rect: (u32, u32, u32, u32)) -> Result<()> {
+    let coding = &tile.coding;
+    let mut source = PacketSource {
+        data: &tile.data,
+        pos: 0,
+        headers: tile.packed_headers.as_deref().map(|headers| (headers, 0)),
+        sop: coding.sop,
+        eph: coding.eph,
+    };
+
+    // Progression changes can revisit packets that were already read.
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    let revisits = !coding.changes.is_empty();
+    let full = (coding.layers, 0, u8::MAX, 0, components.len() as u16, coding.progression);
+    let changes = coding
+        .changes
+        .iter()
+        .map(|c| (c.layer_end, c.resolution_start, c.resolution_end, c.component_start, c.component_end, c.progression));
+    for (layer_end, resolution_start, resolution_end, component_start, component_end, progression) in changes.chain([full]) {
+        let bounds = Bounds {
+            layers: layer_end.min(coding.layers),
+            resolutions: resolution_start..resolution_end,
+            components: component_start as usize..(component_end as usize).min(components.len()),
+        };
+        packet_order(components, progression, &bounds, rect, &mut |packet| {
+            if !revisits || seen.insert(packet) {
+                order.push(packet);
+            }
+            order.len() < MAX_PACKETS
+        });
+    }
+
+    for (layer, r, c, p) in order {
+        if source.exhausted() {
+            break;
+        }
+        let component = &mut components[c];
+        let style = component.coding.style.block_style;
+        let resolution = &mut component.resolutions[r as usize];
+        if read_packet(&mut source, layer, resolution, p as usize, style).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn read_packet(source: &mut PacketSource, layer: u16, resolution: &mut Resolution, precinct: usize, style: u8) -> Result<()> {
+    if source.sop && source.data.get(source.pos..).is_some_and(|data| data.starts_with(&[0xFF, 0x91])) {
+        source.pos += 6;
+    }
+    let (header_data, header_pos) = source.headers.unwrap_or((source.data, source.pos));
+    let mut reader = BitReader::new(header_data, header_pos);
+
+    // (band, block, segment, length) of each piece of body data.
+    let mut pieces = Vec::new();
+    if reader.bit()? == 1 {
+        for (band_index, band) in resolution.bands.iter_mut().enumerate() {
+            let precinct_band = &mut resolution.precincts[precinct][band_index];
+            for (i, &block_index) in precinct_band.blocks.iter().enumerate() {
+                let (x, y) = (i % precinct_band.width, i / precinct_band.width);
+                let block = &mut band.blocks[block_index];
+                let first = !block.included;
+                let included = if first {
+                    precinct_band.inclusion.decode(&mut reader, x, y, layer as u32 + 1)?
+                } else {
+                    reader.bit()? == 1
+                };
+                if !included {
+                    continue;
+                }
+                if first {
+                    let mut threshold = 1;
+                    while !precinct_band.zero_planes.decode(&mut reader, x, y, threshold)? {
+                        threshold += 1;
+                    }
+                    block.zero_planes = precinct_band.zero_planes.value(x, y);
+                    block.included = true;
+                }
+
+                let mut passes = reader.pass_count()?;
+                while reader.bit()? == 1 {
+                    block.length_bits += 1;
+                }
+                // Passes go to the open segment until it is full; each
+                // segment gets its own length.
+                while passes > 0 {
+                    let passes_so_far: u32 = block.segments.iter().map(|s| s.passes).sum();
+                    let open = block
+                        .segments
+                        .last()
+                        .is_some_and(|s| s.passes < segment_capacity(style, passes_so_far - s.passes));
+                    if !open {
+                        block.segments.push(Segment { data: Vec::new(), passes: 0 });
+                    }
+                    let segment = block.segments.len() - 1;
+                    let last = &mut block.segments[segment];
+                    let added = passes.min(segment_capacity(style, passes_so_far - last.passes) - last.passes);
+                    last.passes += added;
+                    passes -= added;
+                    let length = reader.bits(block.length_bits + added.ilog2())? as usize;
+                    pieces.push((band_index, block_index, segment, length));
+                }
+            }
+        }
+    }
+    reader.align();
+    let mut header_end = reader.pos;
+    if source.eph && header_data[header_end.min(header_data.len())..].starts_with(&[0xFF, 0x92]) {
+        header_end += 2;
+    }
+    match source.headers.as_mut() {
+        Some((_, pos)) => *pos = header_end,
+        None => source.pos = header_end,
+    }
+
+    for (band, block, segment, length) in pieces {
+        let start = source.pos.min(source.data.len());
+        let end = (start + length).min(source.data.len());
+        resolution.bands[band].blocks[block].segments[segment]
+            .data
+            .extend_from_slice(&source.data[start..end]);
+        source.pos = end;
+    }
+    Ok(())
+}
+
+// How many passes the segment starting at pass `first` can hold. Bypassed
+// blocks switch between coded and raw segments; others only end where
+// every pass is terminated.
+fn segment_capacity(style: u8, first: u32) -> u32 {
+    if style & TERMINATE_ALL != 0 {
+        1
+    } else if style & tier1::BYPASS != 0 {
+        if first == 0 {
+            10
+        } else if tier1::is_raw_pass(style, first) {
+            2
+        } else {
+            1
+        }
+    } else {
+        u32::MAX
+    }
+}
+
+struct Bounds {
+    layers: u16,
+    resolutions: std::ops::Range<u8>,
+    components: std::ops::Range<usize>,
+}
+
+type Packet = (u16, u8, usize, u32);
+
+// Visit the packets of a progression, as (layer, resolution, component,
+// precinct), until `visit` returns false.
+fn packet_order(
+    components: &[TileComponent],
+    progression: Progression,
+    bounds: &Bounds,
+    rect: (u32, u32, u32, u32),
+    visit: &mut dyn FnMut(Packet) -> bool,
+) {
+    let max_resolution = components.iter().map(|c| c.resolutions.len()).max().unwrap_or(0) as u8;
+    let resolutions = bounds.resolutions.start..bounds.resolutions.end.min(max_resolution);
+    let precincts = |c: usize, r: u8| components[c].resolutions.get(r as usize).map_or(0, |r| r.precincts.len() as u32);
+    match progression {
+        Progression::Lrcp => {
+            for layer in 0..bounds.layers {
+                for r in resolutions.clone() {
+                    for c in bounds.components.clone() {
+                        for p in 0..precincts(c, r) {
+                            if !visit((layer, r, c, p)) {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Progression::Rlcp => {
+            for r in resolutions.clone() {
+                for layer in 0..bounds.layers {
+                    for c in bounds.components.clone() {
+                        for p in 0..precincts(c, r) {
+                            if !visit((layer, r, c, p)) {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Progression::Rpcl => {
+            let mut layers = |r: u8, c: usize, p: u32| (0..bounds.layers).all(|layer| visit((layer, r, c, p)));
+            for r in resolutions.clone() {
+                let finished = walk_positions(components, bounds.components.clone(), rect, &mut |x, y| {
+                    bounds.components.clone().all(|c| {
+                        precinct_at(&components[c], r, (x, y), rect).is_none_or(|p| layers(r, c, p))
+                    })
+                });
+                if !finished {
+                    return;
+                }
+            }
+        }
+        Progression::Pcrl => {
+            let mut layers = |r: u8, c: usize, p: u32| (0..bounds.layers).all(|layer| visit((layer, r, c, p)));
+            walk_positions(components, bounds.components.clone(), rect, &mut |x, y| {
+                bounds.components.clone().all(|c| {
+                    resolutions
+                        .clone()
+                        .all(|r| precinct_at(&components[c], r, (x, y), rect).is_none_or(|p| layers(r, c, p)))
+                })
+            });
+        }
+        Progression::Cprl => {
+            let mut layers = |r: u8, c: usize, p: u32| (0..bounds.layers).all(|layer| visit((layer, r, c, p)));
+            for c in bounds.components.clone() {
+                let finished = walk_positions(components, c..c + 1, rect, &mut |x, y| {
+                    resolutions
+                        .clone()
+                        .all(|r| precinct_at(&components[c], r, (x, y), rect).is_none_or(|p| layers(r, c, p)))
+                });
+                if !finished {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// Walk the tile in steps of the smallest precinct of the given components,
+// until `visit` returns false.
+fn walk_positions(
+    components: &[TileComponent],
+    range: std::ops::Range<usize>,
+    (tx0, ty0, tx1, ty1): (u32, u32, u32, u32),
+    visit: &mut dyn FnMut(u64, u64) -> bool,
+) -> bool {
+    let mut step_x = u64::MAX;
+    let mut step_y = u64::MAX;
+    for component in &components[range] {
+        let levels = component.resolutions.len() - 1;
+        for (r, resolution) in component.resolutions.iter().enumerate() {
+            let shift = (levels - r) as u32;
+            step_x = step_x.min((component.info.dx as u64) << (resolution.precinct_width as u32 + shift));
+            step_y = step_y.min((component.info.dy as u64) << (resolution.precinct_height as u32 + shift));
+        }
+    }
+    if step_x == u64::MAX || step_y == u64::MAX {
+        return true;
+    }
+
+    let mut y = ty0 as u64;
+    while y < ty1 as u64 {
+        let mut x = tx0 as u64;
+        while x < tx1 as u64 {
+            if !visit(x, y) {
+                return false;
+            }
+            x += step_x - x % step_x;
+        }
+        y += step_y - y % step_y;
+    }
+    true
+}
+
+// The precinct of resolution `r` whose top-left corner, on the reference
+// grid, is at (x, y), or at the tile's edge when the precinct starts
+// before it.
+fn precinct_at(component: &TileComponent, r: u8, (x, y): (u64, u64), (tx0, ty0, _, _): (u32, u32, u32, u32)) -> Option<u32> {
+    let resolution = component.resolutions.get(r as usize)?;
+    if resolution.precincts.is_empty() {
+        return None;
+    }
+    let shift = (component.resolutions.len() - 1 - r as usize) as u32;
+    let (dx, dy) = (component.info.dx as u64, component.info.dy as u64);
+    let (ppx, ppy) = (resolution.precinct_width as u32, resolution.precinct_height as u32);
+    let (rx0, ry0) = (resolution.x0 as u64, resolution.y0 as u64);
+
+    let starts_x = x.is_multiple_of(dx << (ppx + shift)) || (x == tx0 as u64 && !(rx0 << shift).is_multiple_of(1 << (ppx + shift)));
+    let starts_y = y.is_multiple_of(dy << (ppy + shift)) || (y == ty0 as u64 && !(ry0 << shift).is_multiple_of(1 << (ppy + shift)));
+    if !starts_x || !starts_y {
+        return None;
+    }
+    let px = (x.div_ceil(dx << shift) >> ppx) - (rx0 >> ppx);
+    let py = (y.div_ceil(dy << shift) >> ppy) - (ry0 >> ppy);
+    if px >= resolution.precincts_wide as u64 || py >= resolution.precincts_high as u64 {
+        return None;
+    }
+    Some((py * resolution.precincts_wide as u64 + px) as u32)
+}
@@ -0,0 +1,117 @@
+// The inverse wavelet transforms: the reversible 5/3 filter and the
+// irreversible 9/7 filter, both as lifting steps.
+
+const ALPHA: f32 = -1.586_134_3;
+const BETA: f32 = -0.052_980_12;
+const GAMMA: f32 = 0.882_911_1;
+const DELTA: f32 = 0.443_506_87;
+const K: f32 = 1.230_174_1;
+
+// Samples borrowed from beyond each end, enough for four lifting steps.
+const EXTENSION: usize = 4;
+
+// A band of coefficients and its area in band coordinates.
+pub(super) struct Subband<'a> {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub data: &'a [f32],
+}
+
+// Rebuild the resolution covering (x0, y0)-(x1, y1) from the one below it
+// and its HL, LH and HH bands.
+pub(super) fn synthesize(
+    low: &Subband,
+    high: [&Subband; 3],
+    (x0, y0, x1, y1): (u32, u32, u32, u32),
+    reversible: bool,
+) -> Vec<f32> {
+    let width = (x1 - x0) as usize;
+    let height = (y1 - y0) as usize;
+    let mut samples = vec![0.0; width * height];
+
+    // Low-pass samples sit at even coordinates, high-pass ones at odd.
+    for (band, (xo, yo)) in [low, high[0], high[1], high[2]].into_iter().zip([(0, 0), (1, 0), (0, 1), (1, 1)]) {
+        let band_width = (band.x1 - band.x0) as usize;
+        for by in band.y0..band.y1 {
+            let y = 2 * by + yo;
+            if y < y0 || y >= y1 {
+                continue;
+            }
+            let row = (by - band.y0) as usize * band_width;
+            for bx in band.x0..band.x1 {
+                let x = 2 * bx + xo;
+                if x >= x0 && x < x1 {
+                    samples[(y - y0) as usize * width + (x - x0) as usize] = band.data[row + (bx - band.x0) as usize];
+                }
+            }
+        }
+    }
+
+    let mut line = Vec::new();
+    for row in samples.chunks_mut(width.max(1)) {
+        synthesize_line(row, x0, reversible, &mut line);
+    }
+    let mut column = vec![0.0; height];
+    for x in 0..width {
+        for (y, sample) in column.iter_mut().enumerate() {
+            *sample = samples[y * width + x];
+        }
+        synthesize_line(&mut column, y0, reversible, &mut line);
+        for (y, sample) in column.iter().enumerate() {
+            samples[y * width + x] = *sample;
+        }
+    }
+    samples
+}
+
+// One-dimensional synthesis of interleaved samples whose first one is at
+// coordinate `start`.
+fn synthesize_line(samples: &mut [f32], start: u32, reversible: bool, line: &mut Vec<f32>) {
+    let n = samples.len();
+    if n == 0 {
+        return;
+    }
+    if n == 1 {
+        // A lone high-pass sample holds twice the value.
+        if start % 2 == 1 {
+            samples[0] /= 2.0;
+        }
+        return;
+    }
+
+    // Mirror the signal around its first and last samples.
+    let period = 2 * (n - 1);
+    let mirrored = |i: isize| {
+        let i = i.rem_euclid(period as isize) as usize;
+        samples[if i < n { i } else { period - i }]
+    };
+    line.clear();
+    line.extend((-(EXTENSION as isize)..(n + EXTENSION) as isize).map(mirrored));
+
+    // Whether line[i] is an even, low-pass, sample.
+    let first_even = (start as usize + EXTENSION).is_multiple_of(2);
+    let even = |i: usize| i.is_multiple_of(2) == first_even;
+    let lift = |line: &mut Vec<f32>, even_samples: bool, step: &dyn Fn(f32, f32, f32) -> f32| {
+        for i in 1..line.len() - 1 {
+            if even(i) == even_samples {
+                line[i] = step(line[i], line[i - 1], line[i + 1]);
+            }
+        }
+    };
+
+    if reversible {
+        lift(line, true, &|x, l, r| x - ((l + r + 2.0) / 4.0).floor());
+        lift(line, false, &|x, l, r| x + ((l + r) / 2.0).floor());
+    } else {
+        for (i, sample) in line.iter_mut().enumerate() {
+            *sample *= if even(i) { K } else { 1.0 / K };
+        }
+        lift(line, true, &|x, l, r| x - DELTA * (l + r));
+        lift(line, false, &|x, l, r| x - GAMMA * (l + r));
+        lift(line, true, &|x, l, r| x - BETA * (l + r));
+        lift(line, false, &|x, l, r| x - ALPHA * (l + r));
+    }
+    samples.copy_from_slice(&line[EXTENSION..EXTENSION + n]);
+}
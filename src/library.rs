@@ -0,0 +1,369 @@
+use anyhow::{Context as AnyhowContext, Result};
+use eframe::egui::{self, Color32, ColorImage, Rect, Sense, TextureHandle, TextureOptions, Ui};
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use walkdir::WalkDir;
+
+use crate::workers::WorkerPool;
+use crate::{archive, decoder, image_files_in_directory, natural_sort_paths, platform, progress, settings, texture};
+
+pub const THUMBNAIL_WIDTH: u32 = 160;
+pub const THUMBNAIL_HEIGHT: u32 = 240;
+const THUMBNAIL_WORKERS: usize = 2;
+const CARD_LABEL_HEIGHT: f32 = 36.0;
+
+// Root folders the library is built from.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LibrarySettings {
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+}
+
+impl LibrarySettings {
+    pub const FILE_NAME: &'static str = "library.json";
+}
+
+// A readable volume: a comic archive, or a folder of images.
+#[derive(Debug, Clone)]
+pub struct LibraryItem {
+    pub path: PathBuf,
+    pub title: String,
+}
+
+enum Thumbnail {
+    Pending,
+    Ready(TextureHandle),
+    Failed,
+}
+
+type ThumbnailResult = (PathBuf, Result<ColorImage, String>);
+
+pub struct Library {
+    settings: LibrarySettings,
+    items: Vec<LibraryItem>,
+    scanning: Option<Receiver<Vec<LibraryItem>>>,
+    scanned: bool,
+    thumbnails: HashMap<PathBuf, Thumbnail>,
+    // Loads thumbnails from the disk cache or builds them from the first
+    // page of each volume.
+    loader: WorkerPool<PathBuf, ThumbnailResult>,
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Self {
+            settings: LibrarySettings::default(),
+            items: Vec::new(),
+            scanning: None,
+            scanned: false,
+            thumbnails: HashMap::new(),
+            loader: WorkerPool::new("thumbnail", THUMBNAIL_WORKERS, |path: PathBuf, _| {
+                let result = load_thumbnail(&path).map_err(|e| format!("{:#}", e));
+                (path, result)
+            }),
+        }
+    }
+
+    pub fn load_settings(&mut self) {
+        self.settings = settings::load(LibrarySettings::FILE_NAME);
+    }
+
+    // Wake the UI whenever a thumbnail is ready.
+    pub fn set_repaint_context(&self, ctx: egui::Context) {
+        self.loader.set_repaint_context(ctx);
+    }
+
+    // Rescan every root folder in the background.
+    pub fn rescan(&mut self, ctx: &egui::Context) {
+        let roots = self.settings.roots.clone();
+        let (sender, receiver) = mpsc::channel();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let _ = sender.send(scan_roots(&roots));
+            ctx.request_repaint();
+        });
+        self.scanning = Some(receiver);
+        self.scanned = true;
+    }
+
+    fn add_root(&mut self, root: PathBuf, ctx: &egui::Context) -> Result<()> {
+        if !self.settings.roots.contains(&root) {
+            self.settings.roots.push(root);
+            settings::save(LibrarySettings::FILE_NAME, &self.settings)?;
+            self.rescan(ctx);
+        }
+        Ok(())
+    }
+
+    fn remove_root(&mut self, index: usize, ctx: &egui::Context) -> Result<()> {
+        self.settings.roots.remove(index);
+        settings::save(LibrarySettings::FILE_NAME, &self.settings)?;
+        self.rescan(ctx);
+        Ok(())
+    }
+
+    fn poll(&mut self, ctx: &egui::Context) {
+        if let Some(items) = self.scanning.as_ref().and_then(|receiver| receiver.try_recv().ok()) {
+            self.items = items;
+            self.scanning = None;
+        }
+
+        while let Some((path, result)) = self.loader.try_recv() {
+            let thumbnail = match result {
+                Ok(image) => Thumbnail::Ready(ctx.load_texture(
+                    format!("thumbnail:{}", path.display()),
+                    image,
+                    TextureOptions::default(),
+                )),
+                Err(e) => {
+                    eprintln!("Failed to create thumbnail for {}: {}", path.display(), e);
+                    Thumbnail::Failed
+                }
+            };
+            self.thumbnails.insert(path, thumbnail);
+        }
+    }
+
+    // Draw the library. Returns the volume the user clicked, if any.
+    pub fn show(&mut self, ui: &mut Ui, ctx: &egui::Context) -> Result<Option<PathBuf>> {
+        if !self.scanned {
+            self.rescan(ctx);
+        }
+        self.poll(ctx);
+
+        let mut result = Ok(None);
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("Add Folder...").clicked() {
+                if let Some(root) = rfd::FileDialog::new().pick_folder() {
+                    result = self.add_root(root, ctx).map(|_| None);
+                }
+            }
+            if ui.button("Rescan").clicked() {
+                self.rescan(ctx);
+            }
+            ui.separator();
+
+            let mut remove = None;
+            for (index, root) in self.settings.roots.iter().enumerate() {
+                ui.label(root.display().to_string());
+                if ui.small_button("x").on_hover_text("Remove from library").clicked() {
+                    remove = Some(index);
+                }
+            }
+            if let Some(index) = remove {
+                result = self.remove_root(index, ctx).map(|_| None);
+            }
+        });
+        ui.separator();
+
+        if self.scanning.is_some() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Scanning library...");
+            });
+        } else if self.settings.roots.is_empty() {
+            ui.label("Add a folder to build your library.");
+        } else if self.items.is_empty() {
+            ui.label("No comics found in the library folders.");
+        }
+
+        let card_size = egui::vec2(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32 + CARD_LABEL_HEIGHT);
+        let Self { items, thumbnails, loader, .. } = self;
+        egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing = egui::vec2(12.0, 12.0);
+                for item in items.iter() {
+                    let (rect, response) = ui.allocate_exact_size(card_size, Sense::click());
+                    if ui.is_rect_visible(rect) {
+                        let thumbnail = thumbnails.entry(item.path.clone()).or_insert_with(|| {
+                            loader.push(item.path.clone(), false);
+                            Thumbnail::Pending
+                        });
+                        draw_card(ui, rect, item, thumbnail, response.hovered());
+                    }
+                    if response.on_hover_text(item.path.display().to_string()).clicked() {
+                        result = Ok(Some(item.path.clone()));
+                    }
+                }
+            });
+        });
+
+        result
+    }
+}
+
+fn draw_card(ui: &Ui, rect: Rect, item: &LibraryItem, thumbnail: &Thumbnail, hovered: bool) {
+    let painter = ui.painter_at(rect);
+    let cover_rect = Rect::from_min_size(
+        rect.min,
+        egui::vec2(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32),
+    );
+    painter.rect_filled(cover_rect, 4.0, Color32::from_gray(40));
+
+    match thumbnail {
+        Thumbnail::Ready(texture) => {
+            // Thumbnails keep their aspect ratio inside the cover area.
+            let size = texture.size_vec2();
+            let scale = (cover_rect.width() / size.x).min(cover_rect.height() / size.y);
+            let image_rect = Rect::from_center_size(cover_rect.center(), size * scale);
+            let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+            painter.image(texture.id(), image_rect, uv, Color32::WHITE);
+        }
+        Thumbnail::Pending => {
+            painter.text(
+                cover_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Loading...",
+                egui::FontId::proportional(14.0),
+                Color32::GRAY,
+            );
+        }
+        Thumbnail::Failed => {
+            painter.text(
+                cover_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "No cover",
+                egui::FontId::proportional(14.0),
+                Color32::GRAY,
+            );
+        }
+    }
+
+    if hovered {
+        painter.rect_stroke(
+            cover_rect,
+            4.0,
+            egui::Stroke::new(2.0, ui.visuals().selection.bg_fill),
+            egui::StrokeKind::Inside,
+        );
+    }
+
+    let galley = painter.layout(
+        item.title.clone(),
+        egui::FontId::proportional(12.0),
+        ui.visuals().text_color(),
+        rect.width(),
+    );
+    painter.galley(egui::pos2(rect.min.x, cover_rect.max.y + 4.0), galley, Color32::WHITE);
+}
+
+// Find every archive and every folder that directly contains images.
+fn scan_roots(roots: &[PathBuf]) -> Vec<LibraryItem> {
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+
+    for root in roots {
+        let walker = WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !platform::is_hidden_or_junk(entry.path()))
+            .filter_map(|e| e.ok());
+        for entry in walker {
+            let path = entry.path();
+            let is_volume = if entry.file_type().is_dir() {
+                has_images(path)
+            } else {
+                archive::has_archive_extension(path)
+            };
+            if is_volume && seen.insert(path.to_path_buf()) {
+                items.push(LibraryItem {
+                    path: path.to_path_buf(),
+                    title: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| path.display().to_string()),
+                });
+            }
+        }
+    }
+
+    items.sort_by(|a, b| natural_sort_paths(&a.path, &b.path));
+    items
+}
+
+fn has_images(dir: &Path) -> bool {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries.filter_map(|e| e.ok()).any(|entry| {
+                let path = entry.path();
+                path.is_file()
+                    && !platform::is_hidden_or_junk(&path)
+                    && archive::has_image_extension(&path.to_string_lossy())
+            })
+        })
+        .unwrap_or(false)
+}
+
+// The first page of a volume, in the same order the reader shows pages.
+pub fn cover_image(path: &Path) -> Result<DynamicImage> {
+    if path.is_dir() {
+        let first = image_files_in_directory(path)
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No images found in directory: {}", path.display()))?;
+        return decoder::decode_image_file(&first);
+    }
+    if !archive::has_archive_extension(path) {
+        return decoder::decode_image_file(path);
+    }
+
+    let mut backend = archive::open(path)?;
+    let pages = archive::page_entries(backend.as_ref());
+    let front_cover = archive::read_comic_info(backend.as_mut())
+        .and_then(|info| info.front_cover())
+        .and_then(|page| pages.get(page).copied());
+    let first = front_cover
+        .or_else(|| pages.first().copied())
+        .ok_or_else(|| anyhow::anyhow!("No images found in archive: {}", path.display()))?;
+    let name = backend.entry_names()[first].clone();
+    let buffer = backend.read_entry(first)?;
+    decoder::decode_image_bytes(&name, &buffer)
+}
+
+pub fn thumbnail(path: &Path, width: u32, height: u32) -> Result<DynamicImage> {
+    Ok(cover_image(path)?.thumbnail(width, height))
+}
+
+// Thumbnails on disk are keyed by path, size and modification time so they
+// are regenerated when a volume changes.
+fn thumbnail_cache_path(path: &Path) -> Option<PathBuf> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update(metadata.len().to_le_bytes());
+    hasher.update(modified.to_le_bytes());
+    let name = progress::hex_digest(hasher);
+
+    dirs::cache_dir().map(|dir| dir.join("manga_reader").join("thumbnails").join(format!("{}.jpg", name)))
+}
+
+fn load_thumbnail(path: &Path) -> Result<ColorImage> {
+    let cache_path = thumbnail_cache_path(path);
+    if let Some(image) = cache_path.as_ref().and_then(|cached| decoder::decode_image_file(cached).ok()) {
+        return Ok(texture::color_image(&image.to_rgba8()));
+    }
+
+    let image = thumbnail(path, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)?;
+    if let Some(cache_path) = cache_path {
+        if let Some(dir) = cache_path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create thumbnail cache: {}", dir.display()))?;
+        }
+        if let Err(e) = DynamicImage::ImageRgb8(image.to_rgb8()).save_with_format(&cache_path, ImageFormat::Jpeg) {
+            eprintln!("Failed to cache thumbnail {}: {}", cache_path.display(), e);
+        }
+    }
+    Ok(texture::color_image(&image.to_rgba8()))
+}
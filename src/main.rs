@@ -1,20 +1,133 @@
 use anyhow::{Context as AnyhowContext, Result};
 use eframe::{egui, App, CreationContext, Frame, NativeOptions, run_native};
-use egui::{Color32, ColorImage, Rect, Sense, TextureHandle, TextureOptions, Ui, IconData};
+use egui::{Color32, Rect, Sense, Ui, IconData};
 use image::{DynamicImage, ImageFormat};
-use std::env;
-use std::fs::{self, File};
-use std::io::{BufReader, Read};
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
-use zip::ZipArchive;
 use std::cmp::Ordering;
 use std::ffi::OsStr;
-use std::os::windows::fs::MetadataExt;
+
+mod archive;
+mod bookmarks;
+mod cache;
+mod cli;
+mod comicinfo;
+mod decoder;
+mod jpeg2000;
+mod library;
+mod metadata;
+mod platform;
+mod progress;
+mod recompress;
+mod session;
+mod settings;
+mod texture;
+mod verify;
+mod workers;
+
+use bookmarks::{Bookmark, BookmarkAction, BookmarkStore};
+use archive::PackOptions;
+use cache::{CachedPage, PageCache, PageKey};
+use comicinfo::ComicInfo;
+use decoder::{DecodedPage, PageDecoder, PageSource, SharedArchive};
+use library::Library;
+use metadata::{BatchEdit, EditorAction, MetadataEditor};
+use progress::{ProgressStore, VolumeKey};
+use recompress::{RecompressEvent, RecompressPlan, RecompressWindow};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use session::Session;
+use settings::DirectionSettings;
+use texture::PageTexture;
+use verify::VerifyWindow;
+
+// Pages decoded ahead of and behind the current one.
+const PREFETCH_PAGES: usize = 2;
+const DEFAULT_CACHE_BUDGET_MB: usize = 512;
+// Height/width ratio assumed for pages in continuous mode until they decode.
+const CONTINUOUS_PLACEHOLDER_ASPECT: f32 = 1.5;
+const DEFAULT_FIT_WIDTH_CAP_PERCENT: u32 = 100;
+// Reading progress is written at most this often while turning pages.
+const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum ReadingDirection {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum PageLayout {
+    #[default]
+    Single,
+    Spread,
+    // All pages stacked vertically at fit width, for webtoons.
+    Continuous,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum FitMode {
+    #[default]
+    Page,
+    Width,
+    Height,
+    Original,
+    // Fit width, but never zoom past `fit_width_cap_percent`.
+    WidthCapped,
+    // Keep the zoom the user picked.
+    Manual,
+}
+
+impl FitMode {
+    const ALL: [FitMode; 6] = [
+        FitMode::Page,
+        FitMode::Width,
+        FitMode::Height,
+        FitMode::Original,
+        FitMode::WidthCapped,
+        FitMode::Manual,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            FitMode::Page => "Fit Page (P)",
+            FitMode::Width => "Fit Width (W)",
+            FitMode::Height => "Fit Height (H)",
+            FitMode::Original => "Original Size (O)",
+            FitMode::WidthCapped => "Fit Width, Capped (C)",
+            FitMode::Manual => "Manual (M)",
+        }
+    }
+}
+
+// A page moved to the trash, kept so the deletion can be undone.
+struct DeletedFile {
+    path: PathBuf,
+    // Position in `files_in_folder` before it was deleted.
+    index: usize,
+    page_type: Option<String>,
+    volume: Option<PathBuf>,
+}
+
+// An archive being rewritten by the recompress window.
+struct PendingRecompress {
+    // Fingerprint of the archive before it was rewritten.
+    old_key: Option<VolumeKey>,
+    // Page to return to when it was the open archive.
+    page: Option<usize>,
+}
 
 struct MangaReader {
-    current_image: Option<TextureHandle>,
-    current_image_data: Option<DynamicImage>, // Store the actual image data
+    current_image: Option<PageTexture>,
+    // Second page of a two-page spread, shown after `current_image`.
+    spread_image: Option<PageTexture>,
+    current_image_data: Option<Arc<DynamicImage>>, // Store the actual image data
     current_path: Option<PathBuf>,
     files_in_folder: Vec<PathBuf>,
     current_index: usize,
@@ -26,13 +139,61 @@ struct MangaReader {
     last_pos: Option<egui::Pos2>,
     status_message: Option<(String, f32)>,
     fullscreen: bool,
-    auto_fit: bool,
+    fit_mode: FitMode,
+    fit_width_cap_percent: u32,
+    // Size of the image view last frame, used for fitting and resize checks.
+    view_size: Option<egui::Vec2>,
     archive_files: Vec<PathBuf>,
     current_archive_index: usize,
     show_last_image_alert: bool,
     is_in_archive: bool,
+    // Open handle of the current archive, kept for the whole volume.
+    archive: Option<SharedArchive>,
+    // Archive entry index of each page in `files_in_folder`.
+    archive_entries: Vec<usize>,
+    reading_direction: ReadingDirection,
+    direction_settings: DirectionSettings,
     show_delete_confirmation: bool,
     pending_delete_path: Option<PathBuf>,
+    pending_open: Option<PathBuf>,
+    decoder: PageDecoder,
+    // Bumped whenever the page list changes so stale decodes are dropped.
+    generation: u64,
+    pending_pages: HashSet<usize>,
+    page_cache: PageCache,
+    loading_page: Option<usize>,
+    loading_spread_page: Option<usize>,
+    prefetch_pages: usize,
+    show_debug_overlay: bool,
+    page_layout: PageLayout,
+    // Distance scrolled past the top of the current page in continuous mode.
+    scroll_in_page: f32,
+    // Pixel size of every page decoded so far, used to spot wide spreads.
+    page_sizes: HashMap<PageKey, egui::Vec2>,
+    library: Library,
+    show_library: bool,
+    progress: ProgressStore,
+    // When progress changed that is not yet on disk.
+    progress_changed_at: Option<Instant>,
+    // Content fingerprint of the open volume, the key into `progress`.
+    volume_key: Option<VolumeKey>,
+    session: Session,
+    // Page and zoom from the last session, applied once its volume opens.
+    pending_restore: Option<(usize, f32)>,
+    bookmarks: BookmarkStore,
+    show_bookmarks: bool,
+    comic_info: Option<ComicInfo>,
+    // ComicInfo page type (FrontCover, Story, ...) of each shown page.
+    page_types: Vec<Option<String>>,
+    show_info: bool,
+    metadata_editor: MetadataEditor,
+    // Most recent deletion last.
+    deleted_files: Vec<DeletedFile>,
+    verify_window: VerifyWindow,
+    show_pack_dialog: bool,
+    pack_options: PackOptions,
+    recompress_window: RecompressWindow,
+    pending_recompress: Option<PendingRecompress>,
 }
 
 // Implement natural sorting for filenames
@@ -102,10 +263,29 @@ fn natural_sort(a: &str, b: &str) -> Ordering {
     }
 }
 
+// Image files directly inside `dir`, in natural order, skipping hidden and
+// junk files.
+fn image_files_in_directory(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkDir::new(dir)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            path.is_file()
+                && !platform::is_hidden_or_junk(path)
+                && archive::has_image_extension(&path.to_string_lossy())
+        })
+        .collect();
+    files.sort_by(|a, b| natural_sort_paths(a, b));
+    files
+}
+
 impl Default for MangaReader {
     fn default() -> Self {
         Self {
             current_image: None,
+            spread_image: None,
             current_image_data: None,
             current_path: None,
             files_in_folder: Vec::new(),
@@ -118,46 +298,237 @@ impl Default for MangaReader {
             last_pos: None,
             status_message: None,
             fullscreen: false,
-            auto_fit: true,
+            fit_mode: FitMode::default(),
+            fit_width_cap_percent: DEFAULT_FIT_WIDTH_CAP_PERCENT,
+            view_size: None,
             archive_files: Vec::new(),
             current_archive_index: 0,
             show_last_image_alert: false,
             is_in_archive: false,
+            archive: None,
+            archive_entries: Vec::new(),
+            reading_direction: ReadingDirection::default(),
+            direction_settings: DirectionSettings::default(),
             show_delete_confirmation: false,
             pending_delete_path: None,
+            pending_open: None,
+            decoder: PageDecoder::new(
+                thread::available_parallelism().map(|n| n.get().clamp(1, 4)).unwrap_or(2),
+            ),
+            generation: 0,
+            pending_pages: HashSet::new(),
+            page_cache: PageCache::new(DEFAULT_CACHE_BUDGET_MB),
+            loading_page: None,
+            loading_spread_page: None,
+            prefetch_pages: PREFETCH_PAGES,
+            show_debug_overlay: false,
+            page_layout: PageLayout::default(),
+            scroll_in_page: 0.0,
+            page_sizes: HashMap::new(),
+            library: Library::new(),
+            show_library: false,
+            progress: ProgressStore::default(),
+            progress_changed_at: None,
+            volume_key: None,
+            session: Session::default(),
+            pending_restore: None,
+            bookmarks: BookmarkStore::default(),
+            show_bookmarks: false,
+            comic_info: None,
+            page_types: Vec::new(),
+            show_info: false,
+            metadata_editor: MetadataEditor::new(),
+            deleted_files: Vec::new(),
+            verify_window: VerifyWindow::new(),
+            show_pack_dialog: false,
+            pack_options: PackOptions::default(),
+            recompress_window: RecompressWindow::new(),
+            pending_recompress: None,
         }
     }
 }
 
 impl MangaReader {
-    fn new(cc: &CreationContext<'_>) -> Self {
-        let args: Vec<String> = env::args().collect();
+    fn new(cc: &CreationContext<'_>, session: Session, path: Option<PathBuf>) -> Self {
         let mut reader = Self::default();
+        reader.decoder.set_repaint_context(cc.egui_ctx.clone());
+        reader.direction_settings = settings::load(DirectionSettings::FILE_NAME);
+        reader.library.set_repaint_context(cc.egui_ctx.clone());
+        reader.library.load_settings();
+        reader.progress = settings::load(ProgressStore::FILE_NAME);
+        reader.bookmarks = settings::load(BookmarkStore::FILE_NAME);
+
+        reader.fit_mode = session.fit_mode;
+        reader.fullscreen = session.fullscreen;
 
-        if args.len() > 1 {
-            let file_path = PathBuf::from(&args[1]);
+        if let Some(file_path) = path {
             if file_path.exists() {
-                let _ctx = cc.egui_ctx.clone();
-                let _file_path_clone = file_path.clone();
                 cc.egui_ctx.request_repaint();
-                reader.current_path = Some(file_path);
+                reader.pending_open = Some(file_path);
             }
+        } else if let Some(volume) = session.volume.clone().filter(|volume| volume.exists()) {
+            cc.egui_ctx.request_repaint();
+            reader.pending_open = Some(volume);
+            reader.pending_restore = Some((session.page, session.zoom));
         }
 
+        reader.session = session;
         reader
     }
 
+    fn page_name(&self, page: usize) -> String {
+        self.files_in_folder
+            .get(page)
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    fn add_bookmark(&mut self) -> Result<()> {
+        let volume = self
+            .volume_path()
+            .ok_or_else(|| anyhow::anyhow!("No volume is open"))?;
+        if self.bookmarks.contains(&volume, self.current_index) {
+            self.set_status(format!("Page {} is already bookmarked", self.current_index + 1), 3.0);
+            return Ok(());
+        }
+        let page_name = self.page_name(self.current_index);
+        self.bookmarks.add(volume, self.volume_key.clone(), self.current_index, page_name);
+        settings::save(BookmarkStore::FILE_NAME, &self.bookmarks)?;
+        self.set_status(format!("Bookmarked page {}", self.current_index + 1), 3.0);
+        Ok(())
+    }
+
+    fn jump_to_bookmark(&mut self, bookmark: &Bookmark, ctx: &egui::Context) -> Result<()> {
+        // A renamed volume is found again through the progress store.
+        let volume = Some(bookmark.volume.clone())
+            .filter(|volume| volume.exists())
+            .or_else(|| {
+                bookmark
+                    .volume_key
+                    .as_ref()
+                    .and_then(|key| self.progress.locate(&key.fingerprint))
+                    .map(Path::to_path_buf)
+            })
+            .ok_or_else(|| anyhow::anyhow!("Bookmarked volume not found: {}", bookmark.volume.display()))?;
+
+        if self.volume_path().as_ref() != Some(&volume) || self.is_in_archive != volume.is_file() {
+            self.open_file(&volume, ctx)?;
+        }
+        if self.files_in_folder.is_empty() {
+            return Ok(());
+        }
+
+        // Prefer the page with the bookmarked name in case pages moved.
+        self.current_index = if self.page_name(bookmark.page) == bookmark.page_name {
+            bookmark.page
+        } else {
+            self.files_in_folder
+                .iter()
+                .position(|path| {
+                    path.file_name()
+                        .is_some_and(|name| name.to_string_lossy() == bookmark.page_name)
+                })
+                .unwrap_or(bookmark.page.min(self.files_in_folder.len() - 1))
+        };
+        self.load_current_page(ctx)
+    }
+
+    fn draw_bookmarks_panel(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::right("bookmarks").show(ctx, |ui| {
+            ui.heading("Bookmarks");
+            let can_add = !self.files_in_folder.is_empty();
+            let result = match self.bookmarks.show_panel(ui, can_add) {
+                Some(BookmarkAction::Add) => self.add_bookmark(),
+                Some(BookmarkAction::Jump(bookmark)) => self.jump_to_bookmark(&bookmark, ctx),
+                Some(BookmarkAction::Export) => match rfd::FileDialog::new()
+                    .add_filter("JSON", &["json"])
+                    .set_file_name("bookmarks.json")
+                    .save_file()
+                {
+                    Some(path) => self.bookmarks.export(&path),
+                    None => Ok(()),
+                },
+                Some(BookmarkAction::Changed) => settings::save(BookmarkStore::FILE_NAME, &self.bookmarks),
+                None => Ok(()),
+            };
+            if let Err(e) = result {
+                self.set_status(format!("Error: {}", e), 5.0);
+            }
+        });
+    }
+
+    fn save_session(&mut self) {
+        self.session.volume = self.current_path.clone();
+        self.session.page = self.current_index;
+        self.session.zoom = self.zoom;
+        self.session.fit_mode = self.fit_mode;
+        self.session.fullscreen = self.fullscreen;
+        if let Err(e) = settings::save(Session::FILE_NAME, &self.session) {
+            eprintln!("Failed to save session: {:#}", e);
+        }
+    }
+
+    // Remember the normal window geometry, and save the session on close.
+    fn track_window(&mut self, ctx: &egui::Context) {
+        let (outer, inner, maximized, fullscreen, close_requested) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (
+                viewport.outer_rect,
+                viewport.inner_rect,
+                viewport.maximized.unwrap_or(false),
+                viewport.fullscreen.unwrap_or(false),
+                viewport.close_requested(),
+            )
+        });
+
+        if !fullscreen {
+            self.session.window.maximized = maximized;
+            if !maximized {
+                if let Some(inner) = inner {
+                    self.session.window.size = Some([inner.width(), inner.height()]);
+                }
+                if let Some(outer) = outer {
+                    self.session.window.position = Some([outer.min.x, outer.min.y]);
+                }
+            }
+        }
+
+        if close_requested {
+            self.save_progress(true, ctx);
+            self.save_session();
+        }
+    }
+
+    fn restore_session_page(&mut self, ctx: &egui::Context) {
+        let Some((page, zoom)) = self.pending_restore.take() else {
+            return;
+        };
+        if page < self.files_in_folder.len() && page != self.current_index {
+            self.current_index = page;
+            if let Err(e) = self.load_current_page(ctx) {
+                self.set_status(format!("Error: {}", e), 5.0);
+            }
+        }
+        if self.fit_mode == FitMode::Manual {
+            self.zoom = zoom;
+        }
+    }
+
     fn set_status(&mut self, message: String, duration: f32) {
         self.status_message = Some((message, duration));
     }
 
     fn is_archive_file(path: &Path) -> bool {
-        if let Some(extension) = path.extension() {
-            let ext = extension.to_string_lossy().to_lowercase();
-            ext == "cbz" || ext == "zip"
-        } else {
-            false
-        }
+        archive::has_archive_extension(path)
+    }
+
+    fn supported_extensions() -> Vec<&'static str> {
+        archive::IMAGE_EXTENSIONS
+            .iter()
+            .chain(archive::ARCHIVE_EXTENSIONS)
+            .copied()
+            .collect()
     }
 
     fn list_archive_files_in_directory(&mut self, dir: &Path) -> Result<()> {
@@ -173,21 +544,35 @@ impl MangaReader {
     }
 
     fn open_file(&mut self, path: &Path, ctx: &egui::Context) -> Result<()> {
+        self.open_path(path, ctx)?;
+        self.session.add_recent(path, self.volume_key.clone());
+        self.save_session();
+        Ok(())
+    }
+
+    fn open_path(&mut self, path: &Path, ctx: &egui::Context) -> Result<()> {
+        self.save_progress(true, ctx);
+        self.show_library = false;
         self.current_path = Some(path.to_path_buf());
         self.zoom = 1.0;
         self.offset_x = 0.0;
         self.offset_y = 0.0;
         self.show_last_image_alert = false;
+        self.archive = None;
+        self.archive_entries.clear();
+        self.reset_pages();
 
         if path.is_dir() {
             self.is_in_archive = false;
             self.list_image_files_in_directory(path)?;
+            let comic_info = comicinfo::load_from_dir(path);
+            self.apply_reading_direction(comic_info.as_ref().and_then(ComicInfo::reading_direction));
+            self.apply_comic_info(comic_info);
+            self.identify_volume();
             if !self.files_in_folder.is_empty() {
-                let first_file = self.files_in_folder[0].clone();
-                self.current_index = 0;
-                self.load_image(&first_file, ctx)
-                    .with_context(|| format!("Failed to load first image in directory: {}", first_file.display()))?;
-                self.set_status(format!("Opened directory: {}", path.display()), 3.0);
+                self.current_index = self.saved_page();
+                self.load_current_page(ctx)?;
+                self.set_status(format!("Opened directory: {}{}", path.display(), self.resume_note()), 3.0);
             } else {
                 self.set_status(format!("No images found in directory: {}", path.display()), 3.0);
             }
@@ -205,168 +590,671 @@ impl MangaReader {
             }
             self.load_cbz(path, ctx)
                 .with_context(|| format!("Failed to load archive: {}", path.display()))?;
-            self.set_status(format!("Opened archive: {}", path.display()), 3.0);
+            self.set_status(format!("Opened archive: {}{}", path.display(), self.resume_note()), 3.0);
             return Ok(());
         }
 
         self.is_in_archive = false;
-        self.load_image(path, ctx)
-            .with_context(|| format!("Failed to load image: {}", path.display()))?;
-        self.set_status(format!("Opened image: {}", path.display()), 3.0);
-
+        self.apply_reading_direction(None);
+        self.apply_comic_info(None);
+        self.files_in_folder.clear();
         if let Some(parent) = path.parent() {
             self.list_image_files_in_directory(parent)?;
-            self.current_index = self.files_in_folder
-                .iter()
-                .position(|p| p == path)
-                .unwrap_or(0);
         }
+        // Opening a specific image starts there rather than at the saved page.
+        self.identify_volume();
+        self.current_index = match self.files_in_folder.iter().position(|p| p == path) {
+            Some(index) => index,
+            None => {
+                // Hidden or oddly named files can still be opened directly.
+                self.files_in_folder.insert(0, path.to_path_buf());
+                0
+            }
+        };
+        self.load_current_page(ctx)?;
+        self.set_status(format!("Opened image: {}", path.display()), 3.0);
 
         Ok(())
     }
 
     fn list_image_files_in_directory(&mut self, dir: &Path) -> Result<()> {
-        self.files_in_folder.clear();
         println!("Scanning directory: {}", dir.display());
+        self.files_in_folder = image_files_in_directory(dir);
+        println!("Found {} images", self.files_in_folder.len());
+        Ok(())
+    }
 
-        for entry in WalkDir::new(dir)
-            .max_depth(1)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
-            if !path.is_file() {
-                continue;
-            }
+    fn load_cbz(&mut self, path: &Path, ctx: &egui::Context) -> Result<()> {
+        // Moving on to the next archive closes the current volume.
+        self.save_progress(true, ctx);
+        self.reset_pages();
+        let mut backend = archive::open(path)?;
+        let comic_info = archive::read_comic_info(backend.as_mut());
+        let names = backend.entry_names();
+        self.archive_entries = archive::page_entries(backend.as_ref());
+        self.files_in_folder = self.archive_entries
+            .iter()
+            .map(|&index| PathBuf::from(&names[index]))
+            .collect();
+        let direction = backend
+            .reading_direction()
+            .or_else(|| comic_info.as_ref().and_then(ComicInfo::reading_direction));
+        self.apply_reading_direction(direction);
+        self.apply_comic_info(comic_info);
+        self.archive = Some(Arc::new(Mutex::new(backend)));
+        self.identify_volume();
 
-            if let Ok(metadata) = path.metadata() {
-                const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
-                const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
-                let attributes = metadata.file_attributes();
-                if (attributes & FILE_ATTRIBUTE_HIDDEN) != 0 || (attributes & FILE_ATTRIBUTE_SYSTEM) != 0 {
-                    println!("Skipping hidden/system file: {}", path.display());
-                    continue;
-                }
+        if !self.files_in_folder.is_empty() {
+            self.current_index = self.saved_page();
+            self.load_current_page(ctx)?;
+            self.set_status(format!("Loaded archive with {} images", self.files_in_folder.len()), 3.0);
+        } else {
+            self.set_status("No images found in archive".to_string(), 3.0);
+        }
+
+        Ok(())
+    }
+
+    // The volume being read: the archive, or the folder holding loose images.
+    fn volume_path(&self) -> Option<PathBuf> {
+        let path = self.current_path.as_ref()?;
+        let volume = if self.is_in_archive || path.is_dir() {
+            path.clone()
+        } else {
+            path.parent()?.to_path_buf()
+        };
+        Some(fs::canonicalize(&volume).unwrap_or(volume))
+    }
+
+    // Hide pages ComicInfo marks as deleted and remember the page types.
+    // ComicInfo numbers pages by their position in the full sorted list.
+    fn apply_comic_info(&mut self, comic_info: Option<ComicInfo>) {
+        self.page_types.clear();
+        if let Some(info) = &comic_info {
+            let kept: Vec<usize> = (0..self.files_in_folder.len())
+                .filter(|&page| !info.is_deleted(page))
+                .collect();
+            self.files_in_folder = kept.iter().map(|&page| self.files_in_folder[page].clone()).collect();
+            if !self.archive_entries.is_empty() {
+                self.archive_entries = kept.iter().map(|&page| self.archive_entries[page]).collect();
             }
+            self.page_types = kept
+                .iter()
+                .map(|&page| info.page_type(page).map(str::to_owned))
+                .collect();
+        }
+        self.comic_info = comic_info;
+    }
+
+    fn page_type(&self, page: usize) -> Option<&str> {
+        self.page_types.get(page).and_then(|page_type| page_type.as_deref())
+    }
+
+    fn draw_info_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_info;
+        egui::Window::new("Volume Info")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                let Some(path) = &self.current_path else {
+                    ui.label("No volume is open.");
+                    return;
+                };
+                egui::Grid::new("volume_info").num_columns(2).striped(true).show(ui, |ui| {
+                    ui.label("File");
+                    ui.label(path.display().to_string());
+                    ui.end_row();
+                    ui.label("Pages");
+                    ui.label(self.files_in_folder.len().to_string());
+                    ui.end_row();
+
+                    let Some(info) = &self.comic_info else {
+                        return;
+                    };
+                    for (name, value) in &info.fields {
+                        if name != "Summary" && !value.is_empty() {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    }
+                    if let Some(cover) = self.page_types.iter().position(|t| t.as_deref() == Some("FrontCover")) {
+                        ui.label("Front cover");
+                        ui.label(format!("Page {}", cover + 1));
+                        ui.end_row();
+                    }
+                    let deleted = info.pages.iter().filter(|page| page.page_type() == Some("Deleted")).count();
+                    if deleted > 0 {
+                        ui.label("Hidden pages");
+                        ui.label(deleted.to_string());
+                        ui.end_row();
+                    }
+                });
 
-            if let Some(extension) = path.extension() {
-                let ext = extension.to_string_lossy().to_lowercase();
-                if ["jpg", "jpeg", "png", "webp", "gif"].contains(&ext.as_str()) {
-                    println!("Adding: {}", path.display());
-                    self.files_in_folder.push(path.to_path_buf());
+                match self.comic_info.as_ref().and_then(|info| info.get("Summary")) {
+                    Some(summary) => {
+                        ui.separator();
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            ui.label(summary);
+                        });
+                    }
+                    None if self.comic_info.is_none() => {
+                        ui.separator();
+                        ui.label("This volume has no ComicInfo.xml.");
+                    }
+                    None => {}
                 }
+            });
+        self.show_info = open;
+    }
+
+    fn open_metadata_editor(&mut self) {
+        let writable = self.is_in_archive
+            && self.current_path.as_deref().is_some_and(|path| {
+                matches!(archive::detect_kind(path), Ok(archive::ArchiveKind::Zip))
+            });
+        self.metadata_editor.edit(self.comic_info.as_ref(), writable);
+    }
+
+    fn draw_metadata_editor(&mut self, ctx: &egui::Context) {
+        let archive_count = if self.is_in_archive { self.archive_files.len() } else { 0 };
+        let result = match self.metadata_editor.show(ctx, archive_count) {
+            Some(EditorAction::Save(info)) => self.save_comic_info(info, ctx),
+            Some(EditorAction::ApplyToAll(batch)) => self.apply_metadata_to_all(batch, ctx),
+            None => return,
+        };
+        if let Err(e) = result {
+            self.set_status(format!("Error saving metadata: {:#}", e), 5.0);
+        }
+        if self.metadata_editor.open {
+            self.open_metadata_editor();
+        }
+    }
+
+    // Let go of the open archive before it is rewritten. Queued pages are
+    // dropped and pages still being decoded are waited for, since they keep
+    // the file open and Windows cannot replace an open file.
+    fn close_archive(&mut self) {
+        self.reset_pages();
+        let Some(mut archive) = self.archive.take() else {
+            return;
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            match Arc::try_unwrap(archive) {
+                Ok(_) => return,
+                Err(shared) => archive = shared,
             }
+            thread::sleep(Duration::from_millis(10));
         }
+    }
 
-        println!("Found {} images", self.files_in_folder.len());
-        self.files_in_folder.sort_by(|a, b| natural_sort_paths(a, b));
+    // Change a volume on disk, carrying its reading progress over to the new
+    // fingerprint.
+    fn modify_volume(&mut self, path: &Path, modify: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+        let old_key = progress::volume_key(path).ok();
+        modify(path)?;
+        if self.rekey_volume(path, old_key) {
+            self.save_volume_keys();
+        }
         Ok(())
     }
 
-    fn load_image(&mut self, path: &Path, ctx: &egui::Context) -> Result<()> {
-        let img = image::ImageReader::open(path)
-            .with_context(|| format!("Failed to open image file: {}", path.display()))?
-            .with_guessed_format()
-            .with_context(|| format!("Failed to determine image format: {}", path.display()))?
-            .decode()
-            .with_context(|| format!("Failed to decode image: {}", path.display()))?;
+    // Move reading progress recorded under `old_key` to the current
+    // fingerprint of the volume at `path`. Returns whether anything needs
+    // saving with `save_volume_keys`.
+    fn rekey_volume(&mut self, path: &Path, old_key: Option<VolumeKey>) -> bool {
+        let (Some(old_key), Ok(new_key)) = (old_key, progress::volume_key(path)) else {
+            return false;
+        };
+        self.progress.rename_key(&old_key, &new_key);
+        for entry in &mut self.session.recent {
+            if entry.volume_key.as_ref() == Some(&old_key) {
+                entry.volume_key = Some(new_key.clone());
+            }
+        }
+        if self.volume_key.as_ref() == Some(&old_key) {
+            self.volume_key = Some(new_key);
+        }
+        true
+    }
 
-        self.set_image(img, ctx);
+    // Write out progress and the session after volumes were rekeyed.
+    fn save_volume_keys(&mut self) {
+        self.progress_changed_at = None;
+        if let Err(e) = settings::save(ProgressStore::FILE_NAME, &self.progress) {
+            eprintln!("Failed to save reading progress: {:#}", e);
+        }
+        self.save_session();
+    }
+
+    // Reopen the current archive after it was rewritten, staying on `page`.
+    fn reload_archive(&mut self, page: usize, ctx: &egui::Context) -> Result<()> {
+        let Some(path) = self.current_path.clone() else {
+            return Ok(());
+        };
+        self.load_cbz(&path, ctx)?;
+        if !self.files_in_folder.is_empty() && self.current_index != page {
+            self.current_index = page.min(self.files_in_folder.len() - 1);
+            self.load_current_page(ctx)?;
+        }
         Ok(())
     }
 
-    fn load_cbz(&mut self, path: &Path, ctx: &egui::Context) -> Result<()> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let mut archive = ZipArchive::new(reader)?;
+    fn save_comic_info(&mut self, info: ComicInfo, ctx: &egui::Context) -> Result<()> {
+        let Some(path) = self.current_path.clone().filter(|_| self.is_in_archive) else {
+            anyhow::bail!("No archive is open");
+        };
+        let page = self.current_index;
+        self.close_archive();
+        let result = self.modify_volume(&path, |path| archive::write_comic_info(path, &info));
+        self.reload_archive(page, ctx)?;
+        result?;
+        self.set_status(format!("Saved metadata to {}", path.display()), 3.0);
+        Ok(())
+    }
 
-        self.files_in_folder.clear();
-        for i in 0..archive.len() {
-            let file = archive.by_index(i)?;
-            let name = file.name().to_owned();
-            if let Some(extension) = Path::new(&name).extension() {
-                let ext = extension.to_string_lossy().to_lowercase();
-                if ["jpg", "jpeg", "png", "webp", "gif"].contains(&ext.as_str()) {
-                    self.files_in_folder.push(PathBuf::from(name));
+    // Write the batch fields into every archive next to the current one.
+    // Archives that cannot be edited are skipped and counted.
+    fn apply_metadata_to_all(&mut self, batch: BatchEdit, ctx: &egui::Context) -> Result<()> {
+        let page = self.current_index;
+        let current = self.current_archive_index;
+        self.close_archive();
+        let mut updated = 0;
+        let mut failed = 0;
+        let mut rekeyed = false;
+        for (position, path) in self.archive_files.clone().iter().enumerate() {
+            let old_key = progress::volume_key(path).ok();
+            let result = archive::open(path).and_then(|mut backend| {
+                let mut info = archive::read_comic_info(backend.as_mut()).unwrap_or_default();
+                drop(backend);
+                batch.apply(&mut info, position, current);
+                archive::write_comic_info(path, &info)
+            });
+            match result {
+                Ok(()) => {
+                    updated += 1;
+                    rekeyed |= self.rekey_volume(path, old_key);
+                }
+                Err(e) => {
+                    eprintln!("Failed to update {}: {:#}", path.display(), e);
+                    failed += 1;
                 }
             }
         }
+        // Progress and the session are written once for the whole batch.
+        if rekeyed {
+            self.save_volume_keys();
+        }
+        self.reload_archive(page, ctx)?;
+        let skipped = if failed > 0 { format!(", {} failed", failed) } else { String::new() };
+        self.set_status(format!("Updated metadata in {} archives{}", updated, skipped), 5.0);
+        Ok(())
+    }
 
-        self.files_in_folder.sort_by(|a, b| {
-            let a_name = a.to_string_lossy();
-            let b_name = b.to_string_lossy();
-            natural_sort(&a_name, &b_name)
+    fn identify_volume(&mut self) {
+        self.volume_key = self.volume_path().and_then(|volume| match progress::volume_key(&volume) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                eprintln!("Failed to fingerprint {}: {:#}", volume.display(), e);
+                None
+            }
         });
+    }
 
-        if !self.files_in_folder.is_empty() {
-            let first_image = self.files_in_folder[0].clone();
-            self.current_index = 0;
-            self.load_cbz_image(path, &first_image, ctx)?;
-            self.set_status(format!("Loaded archive with {} images", self.files_in_folder.len()), 3.0);
+    // The page to reopen the current volume at.
+    fn saved_page(&self) -> usize {
+        self.volume_key
+            .as_ref()
+            .and_then(|key| self.progress.get(key))
+            .map(|progress| progress.last_page.min(self.files_in_folder.len().saturating_sub(1)))
+            .unwrap_or(0)
+    }
+
+    fn resume_note(&self) -> String {
+        if self.current_index > 0 {
+            format!(" (resumed at page {})", self.current_index + 1)
         } else {
-            self.set_status("No images found in archive".to_string(), 3.0);
+            String::new()
         }
+    }
 
-        Ok(())
+    fn record_progress(&mut self) {
+        let Some(key) = self.volume_key.clone() else {
+            return;
+        };
+        self.progress.update(
+            &key,
+            self.current_index,
+            self.visible_page_count(),
+            self.files_in_folder.len(),
+        );
+        self.progress_changed_at.get_or_insert_with(Instant::now);
     }
 
-    fn load_cbz_image(&mut self, cbz_path: &Path, image_path: &Path, ctx: &egui::Context) -> Result<()> {
-        let file = File::open(cbz_path)?;
-        let reader = BufReader::new(file);
-        let mut archive = ZipArchive::new(reader)?;
+    // Write reading progress to disk. Unless `now` is set, this waits until
+    // the first unsaved change is a few seconds old so page turns do not
+    // each write the file.
+    fn save_progress(&mut self, now: bool, ctx: &egui::Context) {
+        let Some(changed_at) = self.progress_changed_at else {
+            return;
+        };
+        let wait = PROGRESS_SAVE_INTERVAL.saturating_sub(changed_at.elapsed());
+        if !now && !wait.is_zero() {
+            ctx.request_repaint_after(wait);
+            return;
+        }
+        self.progress_changed_at = None;
+        if let Err(e) = settings::save(ProgressStore::FILE_NAME, &self.progress) {
+            eprintln!("Failed to save reading progress: {:#}", e);
+        }
+    }
 
-        let image_name = image_path.to_string_lossy();
-        let mut file = archive.by_name(&image_name)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+    fn apply_reading_direction(&mut self, hint: Option<ReadingDirection>) {
+        self.reading_direction = match self.volume_path() {
+            Some(volume) => self.direction_settings.resolve(&volume, hint),
+            None => hint.unwrap_or_default(),
+        };
+    }
 
-        let format = match image_path.extension().and_then(|ext| ext.to_str()) {
-            Some("jpg") | Some("jpeg") => ImageFormat::Jpeg,
-            Some("png") => ImageFormat::Png,
-            Some("webp") => ImageFormat::WebP,
-            Some("gif") => ImageFormat::Gif,
-            _ => return Err(anyhow::anyhow!("Unsupported image format")),
+    fn toggle_reading_direction(&mut self) {
+        self.reading_direction = match self.reading_direction {
+            ReadingDirection::LeftToRight => ReadingDirection::RightToLeft,
+            ReadingDirection::RightToLeft => ReadingDirection::LeftToRight,
         };
+        if let Some(volume) = self.volume_path() {
+            self.direction_settings.set(&volume, self.reading_direction);
+            if let Err(e) = settings::save(DirectionSettings::FILE_NAME, &self.direction_settings) {
+                self.set_status(format!("Error saving reading direction: {}", e), 5.0);
+                return;
+            }
+        }
+        let label = match self.reading_direction {
+            ReadingDirection::LeftToRight => "left to right",
+            ReadingDirection::RightToLeft => "right to left",
+        };
+        self.set_status(format!("Reading {}", label), 2.0);
+    }
+
+    // Turn the page towards the left or right edge of the screen; which of
+    // those is "next" depends on the reading direction.
+    fn turn_page_left(&mut self, ctx: &egui::Context) -> Result<()> {
+        match self.reading_direction {
+            ReadingDirection::LeftToRight => self.previous_image(ctx),
+            ReadingDirection::RightToLeft => self.next_image(ctx),
+        }
+    }
+
+    fn turn_page_right(&mut self, ctx: &egui::Context) -> Result<()> {
+        match self.reading_direction {
+            ReadingDirection::LeftToRight => self.next_image(ctx),
+            ReadingDirection::RightToLeft => self.previous_image(ctx),
+        }
+    }
+
+    fn page_source(&self, page: usize) -> Result<PageSource> {
+        if self.is_in_archive {
+            let archive = self.archive
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("No archive is open"))?;
+            Ok(PageSource::Archive {
+                archive,
+                entry: self.archive_entries[page],
+                name: self.files_in_folder[page].to_string_lossy().into_owned(),
+            })
+        } else {
+            Ok(PageSource::File(self.files_in_folder[page].clone()))
+        }
+    }
+
+    fn page_key(&self, page: usize) -> PageKey {
+        if self.is_in_archive {
+            PageKey {
+                source: self.current_path.clone().unwrap_or_default(),
+                entry: Some(self.archive_entries[page]),
+            }
+        } else {
+            PageKey {
+                source: self.files_in_folder[page].clone(),
+                entry: None,
+            }
+        }
+    }
 
-        let img = image::load_from_memory_with_format(&buffer, format)?;
-        self.set_image(img, ctx);
+    fn request_page(&mut self, page: usize, urgent: bool) -> Result<()> {
+        if self.pending_pages.contains(&page) {
+            if urgent {
+                self.decoder.prioritize(self.generation, page);
+            }
+            return Ok(());
+        }
+        let source = self.page_source(page)?;
+        self.pending_pages.insert(page);
+        self.decoder.request(self.generation, page, source, urgent);
         Ok(())
     }
 
-    fn set_image(&mut self, img: DynamicImage, ctx: &egui::Context) {
-        let size = [img.width() as _, img.height() as _];
-        let image_buffer = img.to_rgba8();
-        let pixels = image_buffer.as_flat_samples();
-        let color_image = ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
+    fn prefetch_window(&self) -> (usize, usize) {
+        let pages = match self.page_layout {
+            PageLayout::Single | PageLayout::Continuous => self.prefetch_pages,
+            PageLayout::Spread => self.prefetch_pages * 2,
+        };
+        let first = self.current_index.saturating_sub(pages);
+        let last = (self.current_index + pages).min(self.files_in_folder.len().saturating_sub(1));
+        (first, last)
+    }
+
+    // Pages that are wider than tall are already two-page spreads.
+    fn is_wide_page(&self, page: usize) -> bool {
+        self.page_sizes
+            .get(&self.page_key(page))
+            .is_some_and(|size| size.x > size.y)
+    }
+
+    // The page shown next to `page` in spread layout, if any.
+    fn spread_partner(&self, page: usize) -> Option<usize> {
+        let partner = page + 1;
+        if self.page_layout != PageLayout::Spread
+            || partner >= self.files_in_folder.len()
+            || self.is_wide_page(page)
+            || self.is_wide_page(partner)
+        {
+            return None;
+        }
+        Some(partner)
+    }
+
+    // Number of pages currently on screen.
+    fn visible_page_count(&self) -> usize {
+        if self.spread_partner(self.current_index).is_some() { 2 } else { 1 }
+    }
+
+    // Fill the second half of the spread, or clear it if the current page
+    // is shown alone.
+    fn update_spread(&mut self, ctx: &egui::Context) {
+        self.loading_spread_page = None;
+        let old_spread = self.spread_image.take();
+
+        if let Some(partner) = self.spread_partner(self.current_index) {
+            if let Some(cached) = self.page_cache.get(&self.page_key(partner)) {
+                self.spread_image = Some(cached.texture);
+            } else {
+                self.loading_spread_page = Some(partner);
+                let _ = self.request_page(partner, true);
+            }
+        }
+
+        if self.fit_mode != FitMode::Manual && old_spread != self.spread_image {
+            self.fit_to_view(ctx);
+        }
+    }
+
+    fn prefetch_around_current(&mut self) {
+        let (first, last) = self.prefetch_window();
+
+        // Pages ahead are more likely to be needed next.
+        let ahead = self.current_index + 1..=last;
+        let behind = (first..self.current_index).rev();
+        for page in ahead.chain(behind) {
+            if !self.page_cache.contains(&self.page_key(page)) {
+                let _ = self.request_page(page, false);
+            }
+        }
+    }
+
+    // Forget all queued pages, e.g. when the page list changes. Decoded
+    // pages stay cached since they are keyed by source, not position.
+    fn reset_pages(&mut self) {
+        self.generation += 1;
+        self.decoder.cancel_pending();
+        self.pending_pages.clear();
+        self.loading_page = None;
+    }
+
+    fn poll_decoder(&mut self, ctx: &egui::Context) {
+        while let Some(decoded) = self.decoder.try_recv() {
+            if decoded.generation != self.generation {
+                continue;
+            }
+            self.pending_pages.remove(&decoded.page);
+
+            match decoded.result {
+                Ok(page) => {
+                    let cached = self.cache_page(decoded.page, page, ctx);
+                    if self.loading_page == Some(decoded.page) {
+                        self.loading_page = None;
+                        self.set_image(cached, ctx);
+                        self.update_spread(ctx);
+                    } else if self.loading_spread_page == Some(decoded.page) {
+                        self.update_spread(ctx);
+                    }
+                }
+                Err(e) if self.loading_page == Some(decoded.page) => {
+                    self.loading_page = None;
+                    self.set_status(format!("Error loading page {}: {}", decoded.page + 1, e), 5.0);
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    // Upload a decoded page to the GPU and keep it in the page cache.
+    fn cache_page(&mut self, page: usize, decoded: DecodedPage, ctx: &egui::Context) -> CachedPage {
+        let key = self.page_key(page);
+        self.page_sizes.insert(
+            key.clone(),
+            egui::vec2(decoded.image.width() as f32, decoded.image.height() as f32),
+        );
+        let texture = PageTexture::new(
+            ctx,
+            format!("page:{}#{:?}", key.source.display(), key.entry),
+            decoded.color_image,
+            decoded.mips,
+        );
+        let cached = CachedPage {
+            image: Arc::new(decoded.image),
+            texture,
+        };
+        self.page_cache.insert(key, cached.clone());
+        cached
+    }
 
-        self.current_image = Some(ctx.load_texture(
-            "current_image",
-            color_image,
-            TextureOptions::default(),
-        ));
+    fn set_image(&mut self, page: CachedPage, ctx: &egui::Context) {
+        self.current_image = Some(page.texture);
         
         // Store the image data for saving
-        self.current_image_data = Some(img);
+        self.current_image_data = Some(page.image);
 
-        if self.auto_fit {
+        if self.fit_mode != FitMode::Manual {
             self.fit_to_view(ctx);
         }
     }
 
+    // Size of everything on screen at zoom 1: the current page, plus the
+    // spread partner scaled to the same height.
+    fn displayed_size(&self) -> Option<egui::Vec2> {
+        let primary = self.current_image.as_ref()?.size_vec2();
+        Some(match &self.spread_image {
+            Some(partner) => {
+                let partner = partner.size_vec2();
+                egui::vec2(primary.x + partner.x * primary.y / partner.y, primary.y)
+            }
+            None => primary,
+        })
+    }
+
     fn fit_to_view(&mut self, ctx: &egui::Context) {
-        if let Some(image) = &self.current_image {
-            let image_size = image.size_vec2();
-            let screen_size = ctx.available_rect().size();
+        // Continuous mode always lays pages out at fit width.
+        if self.page_layout == PageLayout::Continuous {
+            self.zoom = 1.0;
+            self.offset_x = 0.0;
+            return;
+        }
+        if let Some(image_size) = self.displayed_size() {
+            let screen_size = self.view_size.unwrap_or_else(|| ctx.available_rect().size());
 
             let width_ratio = screen_size.x / image_size.x;
             let height_ratio = screen_size.y / image_size.y;
 
-            self.zoom = width_ratio.min(height_ratio) * 0.9;
+            self.zoom = match self.fit_mode {
+                FitMode::Page | FitMode::Manual => width_ratio.min(height_ratio) * 0.9,
+                FitMode::Width => width_ratio,
+                FitMode::Height => height_ratio,
+                // One image pixel per physical screen pixel.
+                FitMode::Original => 1.0 / ctx.pixels_per_point(),
+                FitMode::WidthCapped => width_ratio.min(self.fit_width_cap_percent as f32 / 100.0),
+            };
             self.offset_x = 0.0;
-            self.offset_y = 0.0;
+            // Pages taller than the view start at their top edge.
+            self.offset_y = self.vertical_overflow().max(0.0);
         }
     }
 
+    fn set_fit_mode(&mut self, mode: FitMode, ctx: &egui::Context) {
+        self.fit_mode = mode;
+        if mode != FitMode::Manual {
+            self.fit_to_view(ctx);
+        }
+    }
+
+    // How far the page sticks out above (and below) the view when centred.
+    fn vertical_overflow(&self) -> f32 {
+        match (self.displayed_size(), self.view_size) {
+            (Some(image_size), Some(view_size)) => (image_size.y * self.zoom - view_size.y) / 2.0,
+            _ => 0.0,
+        }
+    }
+
+    // Scroll a page taller than the view by `delta` points. Returns false if
+    // it was already at that edge, in which case the caller turns the page.
+    fn scroll_within_page(&mut self, delta: f32) -> bool {
+        let overflow = self.vertical_overflow();
+        if overflow <= 0.0 || self.page_layout == PageLayout::Continuous {
+            return false;
+        }
+        let offset = (self.offset_y + delta).clamp(-overflow, overflow);
+        if (offset - self.offset_y).abs() < 0.5 {
+            return false;
+        }
+        self.offset_y = offset;
+        true
+    }
+
+    // Space and the mouse wheel read down a tall page before turning it.
+    fn read_forward(&mut self, delta: f32, ctx: &egui::Context) -> Result<()> {
+        if self.scroll_within_page(-delta) {
+            return Ok(());
+        }
+        self.next_image(ctx)
+    }
+
+    fn read_backward(&mut self, delta: f32, ctx: &egui::Context) -> Result<()> {
+        if self.scroll_within_page(delta) {
+            return Ok(());
+        }
+        self.previous_image(ctx)
+    }
+
     fn load_next_archive(&mut self, ctx: &egui::Context) -> Result<bool> {
         if self.archive_files.is_empty() {
             return Ok(false);
@@ -378,7 +1266,7 @@ impl MangaReader {
             self.current_archive_index = next_index;
             self.current_path = Some(next_archive.clone());
             self.load_cbz(&next_archive, ctx)?;
-            self.set_status(format!("Loaded next archive: {}", next_archive.file_name().unwrap_or_default().to_string_lossy()), 3.0);
+            self.set_status(format!("Loaded next archive: {}{}", next_archive.file_name().unwrap_or_default().to_string_lossy(), self.resume_note()), 3.0);
             Ok(true)
         } else {
             self.set_status("No more archives to load".to_string(), 3.0);
@@ -386,45 +1274,233 @@ impl MangaReader {
         }
     }
 
-    fn delete_current_file(&mut self, ctx: &egui::Context) -> Result<()> {
-        // Cannot delete files inside archives
-        if self.is_in_archive {
-            self.set_status("Cannot delete files inside archives".to_string(), 3.0);
+    fn delete_current_file(&mut self, ctx: &egui::Context) -> Result<()> {
+        if self.files_in_folder.is_empty() {
+            return Ok(());
+        }
+        if self.is_in_archive {
+            return self.delete_archive_page(ctx);
+        }
+
+        let file_to_delete = self.files_in_folder[self.current_index].clone();
+        let key = self.page_key(self.current_index);
+        let volume = self.volume_path();
+
+        match &volume {
+            Some(volume) => self.modify_volume(volume, |_| platform::move_to_trash(&file_to_delete))?,
+            None => platform::move_to_trash(&file_to_delete)?,
+        }
+
+        let undo_hint = if platform::CAN_RESTORE_FROM_TRASH { " (Ctrl+Z to undo)" } else { "" };
+        self.set_status(
+            format!(
+                "Moved to trash: {}{}",
+                file_to_delete.file_name().unwrap_or_default().to_string_lossy(),
+                undo_hint
+            ),
+            5.0,
+        );
+
+        // Remove from the list
+        self.files_in_folder.remove(self.current_index);
+        let page_type = if self.current_index < self.page_types.len() {
+            self.page_types.remove(self.current_index)
+        } else {
+            None
+        };
+        self.deleted_files.push(DeletedFile {
+            path: file_to_delete,
+            index: self.current_index,
+            page_type,
+            volume,
+        });
+        self.page_cache.remove(&key);
+        self.reset_pages();
+
+        // Load the next image or previous if at the end
+        if !self.files_in_folder.is_empty() {
+            if self.current_index >= self.files_in_folder.len() {
+                self.current_index = self.files_in_folder.len() - 1;
+            }
+            self.load_current_page(ctx)?;
+        } else {
+            // No more images
+            self.current_image = None;
+            self.current_image_data = None;
+            self.set_status("No more images in directory".to_string(), 3.0);
+        }
+
+        Ok(())
+    }
+
+    // Restore the last file moved to the trash. When its folder is still
+    // open it goes back to its old place in the page list.
+    fn undo_delete(&mut self, ctx: &egui::Context) -> Result<()> {
+        let Some(deleted) = self.deleted_files.pop() else {
+            self.set_status("Nothing to undo".to_string(), 3.0);
+            return Ok(());
+        };
+        match &deleted.volume {
+            Some(volume) => self.modify_volume(volume, |_| platform::restore_from_trash(&deleted.path))?,
+            None => platform::restore_from_trash(&deleted.path)?,
+        }
+
+        if !self.is_in_archive && deleted.volume.is_some() && self.volume_path() == deleted.volume {
+            let index = deleted.index.min(self.files_in_folder.len());
+            // Page types are only tracked for volumes with a ComicInfo.xml.
+            if self.page_types.len() == self.files_in_folder.len() && !self.page_types.is_empty() {
+                self.page_types.insert(index, deleted.page_type);
+            }
+            self.files_in_folder.insert(index, deleted.path.clone());
+            self.current_index = index;
+            self.reset_pages();
+            self.load_current_page(ctx)?;
+        }
+        self.set_status(
+            format!("Restored: {}", deleted.path.file_name().unwrap_or_default().to_string_lossy()),
+            3.0,
+        );
+        Ok(())
+    }
+
+    // Rewrite the archive without the current page, staying at the same
+    // position so the following page takes its place.
+    fn delete_archive_page(&mut self, ctx: &egui::Context) -> Result<()> {
+        let (Some(path), Some(archive)) = (self.current_path.clone(), self.archive.as_ref()) else {
             return Ok(());
-        }
+        };
+        let page = self.current_index;
+        let name = archive.lock().unwrap().entry_names()[self.archive_entries[page]].clone();
+        self.close_archive();
+
+        let backup = self.session.backup_archives;
+        let result = self.modify_volume(&path, |path| archive::remove_page(path, &name, backup));
+        // Entry indices after the removed page have shifted.
+        self.page_cache.remove_source(&path);
+        self.page_sizes.retain(|key, _| key.source != path);
+        self.reload_archive(page, ctx)?;
+        result?;
 
         if self.files_in_folder.is_empty() {
-            return Ok(());
+            self.current_image = None;
+            self.current_image_data = None;
+            self.spread_image = None;
         }
+        self.set_status(format!("Removed {} from the archive", name), 3.0);
+        Ok(())
+    }
 
-        let file_to_delete = self.files_in_folder[self.current_index].clone();
-
-        // Delete the file from the filesystem
-        fs::remove_file(&file_to_delete)
-            .with_context(|| format!("Failed to delete file: {}", file_to_delete.display()))?;
+    // Pack the open folder of images into a CBZ chosen by the user.
+    fn pack_current_folder(&mut self) -> Result<()> {
+        let Some(dir) = self.volume_path().filter(|_| !self.is_in_archive) else {
+            anyhow::bail!("No folder is open");
+        };
+        let default_path = archive::default_pack_path(&dir);
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Comic Book Archive", &["cbz"])
+            .set_file_name(default_path.file_name().unwrap_or_default().to_string_lossy());
+        if let Some(parent) = default_path.parent() {
+            dialog = dialog.set_directory(parent);
+        }
+        let Some(output) = dialog.save_file() else {
+            return Ok(());
+        };
 
-        self.set_status(format!("Deleted: {}", file_to_delete.file_name().unwrap_or_default().to_string_lossy()), 3.0);
+        // The save dialog has already asked before replacing a file.
+        let options = PackOptions {
+            overwrite: true,
+            ..self.pack_options
+        };
+        let pages = archive::pack_folder(&dir, &output, &options)?;
+        self.set_status(format!("Packed {} pages into {}", pages, output.display()), 5.0);
+        Ok(())
+    }
 
-        // Remove from the list
-        self.files_in_folder.remove(self.current_index);
+    fn draw_pack_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_pack_dialog;
+        let mut pack = false;
+        egui::Window::new("Pack Folder as CBZ")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if let Some(dir) = self.volume_path() {
+                    ui.label(dir.display().to_string());
+                }
+                ui.label(format!("{} pages, stored uncompressed", self.files_in_folder.len()));
+                ui.checkbox(&mut self.pack_options.pad_names, "Rename pages to 001, 002, ...");
+                ui.checkbox(&mut self.pack_options.comic_info, "Embed a generated ComicInfo.xml");
+                pack = ui.button("Pack...").clicked();
+            });
+        self.show_pack_dialog = open;
 
-        // Load the next image or previous if at the end
-        if !self.files_in_folder.is_empty() {
-            if self.current_index >= self.files_in_folder.len() {
-                self.current_index = self.files_in_folder.len() - 1;
+        if pack {
+            match self.pack_current_folder() {
+                Ok(()) => self.show_pack_dialog = false,
+                Err(e) => self.set_status(format!("Error packing folder: {:#}", e), 5.0),
             }
-            let next_file = self.files_in_folder[self.current_index].clone();
-            self.load_image(&next_file, ctx)?;
-        } else {
-            // No more images
-            self.current_image = None;
-            self.current_image_data = None;
-            self.set_status("No more images in directory".to_string(), 3.0);
         }
+    }
+
+    // Write re-encoded pages into their archive, reopening it when it is the
+    // one being read.
+    // Hand the plan to the recompress window to write in the background,
+    // letting go of the archive first when it is the open one.
+    fn apply_recompress(&mut self, plan: RecompressPlan, ctx: &egui::Context) {
+        let is_current = self.is_in_archive && self.current_path.as_deref() == Some(plan.path.as_path());
+        let page = is_current.then_some(self.current_index);
+        if is_current {
+            self.close_archive();
+        }
+        self.pending_recompress = Some(PendingRecompress {
+            old_key: progress::volume_key(&plan.path).ok(),
+            page,
+        });
+        self.recompress_window.write(plan, self.session.backup_archives, ctx);
+    }
 
+    fn finish_recompress(
+        &mut self,
+        path: PathBuf,
+        saved: usize,
+        result: Result<(), String>,
+        ctx: &egui::Context,
+    ) -> Result<()> {
+        let pending = self.pending_recompress.take();
+        let page = pending.as_ref().and_then(|pending| pending.page);
+        if result.is_ok() && self.rekey_volume(&path, pending.and_then(|pending| pending.old_key)) {
+            self.save_volume_keys();
+        }
+        self.page_cache.remove_source(&path);
+        self.page_sizes.retain(|key, _| key.source != path);
+        if let Some(page) = page {
+            self.reload_archive(page, ctx)?;
+        }
+        result.map_err(|e| anyhow::anyhow!(e))?;
+        self.set_status(
+            format!("Recompressed {}, saved {}", path.display(), recompress::format_size(saved)),
+            5.0,
+        );
         Ok(())
     }
 
+    fn draw_recompress_window(&mut self, ctx: &egui::Context) {
+        let backup = self.session.backup_archives;
+        let event = self.recompress_window.show(ctx, &mut self.session.backup_archives);
+        if self.session.backup_archives != backup {
+            self.save_session();
+        }
+        match event {
+            Some(RecompressEvent::Apply(plan)) => self.apply_recompress(plan, ctx),
+            Some(RecompressEvent::Written { path, saved, result }) => {
+                if let Err(e) = self.finish_recompress(path, saved, result, ctx) {
+                    self.set_status(format!("Error recompressing archive: {:#}", e), 5.0);
+                }
+            }
+            None => {}
+        }
+    }
+
     fn save_current_image(&self) -> Result<()> {
         if let Some(img_data) = &self.current_image_data {
             // Generate default filename
@@ -462,15 +1538,17 @@ impl MangaReader {
                 .save_file()
             {
                 // Determine format from extension
-                let format = match save_path.extension().and_then(|ext| ext.to_str()) {
+                let extension = save_path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+                let format = match extension.as_deref() {
                     Some("jpg") | Some("jpeg") => ImageFormat::Jpeg,
                     Some("png") => ImageFormat::Png,
                     Some("webp") => ImageFormat::WebP,
                     _ => ImageFormat::Png, // Default to PNG
                 };
 
-                // Save the image
-                img_data.save_with_format(&save_path, format)
+                // Save the image, dropping alpha for formats without it
+                decoder::convert_for_format(img_data.as_ref().clone(), format)
+                    .save_with_format(&save_path, format)
                     .with_context(|| format!("Failed to save image to: {}", save_path.display()))?;
 
                 return Ok(());
@@ -479,12 +1557,132 @@ impl MangaReader {
         Ok(())
     }
 
+    // Show the page at `current_index`, decoding it in the background if
+    // it has not been prefetched yet.
+    fn load_current_page(&mut self, ctx: &egui::Context) -> Result<()> {
+        let page = self.current_index;
+        if let Some(cached) = self.page_cache.get(&self.page_key(page)) {
+            self.loading_page = None;
+            self.set_image(cached, ctx);
+        } else {
+            self.current_image = None;
+            self.current_image_data = None;
+            self.loading_page = Some(page);
+            self.request_page(page, true)?;
+        }
+        self.scroll_in_page = 0.0;
+        self.update_spread(ctx);
+        self.prefetch_around_current();
+        self.record_progress();
+        Ok(())
+    }
+
+    fn cycle_page_layout(&mut self, ctx: &egui::Context) {
+        self.page_layout = match self.page_layout {
+            PageLayout::Single => PageLayout::Spread,
+            PageLayout::Spread => PageLayout::Continuous,
+            PageLayout::Continuous => PageLayout::Single,
+        };
+        self.scroll_in_page = 0.0;
+        self.update_spread(ctx);
+        if self.fit_mode != FitMode::Manual || self.page_layout == PageLayout::Continuous {
+            self.fit_to_view(ctx);
+        }
+        self.prefetch_around_current();
+    }
+
+    // Height of a page in continuous mode when drawn `width` pixels wide.
+    fn continuous_page_height(&self, page: usize, width: f32) -> f32 {
+        let aspect = self
+            .page_sizes
+            .get(&self.page_key(page))
+            .map(|size| size.y / size.x)
+            .unwrap_or(CONTINUOUS_PLACEHOLDER_ASPECT);
+        width * aspect
+    }
+
+    // Scroll the continuous strip by `delta` pixels. `current_index` follows
+    // the page at the top of the view, and the strip stops at either end.
+    fn scroll_continuous(&mut self, delta: f32, width: f32, view_height: f32) {
+        let old_index = self.current_index;
+        self.scroll_in_page += delta;
+        self.normalize_continuous_scroll(width);
+
+        let mut below = -self.scroll_in_page;
+        for page in self.current_index..self.files_in_folder.len() {
+            below += self.continuous_page_height(page, width);
+            if below >= view_height {
+                break;
+            }
+        }
+        if below < view_height {
+            self.scroll_in_page -= view_height - below;
+            self.normalize_continuous_scroll(width);
+        }
+
+        if self.current_index != old_index {
+            self.sync_continuous_page();
+        }
+    }
+
+    fn normalize_continuous_scroll(&mut self, width: f32) {
+        while self.scroll_in_page < 0.0 {
+            if self.current_index == 0 {
+                self.scroll_in_page = 0.0;
+                return;
+            }
+            self.current_index -= 1;
+            self.scroll_in_page += self.continuous_page_height(self.current_index, width);
+        }
+        loop {
+            let height = self.continuous_page_height(self.current_index, width);
+            if self.scroll_in_page < height || self.current_index + 1 >= self.files_in_folder.len() {
+                return;
+            }
+            self.scroll_in_page -= height;
+            self.current_index += 1;
+        }
+    }
+
+    // Track the page at the top of the strip without resetting the view.
+    // Pages that scroll far away are left to fall out of the page cache.
+    fn sync_continuous_page(&mut self) {
+        let page = self.current_index;
+        if let Some(cached) = self.page_cache.get(&self.page_key(page)) {
+            self.loading_page = None;
+            self.current_image = Some(cached.texture);
+            self.current_image_data = Some(cached.image);
+        } else {
+            self.loading_page = Some(page);
+            let _ = self.request_page(page, true);
+        }
+        self.prefetch_around_current();
+        self.record_progress();
+    }
+
+    // Move the spread pairing by one page, e.g. to put a cover on its own.
+    fn shift_spread(&mut self, ctx: &egui::Context) -> Result<()> {
+        if self.files_in_folder.is_empty() {
+            return Ok(());
+        }
+        self.current_index = (self.current_index + 1) % self.files_in_folder.len();
+        self.load_current_page(ctx)
+    }
+
+    fn page_label(&self) -> String {
+        match self.visible_page_count() {
+            2 => format!("{}-{}", self.current_index + 1, self.current_index + 2),
+            _ => (self.current_index + 1).to_string(),
+        }
+    }
+
     fn next_image(&mut self, ctx: &egui::Context) -> Result<()> {
         if self.files_in_folder.is_empty() {
             return Ok(());
         }
 
-        if self.is_in_archive && self.current_index == self.files_in_folder.len() - 1 {
+        let step = self.visible_page_count();
+        if self.is_in_archive && self.current_index + step >= self.files_in_folder.len() {
             if self.show_last_image_alert {
                 self.show_last_image_alert = false;
                 if !self.load_next_archive(ctx)? {
@@ -499,19 +1697,8 @@ impl MangaReader {
         }
 
         self.show_last_image_alert = false;
-        self.current_index = (self.current_index + 1) % self.files_in_folder.len();
-        let path = self.files_in_folder[self.current_index].clone();
-
-        if let Some(current_path) = &self.current_path {
-            let current_path_clone = current_path.clone();
-            if self.is_in_archive {
-                self.load_cbz_image(&current_path_clone, &path, ctx)?;
-            } else {
-                self.load_image(&path, ctx)?;
-            }
-        }
-
-        Ok(())
+        self.current_index = (self.current_index + step) % self.files_in_folder.len();
+        self.load_current_page(ctx)
     }
 
     fn previous_image(&mut self, ctx: &egui::Context) -> Result<()> {
@@ -522,48 +1709,78 @@ impl MangaReader {
         self.show_last_image_alert = false;
         self.current_index = if self.current_index == 0 {
             self.files_in_folder.len() - 1
+        } else if self.page_layout == PageLayout::Spread
+            && self.current_index >= 2
+            && !self.is_wide_page(self.current_index - 1)
+            && !self.is_wide_page(self.current_index - 2)
+        {
+            self.current_index - 2
         } else {
             self.current_index - 1
         };
 
-        let path = self.files_in_folder[self.current_index].clone();
+        self.load_current_page(ctx)
+    }
 
-        if let Some(current_path) = &self.current_path {
-            let current_path_clone = current_path.clone();
-            if self.is_in_archive {
-                self.load_cbz_image(&current_path_clone, &path, ctx)?;
-            } else {
-                self.load_image(&path, ctx)?;
+    fn handle_keyboard_input(&mut self, ctx: &egui::Context) {
+        // Leave keys to text fields such as bookmark notes.
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        if ctx.input(|i| letter_pressed(i, egui::Key::B)) {
+            self.show_bookmarks = !self.show_bookmarks;
+        }
+        if ctx.input(|i| letter_pressed(i, egui::Key::I)) {
+            self.show_info = !self.show_info;
+        }
+        if ctx.input(|i| letter_pressed(i, egui::Key::L)) {
+            self.show_library = !self.show_library;
+        }
+        if ctx.input(|i| letter_pressed(i, egui::Key::E)) && self.current_path.is_some() {
+            self.open_metadata_editor();
+        }
+        // Reading shortcuts are off while browsing the library.
+        if self.show_library {
+            return;
+        }
+        if ctx.input(|i| i.key_pressed(egui::Key::Z) && i.modifiers.command) {
+            if let Err(e) = self.undo_delete(ctx) {
+                self.set_status(format!("Error restoring file: {:#}", e), 5.0);
             }
         }
 
-        Ok(())
-    }
-
-    fn handle_keyboard_input(&mut self, ctx: &egui::Context) {
         let input = ctx.input(|i| {
             (
                 i.key_pressed(egui::Key::ArrowLeft),
                 i.key_pressed(egui::Key::ArrowRight),
                 i.key_pressed(egui::Key::Plus) && i.modifiers.ctrl,
                 i.key_pressed(egui::Key::Minus) && i.modifiers.ctrl,
-                i.key_pressed(egui::Key::F),
+                letter_pressed(i, egui::Key::F),
                 i.key_pressed(egui::Key::F11),
                 i.key_pressed(egui::Key::Home),
                 i.key_pressed(egui::Key::End),
                 i.key_pressed(egui::Key::Escape),
                 i.key_pressed(egui::Key::Space),
                 i.key_pressed(egui::Key::Delete),
+                i.key_pressed(egui::Key::F12),
+                letter_pressed(i, egui::Key::D),
+                letter_pressed(i, egui::Key::S),
+                letter_pressed(i, egui::Key::R),
             )
         });
 
-        let (left, right, ctrl_plus, ctrl_minus, f_key, f11_key, home_key, end_key, escape_key, space_key, delete_key) = input;
+        let (left, right, ctrl_plus, ctrl_minus, f_key, f11_key, home_key, end_key, escape_key, space_key, delete_key, f12_key, d_key, s_key, r_key) = input;
 
+        // Right-to-left books turn forward with the left arrow.
         if left {
-            let _ = self.previous_image(ctx);
+            let _ = self.turn_page_left(ctx);
+        }
+        if right {
+            let _ = self.turn_page_right(ctx);
         }
-        if right || space_key {
-            let _ = self.next_image(ctx);
+        if space_key {
+            let page_height = self.view_size.map_or(0.0, |size| size.y * 0.9);
+            let _ = self.read_forward(page_height, ctx);
         }
         if ctrl_plus {
             self.zoom *= 1.2;
@@ -585,51 +1802,99 @@ impl MangaReader {
         }
         if home_key && !self.files_in_folder.is_empty() {
             self.current_index = 0;
-            let path = self.files_in_folder[self.current_index].clone();
-            if let Some(current_path) = &self.current_path {
-                let current_path_clone = current_path.clone();
-                if current_path.extension().map_or(false, |ext| {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    ext_str == "cbz" || ext_str == "zip"
-                }) {
-                    let _ = self.load_cbz_image(&current_path_clone, &path, ctx);
-                } else {
-                    let _ = self.load_image(&path, ctx);
-                }
-            }
+            let _ = self.load_current_page(ctx);
         }
         if end_key && !self.files_in_folder.is_empty() {
             self.current_index = self.files_in_folder.len() - 1;
-            let path = self.files_in_folder[self.current_index].clone();
-            if let Some(current_path) = &self.current_path {
-                let current_path_clone = current_path.clone();
-                if current_path.extension().map_or(false, |ext| {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    ext_str == "cbz" || ext_str == "zip"
-                }) {
-                    let _ = self.load_cbz_image(&current_path_clone, &path, ctx);
-                } else {
-                    let _ = self.load_image(&path, ctx);
-                }
-            }
+            let _ = self.load_current_page(ctx);
         }
         if escape_key && self.fullscreen {
             self.fullscreen = false;
             ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
         }
+        if f12_key {
+            self.show_debug_overlay = !self.show_debug_overlay;
+        }
+        if d_key {
+            self.cycle_page_layout(ctx);
+        }
+        if s_key && self.page_layout == PageLayout::Spread {
+            let _ = self.shift_spread(ctx);
+        }
+        if r_key {
+            self.toggle_reading_direction();
+        }
+
+        let fit_key = ctx.input(|i| {
+            [
+                (egui::Key::P, FitMode::Page),
+                (egui::Key::W, FitMode::Width),
+                (egui::Key::H, FitMode::Height),
+                (egui::Key::O, FitMode::Original),
+                (egui::Key::C, FitMode::WidthCapped),
+                (egui::Key::M, FitMode::Manual),
+            ]
+            .into_iter()
+            .find(|(key, _)| letter_pressed(i, *key))
+            .map(|(_, mode)| mode)
+        });
+        if let Some(mode) = fit_key {
+            self.set_fit_mode(mode, ctx);
+        }
+    }
+
+    fn draw_debug_overlay(&mut self, ctx: &egui::Context) {
+        let stats = self.page_cache.stats();
+        let used_mb = self.page_cache.used_bytes() as f32 / (1024.0 * 1024.0);
+        let mut budget_mb = self.page_cache.budget_mb();
+
+        egui::Window::new("Debug")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 40.0))
+            .show(ctx, |ui| {
+                egui::Grid::new("debug_stats").num_columns(2).show(ui, |ui| {
+                    ui.label("Cached pages");
+                    ui.label(self.page_cache.len().to_string());
+                    ui.end_row();
+                    ui.label("Cache memory");
+                    ui.label(format!("{:.1} / {} MB", used_mb, budget_mb));
+                    ui.end_row();
+                    ui.label("Hits / misses");
+                    ui.label(format!("{} / {}", stats.hits, stats.misses));
+                    ui.end_row();
+                    ui.label("Evictions");
+                    ui.label(stats.evictions.to_string());
+                    ui.end_row();
+                    ui.label("Pending decodes");
+                    ui.label(self.pending_pages.len().to_string());
+                    ui.end_row();
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Cache budget (MB):");
+                    ui.add(egui::DragValue::new(&mut budget_mb).range(64..=16384).speed(16));
+                });
+            });
+
+        if budget_mb != self.page_cache.budget_mb() {
+            self.page_cache.set_budget_mb(budget_mb);
+        }
     }
 }
 
 impl App for MangaReader {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-        if let Some(path) = self.current_path.clone() {
-            if self.current_image.is_none() {
-                if let Err(e) = self.open_file(&path, ctx) {
-                    self.set_status(format!("Error opening file: {}", e), 5.0);
-                }
+        if let Some(path) = self.pending_open.take() {
+            if let Err(e) = self.open_file(&path, ctx) {
+                self.set_status(format!("Error opening file: {}", e), 5.0);
             }
+            self.restore_session_page(ctx);
         }
+        self.track_window(ctx);
+        self.save_progress(false, ctx);
 
+        self.poll_decoder(ctx);
         self.handle_keyboard_input(ctx);
 
         if let Some((_, ref mut duration)) = self.status_message {
@@ -647,12 +1912,21 @@ impl App for MangaReader {
                 .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
                 .show(ctx, |ui| {
                     ui.vertical_centered(|ui| {
-                        ui.label("Are you sure you want to delete this file?");
+                        if self.is_in_archive {
+                            ui.label("Are you sure you want to remove this page from the archive?");
+                        } else {
+                            ui.label("Move this file to the trash?");
+                        }
                         if let Some(path) = &self.pending_delete_path {
                             ui.add_space(10.0);
                             ui.label(format!("{}", path.file_name().unwrap_or_default().to_string_lossy()));
                             ui.add_space(10.0);
                         }
+                        if self.is_in_archive
+                            && ui.checkbox(&mut self.session.backup_archives, "Keep a backup (.bak)").changed()
+                        {
+                            self.save_session();
+                        }
                         ui.horizontal(|ui| {
                             if ui.button("Yes, Delete").clicked() {
                                 if let Err(e) = self.delete_current_file(ctx) {
@@ -670,6 +1944,29 @@ impl App for MangaReader {
                 });
         }
 
+        if self.show_debug_overlay {
+            self.draw_debug_overlay(ctx);
+        }
+        if self.show_info {
+            self.draw_info_window(ctx);
+        }
+        if self.metadata_editor.open {
+            self.draw_metadata_editor(ctx);
+        }
+        if self.show_pack_dialog {
+            self.draw_pack_dialog(ctx);
+        }
+        if self.recompress_window.open || self.recompress_window.is_writing() {
+            self.draw_recompress_window(ctx);
+        }
+        if self.verify_window.open {
+            if let Some(path) = self.verify_window.show(ctx) {
+                if let Err(e) = self.open_file(&path, ctx) {
+                    self.set_status(format!("Error opening file: {}", e), 5.0);
+                }
+            }
+        }
+
         if self.show_last_image_alert {
             egui::Window::new("Last Image")
                 .collapsible(false)
@@ -700,7 +1997,7 @@ impl App for MangaReader {
                 ui.horizontal(|ui| {
                     if ui.button("Open File").clicked() {
                         if let Some(path) = rfd::FileDialog::new()
-                            .add_filter("Comics & Images", &["jpg", "jpeg", "png", "webp", "gif", "cbz", "zip"])
+                            .add_filter("Comics & Images", &Self::supported_extensions())
                             .pick_file()
                         {
                             if let Err(e) = self.open_file(&path, ctx) {
@@ -709,19 +2006,116 @@ impl App for MangaReader {
                         }
                     }
 
+                    let mut open_recent = None;
+                    ui.menu_button("Recent", |ui| {
+                        if self.session.recent.is_empty() {
+                            ui.label("No recent files");
+                        }
+                        for entry in &self.session.recent {
+                            let name = entry
+                                .path
+                                .file_name()
+                                .map(|name| name.to_string_lossy().into_owned())
+                                .unwrap_or_else(|| entry.path.display().to_string());
+                            let progress = entry.volume_key.as_ref().and_then(|key| self.progress.get(key));
+                            let label = match progress {
+                                Some(progress) if progress.completed => format!("{} (read)", name),
+                                Some(progress) => {
+                                    format!("{} ({}/{})", name, progress.last_page + 1, progress.total_pages)
+                                }
+                                None => name,
+                            };
+                            if ui.button(label).on_hover_text(entry.path.display().to_string()).clicked() {
+                                open_recent = Some(entry.path.clone());
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        if ui.button("Clear Recent").clicked() {
+                            self.session.recent.clear();
+                            self.save_session();
+                            ui.close_menu();
+                        }
+                    });
+                    if let Some(path) = open_recent {
+                        if let Err(e) = self.open_file(&path, ctx) {
+                            self.set_status(format!("Error: {}", e), 5.0);
+                        }
+                    }
+
+                    let library_text = if self.show_library { "Reader (L)" } else { "Library (L)" };
+                    if ui.button(library_text).clicked() {
+                        self.show_library = !self.show_library;
+                    }
+                    if ui.selectable_label(self.show_bookmarks, "Bookmarks (B)").clicked() {
+                        self.show_bookmarks = !self.show_bookmarks;
+                    }
+                    if ui.selectable_label(self.show_info, "Info (I)").clicked() {
+                        self.show_info = !self.show_info;
+                    }
+                    ui.menu_button("Tools", |ui| {
+                        let has_volume = self.current_path.is_some();
+                        if ui.add_enabled(has_volume, egui::Button::new("Edit Metadata... (E)")).clicked() {
+                            self.open_metadata_editor();
+                            ui.close_menu();
+                        }
+                        let is_folder = self.current_path.is_some() && !self.is_in_archive;
+                        if ui.add_enabled(is_folder, egui::Button::new("Pack Folder as CBZ...")).clicked() {
+                            self.show_pack_dialog = true;
+                            ui.close_menu();
+                        }
+                        let archive_path = self.current_path.clone().filter(|_| self.is_in_archive);
+                        if ui.add_enabled(archive_path.is_some(), egui::Button::new("Recompress Archive...")).clicked() {
+                            if let Some(path) = archive_path {
+                                self.recompress_window.open_for(path);
+                            }
+                            ui.close_menu();
+                        }
+                        if ui.button("Verify Archives...").clicked() {
+                            let mut dialog = rfd::FileDialog::new();
+                            if let Some(dir) = self.volume_path().and_then(|volume| volume.parent().map(Path::to_path_buf)) {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            if let Some(root) = dialog.pick_folder() {
+                                self.verify_window.start(root, ctx);
+                            }
+                            ui.close_menu();
+                        }
+                        let can_undo = platform::CAN_RESTORE_FROM_TRASH && !self.deleted_files.is_empty();
+                        if ui.add_enabled(can_undo, egui::Button::new("Undo Delete (Ctrl+Z)")).clicked() {
+                            if let Err(e) = self.undo_delete(ctx) {
+                                self.set_status(format!("Error restoring file: {:#}", e), 5.0);
+                            }
+                            ui.close_menu();
+                        }
+                    });
+
                     ui.separator();
 
-                    if ui.button("Previous (<-)").clicked() {
-                        if let Err(e) = self.previous_image(ctx) {
+                    // The left button always turns towards the left edge.
+                    let (left_text, right_text) = match self.reading_direction {
+                        ReadingDirection::LeftToRight => ("Previous (<-)", "Next (->)"),
+                        ReadingDirection::RightToLeft => ("Next (<-)", "Previous (->)"),
+                    };
+                    if ui.button(left_text).clicked() {
+                        if let Err(e) = self.turn_page_left(ctx) {
                             self.set_status(format!("Error: {}", e), 5.0);
                         }
                     }
-                    if ui.button("Next (->)").clicked() {
-                        if let Err(e) = self.next_image(ctx) {
+                    if ui.button(right_text).clicked() {
+                        if let Err(e) = self.turn_page_right(ctx) {
                             self.set_status(format!("Error: {}", e), 5.0);
                         }
                     }
 
+                    let direction_text = match self.reading_direction {
+                        ReadingDirection::LeftToRight => "Direction: LTR (R)",
+                        ReadingDirection::RightToLeft => "Direction: RTL (R)",
+                    };
+                    if ui.button(direction_text).clicked() {
+                        self.toggle_reading_direction();
+                    }
+
                     ui.separator();
 
                     if ui.button("Zoom In (+)").clicked() {
@@ -734,20 +2128,44 @@ impl App for MangaReader {
                         self.fit_to_view(ctx);
                     }
 
-                    let auto_fit_text = if self.auto_fit {
-                        "Auto-fit: ON"
-                    } else {
-                        "Auto-fit: OFF"
-                    };
-                    if ui.button(auto_fit_text).clicked() {
-                        self.auto_fit = !self.auto_fit;
-                        if self.auto_fit {
+                    let mut fit_mode = self.fit_mode;
+                    egui::ComboBox::from_id_salt("fit_mode")
+                        .selected_text(fit_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in FitMode::ALL {
+                                ui.selectable_value(&mut fit_mode, mode, mode.label());
+                            }
+                        });
+                    if fit_mode != self.fit_mode {
+                        self.set_fit_mode(fit_mode, ctx);
+                    }
+                    if self.fit_mode == FitMode::WidthCapped {
+                        let cap = egui::DragValue::new(&mut self.fit_width_cap_percent)
+                            .range(10..=400)
+                            .suffix("%");
+                        if ui.add(cap).changed() {
                             self.fit_to_view(ctx);
                         }
                     }
 
                     ui.separator();
 
+                    let layout_text = match self.page_layout {
+                        PageLayout::Single => "Layout: Single (D)",
+                        PageLayout::Spread => "Layout: Spread (D)",
+                        PageLayout::Continuous => "Layout: Continuous (D)",
+                    };
+                    if ui.button(layout_text).clicked() {
+                        self.cycle_page_layout(ctx);
+                    }
+                    if self.page_layout == PageLayout::Spread && ui.button("Shift Spread (S)").clicked() {
+                        if let Err(e) = self.shift_spread(ctx) {
+                            self.set_status(format!("Error: {}", e), 5.0);
+                        }
+                    }
+
+                    ui.separator();
+
                     if ui.button("Save Image As...").clicked() {
                         if let Err(e) = self.save_current_image() {
                             self.set_status(format!("Error saving image: {}", e), 5.0);
@@ -765,15 +2183,22 @@ impl App for MangaReader {
                 });
             });
 
+            if self.show_bookmarks {
+                self.draw_bookmarks_panel(ctx);
+            }
+
             egui::CentralPanel::default().show(ctx, |ui| {
                 egui::TopBottomPanel::bottom("status_bar").show_inside(ui, |ui| {
                     ui.horizontal(|ui| {
                         if !self.files_in_folder.is_empty() {
                             ui.label(format!(
                                 "Image {}/{}",
-                                self.current_index + 1,
+                                self.page_label(),
                                 self.files_in_folder.len()
                             ));
+                            if let Some(page_type) = self.page_type(self.current_index).filter(|t| *t != "Story") {
+                                ui.label(format!("[{}]", page_type));
+                            }
                             if let Some(path) = self.files_in_folder.get(self.current_index) {
                                 ui.separator();
                                 ui.label(path.file_name().unwrap_or_default().to_string_lossy().to_string());
@@ -790,10 +2215,18 @@ impl App for MangaReader {
                     });
                 });
 
-                self.draw_image_view(ui, ctx);
+                if self.show_library {
+                    self.draw_library(ui, ctx);
+                } else {
+                    self.draw_image_view(ui, ctx);
+                }
             });
         } else {
             egui::CentralPanel::default().show(ctx, |ui| {
+                if self.show_library {
+                    self.draw_library(ui, ctx);
+                    return;
+                }
                 self.draw_image_view(ui, ctx);
                 ui.allocate_space(ui.available_size());
 
@@ -806,7 +2239,7 @@ impl App for MangaReader {
                             ui.horizontal(|ui| {
                                 ui.label(format!(
                                     "Image {}/{} | Zoom: {:.0}% | Press ESC to exit fullscreen",
-                                    self.current_index + 1,
+                                    self.page_label(),
                                     self.files_in_folder.len(),
                                     self.zoom * 100.0
                                 ));
@@ -821,16 +2254,81 @@ impl App for MangaReader {
                 }
             });
         }
+
+        // Only tiles drawn this frame stay on the GPU.
+        let pass = ctx.cumulative_pass_nr();
+        self.page_cache.release_unused_tiles(pass);
+        for texture in self.current_image.iter().chain(&self.spread_image) {
+            texture.release_unused_tiles(pass);
+        }
     }
 }
 
 impl MangaReader {
+    fn draw_library(&mut self, ui: &mut Ui, ctx: &egui::Context) {
+        match self.library.show(ui, ctx) {
+            Ok(Some(path)) => {
+                if let Err(e) = self.open_file(&path, ctx) {
+                    self.set_status(format!("Error: {}", e), 5.0);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => self.set_status(format!("Error: {}", e), 5.0),
+        }
+    }
+
+    fn draw_continuous_view(&mut self, ui: &mut Ui, ctx: &egui::Context, view_rect: Rect, response: &egui::Response) {
+        let width = view_rect.width() * self.zoom;
+        let x = view_rect.center().x - width / 2.0 + self.offset_x;
+        let painter = ui.painter_at(view_rect);
+
+        let mut y = view_rect.min.y - self.scroll_in_page;
+        let mut page = self.current_index;
+        while page < self.files_in_folder.len() && y < view_rect.max.y {
+            let height = self.continuous_page_height(page, width);
+            let page_rect = Rect::from_min_size(egui::pos2(x, y), egui::vec2(width, height));
+
+            match self.page_cache.peek(&self.page_key(page)) {
+                Some(cached) => {
+                    cached.texture.paint(&painter, page_rect);
+                }
+                None => {
+                    let _ = self.request_page(page, true);
+                    painter.rect_filled(page_rect, 0.0, Color32::from_gray(32));
+                    painter.text(
+                        page_rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        format!("Loading page {}...", page + 1),
+                        egui::FontId::proportional(16.0),
+                        Color32::GRAY,
+                    );
+                }
+            }
+
+            y += height;
+            page += 1;
+        }
+
+        if response.double_clicked() {
+            self.fullscreen = !self.fullscreen;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(self.fullscreen));
+        }
+    }
+
     fn draw_image_view(&mut self, ui: &mut Ui, ctx: &egui::Context) {
         let available_size = ui.available_size();
         let image_rect = Rect::from_min_size(ui.cursor().min, available_size);
 
         let response = ui.allocate_rect(image_rect, Sense::drag() | Sense::click());
 
+        // Refit on window resize or when panels change the view size.
+        if self.view_size != Some(image_rect.size()) {
+            self.view_size = Some(image_rect.size());
+            if self.fit_mode != FitMode::Manual {
+                self.fit_to_view(ctx);
+            }
+        }
+
         // Handle right-click context menu
         response.context_menu(|ui| {
             if ui.button("Save Image As...").clicked() {
@@ -851,7 +2349,11 @@ impl MangaReader {
             if let (Some(last_pos), Some(hover_pos)) = (self.last_pos, response.hover_pos()) {
                 let delta = hover_pos - last_pos;
                 self.offset_x += delta.x;
-                self.offset_y += delta.y;
+                if self.page_layout == PageLayout::Continuous {
+                    self.scroll_continuous(-delta.y, image_rect.width() * self.zoom, image_rect.height());
+                } else {
+                    self.offset_y += delta.y;
+                }
                 self.last_pos = response.hover_pos();
             }
         } else if response.drag_stopped() {
@@ -860,7 +2362,21 @@ impl MangaReader {
             self.last_pos = None;
         }
 
-        let (scroll, ctrl_held) = ctx.input(|i| (i.raw_scroll_delta.y, i.modifiers.ctrl));
+        let (scroll, smooth_scroll, ctrl_held) =
+            ctx.input(|i| (i.raw_scroll_delta.y, i.smooth_scroll_delta.y, i.modifiers.ctrl));
+
+        if self.page_layout == PageLayout::Continuous && !self.files_in_folder.is_empty() {
+            if ctrl_held {
+                if scroll != 0.0 {
+                    let zoom_factor = if scroll > 0.0 { 1.1 } else { 0.9 };
+                    self.zoom = (self.zoom * zoom_factor).clamp(0.1, 10.0);
+                }
+            } else if smooth_scroll != 0.0 {
+                self.scroll_continuous(-smooth_scroll, image_rect.width() * self.zoom, image_rect.height());
+            }
+            self.draw_continuous_view(ui, ctx, image_rect, &response);
+            return;
+        }
 
         if scroll != 0.0 {
             if ctrl_held {
@@ -882,19 +2398,18 @@ impl MangaReader {
                 }
             } else {
                 if scroll > 0.0 {
-                    if let Err(e) = self.previous_image(ctx) {
+                    if let Err(e) = self.read_backward(scroll, ctx) {
                         self.set_status(format!("Error: {}", e), 5.0);
                     }
                 } else {
-                    if let Err(e) = self.next_image(ctx) {
+                    if let Err(e) = self.read_forward(-scroll, ctx) {
                         self.set_status(format!("Error: {}", e), 5.0);
                     }
                 }
             }
         }
 
-        if let Some(image) = &self.current_image {
-            let original_size = image.size_vec2();
+        if let (Some(image), Some(original_size)) = (&self.current_image, self.displayed_size()) {
             let scaled_size = original_size * self.zoom;
 
             let center_x = image_rect.center().x;
@@ -905,22 +2420,62 @@ impl MangaReader {
                 center_y - scaled_size.y / 2.0 + self.offset_y,
             );
 
-            let image_rect = Rect::from_min_size(
-                position,
-                scaled_size,
-            );
+            let full_rect = Rect::from_min_size(position, scaled_size);
+            let primary_width = image.size_vec2().x * self.zoom;
+
+            // Right-to-left spreads put the earlier page on the right.
+            let (primary_rect, partner_rect) = match self.reading_direction {
+                ReadingDirection::LeftToRight => {
+                    let split = full_rect.min.x + primary_width;
+                    (
+                        Rect::from_x_y_ranges(full_rect.min.x..=split, full_rect.y_range()),
+                        Rect::from_x_y_ranges(split..=full_rect.max.x, full_rect.y_range()),
+                    )
+                }
+                ReadingDirection::RightToLeft => {
+                    let split = full_rect.max.x - primary_width;
+                    (
+                        Rect::from_x_y_ranges(split..=full_rect.max.x, full_rect.y_range()),
+                        Rect::from_x_y_ranges(full_rect.min.x..=split, full_rect.y_range()),
+                    )
+                }
+            };
 
-            ui.painter().image(
-                image.id(),
-                image_rect,
-                Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
-                Color32::WHITE,
-            );
+            image.paint(ui.painter(), primary_rect);
+
+            if let Some(partner) = &self.spread_image {
+                partner.paint(ui.painter(), partner_rect);
+            }
 
             if response.double_clicked() {
                 self.fullscreen = !self.fullscreen;
                 ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(self.fullscreen));
+            } else if response.clicked() {
+                // Clicking the outer thirds of the view turns the page
+                // towards that side.
+                if let Some(pos) = response.interact_pointer_pos() {
+                    let zone = (pos.x - image_rect.min.x) / image_rect.width();
+                    let result = if zone < 1.0 / 3.0 {
+                        self.turn_page_left(ctx)
+                    } else if zone > 2.0 / 3.0 {
+                        self.turn_page_right(ctx)
+                    } else {
+                        Ok(())
+                    };
+                    if let Err(e) = result {
+                        self.set_status(format!("Error: {}", e), 5.0);
+                    }
+                }
             }
+        } else if let Some(page) = self.loading_page {
+            ui.put(image_rect, |ui: &mut Ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(image_rect.height() / 2.0 - 20.0);
+                    ui.spinner();
+                    ui.label(format!("Loading page {}...", page + 1));
+                })
+                .response
+            });
         } else {
             ui.centered_and_justified(|ui| {
                 ui.vertical_centered(|ui| {
@@ -932,7 +2487,7 @@ impl MangaReader {
                     ui.horizontal(|ui| {
                         if ui.button("Open File").clicked() {
                             if let Some(path) = rfd::FileDialog::new()
-                                .add_filter("Comics & Images", &["jpg", "jpeg", "png", "webp", "gif", "cbz", "zip"])
+                                .add_filter("Comics & Images", &Self::supported_extensions())
                                 .pick_file()
                             {
                                 if let Err(e) = self.open_file(&path, ctx) {
@@ -957,12 +2512,26 @@ impl MangaReader {
                         ui.label("F: Fit image to view");
                         ui.label("F11: Toggle fullscreen");
                         ui.label("Home/End: First/Last image");
-                        ui.label("Space: Next image");
-                        ui.label("Delete: Delete current image");
+                        ui.label("Space: Scroll down, then next image");
+                        ui.label("P/W/H/O/C/M: Fit page/width/height, original size, capped width, manual");
+                        ui.label("Delete: Move current image to the trash, or remove the page from a CBZ");
+                        if platform::CAN_RESTORE_FROM_TRASH {
+                            ui.label("Ctrl+Z: Restore the last image moved to the trash");
+                        } else {
+                            ui.label("Images moved to the trash cannot be restored from here on this system");
+                        }
                         ui.label("Escape: Exit fullscreen");
+                        ui.label("L: Toggle library view");
+                        ui.label("B: Toggle bookmarks panel");
+                        ui.label("I: Toggle volume info");
+                        ui.label("F12: Toggle debug overlay");
+                        ui.label("D: Cycle single page, two-page spread and continuous scroll layouts");
+                        ui.label("S: Shift spread pairing by one page");
+                        ui.label("R: Toggle left-to-right/right-to-left reading");
                         ui.label("Mouse drag: Pan image");
-                        ui.label("Mouse wheel: Navigate images");
+                        ui.label("Mouse wheel: Scroll tall pages, then navigate images");
                         ui.label("Ctrl+Mouse wheel: Zoom in/out");
+                        ui.label("Click left/right edge: Turn page towards that side");
                         ui.label("Double click: Toggle fullscreen");
                         ui.label("Right click: Save image as...");
                     });
@@ -972,6 +2541,12 @@ impl MangaReader {
     }
 }
 
+// Single-letter shortcuts do not fire while a modifier is held, so that
+// e.g. Ctrl+S is not also taken as S.
+fn letter_pressed(input: &egui::InputState, key: egui::Key) -> bool {
+    input.key_pressed(key) && !input.modifiers.any()
+}
+
 fn load_icon() -> Option<IconData> {
     let icon_bytes = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/icon.png"));
     match image::load_from_memory(icon_bytes) {
@@ -995,10 +2570,24 @@ fn load_icon() -> Option<IconData> {
 fn main() -> Result<()> {
     env_logger::init();
 
+    let cli = cli::Cli::parse();
+    if let Some(command) = cli.command {
+        let code = cli::run(command)?;
+        std::process::exit(code);
+    }
+
+    let session: Session = settings::load(Session::FILE_NAME);
+    let window = session.window;
+
     let mut viewport = egui::ViewportBuilder::default()
-        .with_inner_size([1920.0, 1080.0])
+        .with_inner_size(window.size.unwrap_or([1920.0, 1080.0]))
         .with_title("Manga Reader")
-        .with_maximized(true);
+        .with_maximized(window.maximized)
+        .with_fullscreen(session.fullscreen);
+
+    if let Some(position) = window.position {
+        viewport = viewport.with_position(position);
+    }
 
     if let Some(icon) = load_icon() {
         viewport = viewport.with_icon(icon);
@@ -1012,6 +2601,23 @@ fn main() -> Result<()> {
     run_native(
         "Manga Reader",
         native_options,
-        Box::new(|cc| Ok(Box::new(MangaReader::new(cc)))),
+        Box::new(|cc| Ok(Box::new(MangaReader::new(cc, session, cli.path)))),
     ).map_err(|e| anyhow::anyhow!("Failed to start application: {}", e))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_numbers_by_value_and_letters_without_case() {
+        let mut names = vec!["page10.png", "Page2.png", "page1.png", "page02b.png", "cover.png", "page.png", "page1"];
+        names.sort_by(|a, b| natural_sort(a, b));
+        assert_eq!(names, ["cover.png", "page.png", "page1", "page1.png", "Page2.png", "page02b.png", "page10.png"]);
+        assert_eq!(natural_sort("Vol 01", "vol 1"), Ordering::Equal);
+        assert_eq!(
+            natural_sort_paths(Path::new("b/chapter 9"), Path::new("a/chapter 10")),
+            Ordering::Less
+        );
+    }
+}
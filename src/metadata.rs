@@ -0,0 +1,274 @@
+use eframe::egui;
+
+use crate::comicinfo::ComicInfo;
+
+// ComicInfo fields offered by the editor, in schema order. Anything else in
+// the file, including the page list, is kept untouched.
+const EDITABLE_FIELDS: &[&str] = &[
+    "Title", "Series", "Number", "Count", "Volume", "Summary", "Year", "Month", "Writer",
+    "Penciller", "Inker", "Colorist", "Letterer", "CoverArtist", "Editor", "Publisher", "Genre",
+    "Tags", "Web", "LanguageISO", "Manga", "AgeRating",
+];
+const MANGA_VALUES: &[&str] = &["", "Unknown", "No", "Yes", "YesAndRightToLeft"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VolumeNumbering {
+    Unchanged,
+    // Every archive gets the same Volume.
+    Same(String),
+    // Archives are numbered in folder order, the edited archive getting the
+    // given value.
+    Sequential(u32),
+}
+
+// Fields written to every archive of the folder.
+#[derive(Debug, Clone)]
+pub struct BatchEdit {
+    pub series: Option<String>,
+    pub volume: VolumeNumbering,
+}
+
+impl BatchEdit {
+    // Apply to the archive at `position` in the folder listing, where the
+    // edited archive is at `current`. Archives that would be numbered below
+    // zero keep their Volume.
+    pub fn apply(&self, info: &mut ComicInfo, position: usize, current: usize) {
+        if let Some(series) = &self.series {
+            info.set("Series", series);
+        }
+        match &self.volume {
+            VolumeNumbering::Unchanged => {}
+            VolumeNumbering::Same(volume) => info.set("Volume", volume),
+            VolumeNumbering::Sequential(number) => {
+                if let Some(volume) = (*number as usize + position).checked_sub(current) {
+                    info.set("Volume", &volume.to_string());
+                }
+            }
+        }
+    }
+}
+
+pub enum EditorAction {
+    Save(ComicInfo),
+    ApplyToAll(BatchEdit),
+}
+
+pub struct MetadataEditor {
+    pub open: bool,
+    // The volume's ComicInfo as loaded, so unedited parts survive a save.
+    info: ComicInfo,
+    // False when the open volume is not a CBZ.
+    writable: bool,
+    values: Vec<String>,
+    batch_series: bool,
+    batch_volume: VolumeNumbering,
+}
+
+impl MetadataEditor {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            info: ComicInfo::default(),
+            writable: false,
+            values: vec![String::new(); EDITABLE_FIELDS.len()],
+            batch_series: false,
+            batch_volume: VolumeNumbering::Unchanged,
+        }
+    }
+
+    // Start editing the given metadata, or a blank ComicInfo.
+    pub fn edit(&mut self, info: Option<&ComicInfo>, writable: bool) {
+        self.info = info.cloned().unwrap_or_default();
+        self.writable = writable;
+        self.values = EDITABLE_FIELDS
+            .iter()
+            .map(|name| self.info.get(name).unwrap_or_default().to_string())
+            .collect();
+        self.open = true;
+    }
+
+    fn edited_info(&self) -> ComicInfo {
+        let mut info = self.info.clone();
+        for (name, value) in EDITABLE_FIELDS.iter().zip(&self.values) {
+            info.set(name, value);
+        }
+        info
+    }
+
+    fn value(&self, name: &str) -> &str {
+        EDITABLE_FIELDS
+            .iter()
+            .position(|field| *field == name)
+            .map(|index| self.values[index].trim())
+            .unwrap_or_default()
+    }
+
+    // The chosen Volume numbering, following edits to the Volume field. A
+    // blank Volume would remove it from every archive, and only whole numbers
+    // can be counted on from, so either falls back to Unchanged.
+    fn batch_volume(&self) -> VolumeNumbering {
+        let volume = self.value("Volume");
+        match self.batch_volume {
+            VolumeNumbering::Same(_) if !volume.is_empty() => VolumeNumbering::Same(volume.to_string()),
+            VolumeNumbering::Sequential(_) => volume.parse().map_or(VolumeNumbering::Unchanged, VolumeNumbering::Sequential),
+            _ => VolumeNumbering::Unchanged,
+        }
+    }
+
+    fn batch_edit(&self) -> BatchEdit {
+        let series = self.value("Series");
+        BatchEdit {
+            series: (self.batch_series && !series.is_empty()).then(|| series.to_string()),
+            volume: self.batch_volume(),
+        }
+    }
+
+    // `archive_count` is the number of archives in the volume's folder.
+    pub fn show(&mut self, ctx: &egui::Context, archive_count: usize) -> Option<EditorAction> {
+        let mut action = None;
+        let can_save = self.writable;
+        let mut open = self.open;
+
+        egui::Window::new("Edit Metadata")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().max_height(420.0).show(ui, |ui| {
+                    egui::Grid::new("metadata_fields").num_columns(2).show(ui, |ui| {
+                        for (name, value) in EDITABLE_FIELDS.iter().zip(self.values.iter_mut()) {
+                            ui.label(*name);
+                            match *name {
+                                "Summary" => {
+                                    ui.add(egui::TextEdit::multiline(value).desired_rows(3).desired_width(280.0));
+                                }
+                                "Manga" => {
+                                    egui::ComboBox::from_id_salt("metadata_manga")
+                                        .selected_text(value.as_str())
+                                        .show_ui(ui, |ui| {
+                                            for option in MANGA_VALUES {
+                                                ui.selectable_value(value, option.to_string(), *option);
+                                            }
+                                        });
+                                }
+                                _ => {
+                                    ui.add(egui::TextEdit::singleline(value).desired_width(280.0));
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                ui.separator();
+                if !can_save {
+                    ui.label("Metadata can only be written to CBZ/ZIP archives.");
+                }
+                if ui.add_enabled(can_save, egui::Button::new("Save")).clicked() {
+                    action = Some(EditorAction::Save(self.edited_info()));
+                }
+
+                ui.separator();
+                ui.label(format!("Apply to all {} archives in this folder:", archive_count));
+                let series = self.value("Series").to_string();
+                // A blank Series would remove it from every archive.
+                ui.add_enabled(
+                    !series.is_empty(),
+                    egui::Checkbox::new(&mut self.batch_series, format!("Series: {}", series)),
+                );
+                let volume = self.value("Volume").to_string();
+                let first: Option<u32> = volume.parse().ok();
+                self.batch_volume = self.batch_volume();
+                ui.horizontal(|ui| {
+                    ui.label("Volume:");
+                    ui.radio_value(&mut self.batch_volume, VolumeNumbering::Unchanged, "Unchanged");
+                    let same = VolumeNumbering::Same(volume.clone());
+                    if ui
+                        .add_enabled(
+                            !volume.is_empty(),
+                            egui::RadioButton::new(self.batch_volume == same, format!("Same ({})", volume)),
+                        )
+                        .clicked()
+                    {
+                        self.batch_volume = same;
+                    }
+                    let label = match first {
+                        Some(first) => format!("Numbered from {} at this archive", first),
+                        None => "Numbered (Volume must be a whole number)".to_string(),
+                    };
+                    let sequential = first.map(VolumeNumbering::Sequential);
+                    if ui
+                        .add_enabled(
+                            sequential.is_some(),
+                            egui::RadioButton::new(sequential.as_ref() == Some(&self.batch_volume), label),
+                        )
+                        .clicked()
+                    {
+                        self.batch_volume = sequential.unwrap_or(VolumeNumbering::Unchanged);
+                    }
+                });
+                let batch = self.batch_edit();
+                let has_changes = batch.series.is_some() || batch.volume != VolumeNumbering::Unchanged;
+                if ui
+                    .add_enabled(can_save && archive_count > 0 && has_changes, egui::Button::new("Apply to All"))
+                    .clicked()
+                {
+                    action = Some(EditorAction::ApplyToAll(batch));
+                }
+            });
+
+        self.open = open;
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequential_numbering_starts_at_the_edited_archive() {
+        let batch = BatchEdit {
+            series: None,
+            volume: VolumeNumbering::Sequential(5),
+        };
+        let volumes: Vec<Option<String>> = (0..4)
+            .map(|position| {
+                let mut info = ComicInfo::default();
+                batch.apply(&mut info, position, 2);
+                info.get("Volume").map(str::to_owned)
+            })
+            .collect();
+        assert_eq!(volumes, [Some("3".into()), Some("4".into()), Some("5".into()), Some("6".into())]);
+    }
+
+    #[test]
+    fn blank_series_is_not_applied() {
+        let mut editor = MetadataEditor::new();
+        editor.edit(None, true);
+        assert!(!editor.batch_series);
+
+        editor.batch_series = true;
+        assert_eq!(editor.batch_edit().series, None);
+        editor.values[EDITABLE_FIELDS.iter().position(|field| *field == "Series").unwrap()] = "Series".into();
+        assert_eq!(editor.batch_edit().series.as_deref(), Some("Series"));
+    }
+
+    #[test]
+    fn volume_numbering_needs_a_usable_volume() {
+        let mut editor = MetadataEditor::new();
+        editor.edit(None, true);
+        let volume = EDITABLE_FIELDS.iter().position(|field| *field == "Volume").unwrap();
+
+        editor.batch_volume = VolumeNumbering::Same(String::new());
+        assert_eq!(editor.batch_edit().volume, VolumeNumbering::Unchanged);
+        editor.values[volume] = "2.5".into();
+        assert_eq!(editor.batch_edit().volume, VolumeNumbering::Same("2.5".into()));
+
+        editor.batch_volume = VolumeNumbering::Sequential(1);
+        assert_eq!(editor.batch_edit().volume, VolumeNumbering::Unchanged);
+        editor.values[volume] = " 3 ".into();
+        assert_eq!(editor.batch_edit().volume, VolumeNumbering::Sequential(3));
+        editor.values[volume] = String::new();
+        assert_eq!(editor.batch_edit().volume, VolumeNumbering::Unchanged);
+    }
+}
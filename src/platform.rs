@@ -0,0 +1,142 @@
+use anyhow::{Context as AnyhowContext, Result};
+use std::path::{Component, Path};
+
+// File names that operating systems and archivers leave behind and that
+// should never show up as pages.
+const JUNK_FILE_NAMES: &[&str] = &["thumbs.db", ".ds_store", "desktop.ini"];
+const JUNK_DIR_NAMES: &[&str] = &["__macosx"];
+
+fn is_junk_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    JUNK_FILE_NAMES.contains(&name.as_str()) || name.starts_with("._")
+}
+
+// Returns true if a file on disk should be hidden from the reader.
+#[cfg(windows)]
+pub fn is_hidden_or_junk(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+
+    if let Ok(metadata) = path.metadata() {
+        let attributes = metadata.file_attributes();
+        if (attributes & FILE_ATTRIBUTE_HIDDEN) != 0 || (attributes & FILE_ATTRIBUTE_SYSTEM) != 0 {
+            return true;
+        }
+    }
+
+    path.file_name()
+        .map(|name| is_junk_name(&name.to_string_lossy()))
+        .unwrap_or(false)
+}
+
+// Returns true if a file on disk should be hidden from the reader.
+#[cfg(not(windows))]
+pub fn is_hidden_or_junk(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| {
+            let name = name.to_string_lossy();
+            name.starts_with('.') || is_junk_name(&name)
+        })
+        .unwrap_or(false)
+}
+
+// Returns true if an entry inside an archive should be skipped. Archive
+// entries carry no reliable attribute bits, so this only looks at the
+// name: dotfiles, known junk names and anything under `__MACOSX/`.
+pub fn is_junk_archive_entry(name: &str) -> bool {
+    let normalized = name.replace('\\', "/");
+    Path::new(&normalized).components().any(|component| match component {
+        Component::Normal(part) => {
+            let part = part.to_string_lossy();
+            part.starts_with('.')
+                || is_junk_name(&part)
+                || JUNK_DIR_NAMES.contains(&part.to_lowercase().as_str())
+        }
+        _ => false,
+    })
+}
+
+// Move a file to the desktop trash: the freedesktop Trash on Linux, the
+// Recycle Bin on Windows and the Trash on macOS.
+pub fn move_to_trash(path: &Path) -> Result<()> {
+    trash::delete(path).with_context(|| format!("Failed to move to trash: {}", path.display()))
+}
+
+// Whether `restore_from_trash` works here. The macOS Trash cannot be listed.
+pub const CAN_RESTORE_FROM_TRASH: bool =
+    cfg!(any(windows, all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))));
+
+// Put the most recently trashed file that lived at `path` back in place.
+#[cfg(any(windows, all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))))]
+pub fn restore_from_trash(path: &Path) -> Result<()> {
+    // The trash records canonical paths, without the `\\?\` prefix that
+    // Windows canonicalization adds.
+    let original = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => dunce::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf()).join(name),
+        _ => path.to_path_buf(),
+    };
+    let item = trash::os_limited::list()
+        .context("Failed to list the trash")?
+        .into_iter()
+        .filter(|item| item.original_path() == original)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| anyhow::anyhow!("Not found in the trash: {}", path.display()))?;
+    trash::os_limited::restore_all([item]).with_context(|| format!("Failed to restore: {}", path.display()))
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android")))))]
+pub fn restore_from_trash(path: &Path) -> Result<()> {
+    anyhow::bail!("Restoring from the trash is not supported on this system: {}", path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn restores_trashed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        std::fs::write(&path, b"pages").unwrap();
+
+        move_to_trash(&path).unwrap();
+        assert!(!path.exists());
+        restore_from_trash(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"pages");
+    }
+
+    #[test]
+    fn skips_junk_archive_entries() {
+        let cases = [
+            ("001.jpg", false),
+            ("chapter 1/001.jpg", false),
+            ("chapter.1\\001.jpg", false),
+            ("__MACOSX/chapter 1/._001.jpg", true),
+            ("__macosx\\001.jpg", true),
+            ("chapter 1/._001.jpg", true),
+            ("Thumbs.db", true),
+            ("chapter 1/thumbs.db", true),
+            ("Desktop.ini", true),
+            (".DS_Store", true),
+            (".hidden/001.jpg", true),
+            ("chapter 1/.001.jpg", true),
+        ];
+        for (name, junk) in cases {
+            assert_eq!(is_junk_archive_entry(name), junk, "{}", name);
+        }
+    }
+
+    #[test]
+    fn hides_junk_files() {
+        for name in ["._001.jpg", "Thumbs.db", "desktop.ini", ".DS_Store"] {
+            assert!(is_hidden_or_junk(&Path::new("volume").join(name)), "{}", name);
+        }
+        assert!(!is_hidden_or_junk(Path::new("volume/001.jpg")));
+        // Windows goes by the hidden attribute instead of a leading dot.
+        #[cfg(not(windows))]
+        assert!(is_hidden_or_junk(Path::new("volume/.001.jpg")));
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{Context as AnyhowContext, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::image_files_in_directory;
+
+// Bytes read from each end of a file to fingerprint it.
+const FINGERPRINT_BYTES: u64 = 64 * 1024;
+
+// Identifies a volume by where it is and by what it holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeKey {
+    // Canonical path of the archive or folder.
+    pub path: PathBuf,
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeProgress {
+    // Where the volume was last seen; updated when it has been renamed.
+    pub path: PathBuf,
+    pub fingerprint: String,
+    pub last_page: usize,
+    pub total_pages: usize,
+    pub completed: bool,
+    // Seconds since the Unix epoch.
+    pub last_read: u64,
+}
+
+// Reading position of every volume opened so far, keyed by its canonical
+// path. A volume that is not found by path is matched by the fingerprint of
+// its contents, so renamed or moved files keep their place.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProgressStore {
+    #[serde(default)]
+    volumes: HashMap<PathBuf, VolumeProgress>,
+}
+
+impl ProgressStore {
+    pub const FILE_NAME: &'static str = "progress.json";
+
+    pub fn get(&self, key: &VolumeKey) -> Option<&VolumeProgress> {
+        self.volumes
+            .get(&key.path)
+            .or_else(|| self.moved_from(key).map(|path| &self.volumes[path]))
+    }
+
+    // Where a volume with these contents was last read, if it is still there.
+    pub fn locate(&self, fingerprint: &str) -> Option<&Path> {
+        self.volumes
+            .iter()
+            .filter(|(path, progress)| progress.fingerprint == fingerprint && path.exists())
+            .max_by_key(|(_, progress)| progress.last_read)
+            .map(|(path, _)| path.as_path())
+    }
+
+    // The recorded path of a volume with the same contents whose file has
+    // gone, presumably because it was moved or renamed to `key.path`.
+    fn moved_from(&self, key: &VolumeKey) -> Option<&PathBuf> {
+        self.volumes
+            .iter()
+            .find(|(path, progress)| progress.fingerprint == key.fingerprint && !path.exists())
+            .map(|(path, _)| path)
+    }
+
+    // Carry a volume's progress over after its contents changed.
+    pub fn rename_key(&mut self, old: &VolumeKey, new: &VolumeKey) {
+        if let Some(mut progress) = self.volumes.remove(&old.path) {
+            progress.path = new.path.clone();
+            progress.fingerprint = new.fingerprint.clone();
+            self.volumes.insert(new.path.clone(), progress);
+        }
+    }
+
+    // Record that pages `page..page + visible` of the volume are on screen.
+    // A volume first seen at this path takes over the progress of the file
+    // it was moved or renamed from.
+    pub fn update(&mut self, key: &VolumeKey, page: usize, visible: usize, total: usize) {
+        let last_read = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let reached_end = total > 0 && page + visible >= total;
+
+        if !self.volumes.contains_key(&key.path) {
+            if let Some(progress) = self.moved_from(key).cloned().and_then(|path| self.volumes.remove(&path)) {
+                self.volumes.insert(key.path.clone(), progress);
+            }
+        }
+        let entry = self.volumes.entry(key.path.clone()).or_insert_with(|| VolumeProgress {
+            path: key.path.clone(),
+            fingerprint: key.fingerprint.clone(),
+            last_page: 0,
+            total_pages: total,
+            completed: false,
+            last_read,
+        });
+        entry.path = key.path.clone();
+        entry.fingerprint = key.fingerprint.clone();
+        entry.last_page = page;
+        entry.total_pages = total;
+        entry.completed |= reached_end;
+        entry.last_read = last_read;
+    }
+}
+
+pub fn volume_key(path: &Path) -> Result<VolumeKey> {
+    Ok(VolumeKey {
+        path: dunce::canonicalize(path).with_context(|| format!("Failed to resolve path: {}", path.display()))?,
+        fingerprint: fingerprint(path)?,
+    })
+}
+
+// Fingerprint a volume. Archives hash their size plus the first and last
+// 64 KiB, which is cheap even for large files; folders hash the names and
+// sizes of their images.
+fn fingerprint(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+
+    if path.is_dir() {
+        hasher.update(b"dir");
+        for image in image_files_in_directory(path) {
+            let name = image.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let size = fs::metadata(&image).map(|metadata| metadata.len()).unwrap_or_default();
+            hasher.update(name.as_bytes());
+            hasher.update(size.to_le_bytes());
+        }
+    } else {
+        let mut file = File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let size = file.metadata()?.len();
+        hasher.update(b"file");
+        hasher.update(size.to_le_bytes());
+
+        let mut buffer = Vec::new();
+        (&mut file).take(FINGERPRINT_BYTES).read_to_end(&mut buffer)?;
+        if size > FINGERPRINT_BYTES {
+            file.seek(SeekFrom::Start(size.saturating_sub(FINGERPRINT_BYTES).max(FINGERPRINT_BYTES)))?;
+            file.read_to_end(&mut buffer)?;
+        }
+        hasher.update(&buffer);
+    }
+
+    Ok(hex_digest(hasher))
+}
+
+pub fn hex_digest(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_volumes_by_path_and_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("01.cbz");
+        let copy = dir.path().join("copy.cbz");
+        fs::write(&first, b"pages").unwrap();
+        fs::write(&copy, b"pages").unwrap();
+
+        let key = volume_key(&first).unwrap();
+        let copy_key = volume_key(&copy).unwrap();
+        assert_eq!(key.path, dunce::canonicalize(&first).unwrap());
+        assert_eq!(key.fingerprint, copy_key.fingerprint);
+        assert_ne!(key, copy_key);
+        assert_eq!(volume_key(&dir.path().join(".").join("01.cbz")).unwrap(), key);
+
+        fs::write(&first, b"other pages").unwrap();
+        assert_ne!(volume_key(&first).unwrap().fingerprint, key.fingerprint);
+    }
+
+    #[test]
+    fn tracks_position_and_completion() {
+        let key = VolumeKey {
+            path: PathBuf::from("/manga/01.cbz"),
+            fingerprint: "a".to_string(),
+        };
+        let mut store = ProgressStore::default();
+        store.update(&key, 3, 1, 10);
+        let progress = store.get(&key).unwrap();
+        assert_eq!((progress.last_page, progress.total_pages, progress.completed), (3, 10, false));
+
+        // A spread showing the last two pages finishes the volume, which
+        // stays finished when paging back.
+        store.update(&key, 8, 2, 10);
+        assert!(store.get(&key).unwrap().completed);
+        store.update(&key, 0, 1, 10);
+        let progress = store.get(&key).unwrap();
+        assert_eq!((progress.last_page, progress.completed), (0, true));
+    }
+
+    #[test]
+    fn follows_moved_volumes_but_not_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_path = dir.path().join("old.cbz");
+        let new_path = dir.path().join("new.cbz");
+        fs::write(&old_path, b"pages").unwrap();
+        let old_key = volume_key(&old_path).unwrap();
+        let mut store = ProgressStore::default();
+        store.update(&old_key, 5, 1, 20);
+
+        // A copy next to the original starts from the beginning.
+        fs::copy(&old_path, &new_path).unwrap();
+        let new_key = volume_key(&new_path).unwrap();
+        assert!(store.get(&new_key).is_none());
+
+        // Once the original is gone the copy is taken to be it, renamed.
+        fs::remove_file(&old_path).unwrap();
+        assert_eq!(store.get(&new_key).unwrap().last_page, 5);
+        store.update(&new_key, 6, 1, 20);
+        assert_eq!(store.volumes.len(), 1);
+        assert_eq!(store.get(&new_key).unwrap().path, new_key.path);
+        assert_eq!(store.locate(&old_key.fingerprint), Some(new_key.path.as_path()));
+
+        // Rewriting the volume changes its fingerprint but keeps its place.
+        let rewritten = VolumeKey {
+            fingerprint: "rewritten".to_string(),
+            ..new_key.clone()
+        };
+        store.rename_key(&new_key, &rewritten);
+        assert_eq!(store.get(&rewritten).unwrap().last_page, 6);
+        assert_eq!(store.locate(&new_key.fingerprint), None);
+    }
+}
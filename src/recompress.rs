@@ -0,0 +1,590 @@
+use anyhow::{Context as AnyhowContext, Result};
+use eframe::egui;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageEncoder, ImageFormat};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::archive::{self, ArchiveKind, EntryEdit};
+use crate::decoder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecompressOptions {
+    // Convert PNG pages to lossless WebP.
+    pub png_to_webp: bool,
+    // Re-encode JPEG pages at this quality when that makes them smaller.
+    pub jpeg_quality: Option<u8>,
+    // Scale pages taller than this down to it.
+    pub max_height: Option<u32>,
+}
+
+impl Default for RecompressOptions {
+    fn default() -> Self {
+        Self {
+            png_to_webp: true,
+            jpeg_quality: Some(85),
+            max_height: None,
+        }
+    }
+}
+
+// A page that shrinks when re-encoded.
+pub struct PageChange {
+    // Position in the archive's full page list, as ComicInfo counts pages.
+    pub page: usize,
+    pub name: String,
+    pub new_name: String,
+    pub original_size: usize,
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+    // Where the re-encoded data starts in the plan's spool file.
+    offset: u64,
+}
+
+// The re-encoded pages of one archive, computed before anything is written
+// so the savings can be previewed.
+pub struct RecompressPlan {
+    pub path: PathBuf,
+    // Size and modification time of the archive the plan was made from.
+    source_stamp: Option<(u64, SystemTime)>,
+    pub pages: usize,
+    pub original_bytes: usize,
+    pub changes: Vec<PageChange>,
+    pub failures: Vec<(String, String)>,
+    spool: Spool,
+}
+
+// A temporary file the re-encoded pages are written to one after another,
+// so a plan for a large volume does not keep every page in memory. The file
+// is removed when the plan is dropped.
+struct Spool {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl Spool {
+    fn new() -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("manga_reader-recompress-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create temporary file: {}", path.display()))?;
+        Ok(Self { path, file, len: 0 })
+    }
+
+    // Append `data`, returning its offset.
+    fn push(&mut self, data: &[u8]) -> Result<u64> {
+        let offset = self.len;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)?;
+        self.len += data.len() as u64;
+        Ok(offset)
+    }
+
+    fn read(&mut self, offset: u64, size: usize) -> Result<Vec<u8>> {
+        let mut data = vec![0; size];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+impl RecompressPlan {
+    pub fn saved_bytes(&self) -> usize {
+        self.changes
+            .iter()
+            .map(|change| change.original_size.saturating_sub(change.size))
+            .sum()
+    }
+}
+
+fn encode(image: DynamicImage, format: ImageFormat, jpeg_quality: u8) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    match format {
+        ImageFormat::Jpeg => decoder::convert_for_format(image, format)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, jpeg_quality))?,
+        ImageFormat::WebP if image.color().has_alpha() => {
+            let rgba = image.to_rgba8();
+            WebPEncoder::new_lossless(&mut buffer).write_image(
+                rgba.as_raw(),
+                rgba.width(),
+                rgba.height(),
+                image::ExtendedColorType::Rgba8,
+            )?;
+        }
+        ImageFormat::WebP => {
+            let rgb = image.to_rgb8();
+            WebPEncoder::new_lossless(&mut buffer).write_image(
+                rgb.as_raw(),
+                rgb.width(),
+                rgb.height(),
+                image::ExtendedColorType::Rgb8,
+            )?;
+        }
+        _ => image.write_with_encoder(PngEncoder::new(&mut buffer))?,
+    }
+    Ok(buffer)
+}
+
+fn with_extension(name: &str, extension: &str) -> String {
+    match name.rfind('.') {
+        Some(dot) if !name[dot..].contains(['/', '\\']) => format!("{}.{}", &name[..dot], extension),
+        _ => format!("{}.{}", name, extension),
+    }
+}
+
+// Re-encode one page into the spool. Returns None when the page is left
+// alone or would not get smaller.
+fn recompress_page(
+    page: usize,
+    name: &str,
+    data: &[u8],
+    options: &RecompressOptions,
+    taken: &HashSet<String>,
+    spool: &mut Spool,
+) -> Result<Option<PageChange>> {
+    let format = match Path::new(name).extension().and_then(ImageFormat::from_extension) {
+        Some(format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)) => format,
+        // GIFs may be animated, so they are left alone.
+        _ => return Ok(None),
+    };
+
+    let mut image = decoder::decode_image_bytes(name, data)?;
+    let mut resized = false;
+    if let Some(max_height) = options.max_height.filter(|&max_height| image.height() > max_height) {
+        let width = (image.width() as u64 * max_height as u64 / image.height() as u64).max(1) as u32;
+        image = image.resize_exact(width, max_height, FilterType::Lanczos3);
+        resized = true;
+    }
+
+    let webp_name = with_extension(name, "webp");
+    // Never clash with another entry that already has the new name.
+    let (target, new_name) = if format == ImageFormat::Png && options.png_to_webp && !taken.contains(&webp_name) {
+        (ImageFormat::WebP, webp_name)
+    } else {
+        (format, name.to_string())
+    };
+    let reencode = resized || target != format || (target == ImageFormat::Jpeg && options.jpeg_quality.is_some());
+    if !reencode {
+        return Ok(None);
+    }
+
+    let (width, height) = (image.width(), image.height());
+    let encoded = encode(image, target, options.jpeg_quality.unwrap_or(90))?;
+    if encoded.len() >= data.len() {
+        return Ok(None);
+    }
+    let offset = spool.push(&encoded).context("Failed to write to the temporary file")?;
+    Ok(Some(PageChange {
+        page,
+        name: name.to_string(),
+        new_name,
+        original_size: data.len(),
+        size: encoded.len(),
+        width,
+        height,
+        offset,
+    }))
+}
+
+// Work out the re-encoded pages of a CBZ. `progress` is told how many of the
+// pages have been processed.
+pub fn plan(path: &Path, options: &RecompressOptions, mut progress: impl FnMut(usize, usize)) -> Result<RecompressPlan> {
+    if archive::detect_kind(path)? != ArchiveKind::Zip {
+        anyhow::bail!("Only CBZ/ZIP archives can be recompressed: {}", path.display());
+    }
+    let mut backend = archive::open(path)?;
+    let entries = archive::page_entries(backend.as_ref());
+    let taken: HashSet<String> = backend.entry_names().iter().cloned().collect();
+
+    let mut plan = RecompressPlan {
+        path: path.to_path_buf(),
+        source_stamp: file_stamp(path),
+        pages: entries.len(),
+        original_bytes: 0,
+        changes: Vec::new(),
+        failures: Vec::new(),
+        spool: Spool::new()?,
+    };
+    for (page, &index) in entries.iter().enumerate() {
+        progress(page, entries.len());
+        let name = backend.entry_names()[index].clone();
+        // A damaged page is reported and left as it is.
+        let data = match backend.read_entry(index) {
+            Ok(data) => data,
+            Err(e) => {
+                plan.failures.push((name, format!("{:#}", e)));
+                continue;
+            }
+        };
+        plan.original_bytes += data.len();
+        match recompress_page(page, &name, &data, options, &taken, &mut plan.spool) {
+            Ok(Some(change)) => plan.changes.push(change),
+            Ok(None) => {}
+            Err(e) => plan.failures.push((name, format!("{:#}", e))),
+        }
+    }
+    progress(entries.len(), entries.len());
+    Ok(plan)
+}
+
+// Write the planned pages into the archive, updating the page sizes its
+// ComicInfo.xml records. Pages are read back from the spool one at a time.
+pub fn apply(plan: RecompressPlan, backup: bool) -> Result<()> {
+    if plan.source_stamp.is_none() || file_stamp(&plan.path) != plan.source_stamp {
+        anyhow::bail!("{} changed since the savings were estimated", plan.path.display());
+    }
+    let (comic_info_name, comic_info) = {
+        let mut backend = archive::open(&plan.path)?;
+        let name = archive::comic_info_entry(backend.as_ref()).map(|index| backend.entry_names()[index].clone());
+        let info = archive::read_comic_info(backend.as_mut()).map(|mut info| {
+            for change in &plan.changes {
+                info.update_image(change.page, change.size, change.width, change.height);
+            }
+            info.to_xml().into_bytes()
+        });
+        (name, info)
+    };
+    let mut comic_info = comic_info;
+    let mut spool = plan.spool;
+    let mut changes: HashMap<String, PageChange> = plan
+        .changes
+        .into_iter()
+        .map(|change| (change.name.clone(), change))
+        .collect();
+
+    archive::rewrite_zip(
+        &plan.path,
+        backup,
+        |entry| {
+            if let Some(change) = changes.remove(entry) {
+                let data = spool.read(change.offset, change.size)?;
+                return Ok(if change.new_name == change.name {
+                    EntryEdit::Replace(data)
+                } else {
+                    EntryEdit::Rename(change.new_name, data)
+                });
+            }
+            if Some(entry) == comic_info_name.as_deref() {
+                if let Some(xml) = comic_info.take() {
+                    return Ok(EntryEdit::Replace(xml));
+                }
+            }
+            Ok(EntryEdit::Keep)
+        },
+        Vec::new(),
+    )
+    .with_context(|| format!("Failed to recompress {}", plan.path.display()))
+}
+
+pub fn format_size(bytes: usize) -> String {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    if mb >= 1.0 {
+        format!("{:.1} MB", mb)
+    } else {
+        format!("{:.0} KB", bytes as f64 / 1024.0)
+    }
+}
+
+enum PlanEvent {
+    Progress(usize, usize),
+    Done(Result<RecompressPlan, String>),
+}
+
+pub enum RecompressEvent {
+    // The user applied the plan. The app lets go of the archive and hands
+    // the plan back to `RecompressWindow::write`.
+    Apply(RecompressPlan),
+    // Writing the plan finished.
+    Written {
+        path: PathBuf,
+        saved: usize,
+        result: Result<(), String>,
+    },
+}
+
+// A plan being written to its archive by a worker thread.
+struct Writing {
+    path: PathBuf,
+    saved: usize,
+    result: Receiver<Result<(), String>>,
+}
+
+// Options, savings preview and apply button for recompressing the open CBZ.
+pub struct RecompressWindow {
+    pub open: bool,
+    path: Option<PathBuf>,
+    options: RecompressOptions,
+    // Options the current plan was made with.
+    planned_options: RecompressOptions,
+    plan: Option<RecompressPlan>,
+    error: Option<String>,
+    running: Option<Receiver<PlanEvent>>,
+    progress: (usize, usize),
+    writing: Option<Writing>,
+}
+
+impl RecompressWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            path: None,
+            options: RecompressOptions::default(),
+            planned_options: RecompressOptions::default(),
+            plan: None,
+            error: None,
+            running: None,
+            progress: (0, 0),
+            writing: None,
+        }
+    }
+
+    pub fn is_writing(&self) -> bool {
+        self.writing.is_some()
+    }
+
+    // Write `plan` to its archive in the background.
+    pub fn write(&mut self, plan: RecompressPlan, backup: bool, ctx: &egui::Context) {
+        let (sender, result) = mpsc::channel();
+        let path = plan.path.clone();
+        let saved = plan.saved_bytes();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let _ = sender.send(apply(plan, backup).map_err(|e| format!("{:#}", e)));
+            ctx.request_repaint();
+        });
+        self.writing = Some(Writing { path, saved, result });
+    }
+
+    pub fn open_for(&mut self, path: PathBuf) {
+        if self.path.as_ref() != Some(&path) {
+            self.plan = None;
+            self.error = None;
+            self.running = None;
+        }
+        self.path = Some(path);
+        self.open = true;
+    }
+
+    fn start(&mut self, ctx: &egui::Context) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        let options = self.options;
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let progress_ctx = ctx.clone();
+            let result = plan(&path, &options, |done, total| {
+                let _ = progress_sender.send(PlanEvent::Progress(done, total));
+                progress_ctx.request_repaint();
+            });
+            let _ = sender.send(PlanEvent::Done(result.map_err(|e| format!("{:#}", e))));
+            ctx.request_repaint();
+        });
+        self.planned_options = options;
+        self.plan = None;
+        self.error = None;
+        self.progress = (0, 0);
+        self.running = Some(receiver);
+    }
+
+    fn poll(&mut self) {
+        let Some(receiver) = &self.running else {
+            return;
+        };
+        while let Ok(event) = receiver.try_recv() {
+            match event {
+                PlanEvent::Progress(done, total) => self.progress = (done, total),
+                PlanEvent::Done(result) => {
+                    match result {
+                        Ok(plan) => self.plan = Some(plan),
+                        Err(e) => self.error = Some(e),
+                    }
+                    self.running = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    // Draw the window. Returns the plan once the user applies it, and the
+    // outcome once it has been written.
+    pub fn show(&mut self, ctx: &egui::Context, backup: &mut bool) -> Option<RecompressEvent> {
+        self.poll();
+        if let Some(writing) = &self.writing {
+            if let Ok(result) = writing.result.try_recv() {
+                let writing = self.writing.take().unwrap();
+                self.open = false;
+                return Some(RecompressEvent::Written {
+                    path: writing.path,
+                    saved: writing.saved,
+                    result,
+                });
+            }
+        }
+        let mut open = self.open;
+        let mut apply = false;
+
+        egui::Window::new("Recompress Archive")
+            .open(&mut open)
+            .default_width(380.0)
+            .show(ctx, |ui| {
+                if let Some(path) = &self.path {
+                    ui.label(path.display().to_string());
+                }
+                ui.separator();
+
+                ui.checkbox(&mut self.options.png_to_webp, "Convert PNG to lossless WebP");
+                ui.horizontal(|ui| {
+                    let mut cap = self.options.jpeg_quality.is_some();
+                    ui.checkbox(&mut cap, "Re-encode JPEG at quality");
+                    let mut quality = self.options.jpeg_quality.unwrap_or(85);
+                    ui.add_enabled(cap, egui::DragValue::new(&mut quality).range(30..=100));
+                    self.options.jpeg_quality = cap.then_some(quality);
+                });
+                ui.horizontal(|ui| {
+                    let mut limit = self.options.max_height.is_some();
+                    ui.checkbox(&mut limit, "Downscale to a height of");
+                    let mut height = self.options.max_height.unwrap_or(2400);
+                    ui.add_enabled(limit, egui::DragValue::new(&mut height).range(480..=10000).suffix(" px"));
+                    self.options.max_height = limit.then_some(height);
+                });
+                ui.checkbox(backup, "Keep a backup (.bak)");
+                ui.separator();
+
+                if self.options != self.planned_options {
+                    self.plan = None;
+                }
+                if self.writing.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Writing the recompressed pages...");
+                    });
+                    return;
+                }
+                if self.running.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!("Estimating... {} of {} pages", self.progress.0, self.progress.1));
+                    });
+                } else if ui.button("Estimate Savings").clicked() {
+                    self.start(ctx);
+                }
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                let Some(plan) = &self.plan else {
+                    return;
+                };
+                let saved = plan.saved_bytes();
+                let percent = if plan.original_bytes > 0 { saved as f64 * 100.0 / plan.original_bytes as f64 } else { 0.0 };
+                ui.label(format!("{} of {} pages get smaller", plan.changes.len(), plan.pages));
+                ui.label(format!(
+                    "{} -> {} (saves {}, {:.0}%)",
+                    format_size(plan.original_bytes),
+                    format_size(plan.original_bytes - saved),
+                    format_size(saved),
+                    percent
+                ));
+                if !plan.failures.is_empty() {
+                    ui.label(format!("{} pages could not be read or decoded and are left as they are", plan.failures.len()));
+                }
+                apply = ui.add_enabled(!plan.changes.is_empty(), egui::Button::new("Apply")).clicked();
+            });
+
+        self.open = open;
+        if !self.open {
+            self.running = None;
+        }
+        if apply {
+            return self.plan.take().map(RecompressEvent::Apply);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::testing;
+    use zip::CompressionMethod;
+
+    #[test]
+    fn unreadable_page_is_reported_not_fatal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        let page = testing::png(64, 64);
+        testing::write_zip(
+            &path,
+            &[("001.png", &page, CompressionMethod::Stored), ("002.png", &page, CompressionMethod::Stored)],
+        );
+        // Damage the data of the second page so its CRC check fails.
+        let mut bytes = fs::read(&path).unwrap();
+        let second = bytes.windows(7).position(|window| window == b"002.png").unwrap() + 7;
+        let data = second + bytes[second..].windows(4).position(|window| window == b"IDAT").unwrap();
+        bytes[data + 4] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        let plan = plan(&path, &RecompressOptions::default(), |_, _| {}).unwrap();
+        assert_eq!(plan.pages, 2);
+        assert_eq!(plan.failures.len(), 1);
+        assert_eq!(plan.failures[0].0, "002.png");
+        assert_eq!(plan.original_bytes, page.len());
+    }
+
+    #[test]
+    fn applies_planned_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("volume.cbz");
+        let page = testing::png(64, 64);
+        testing::write_zip(
+            &path,
+            &[("001.png", &page, CompressionMethod::Stored), ("002.png", &page, CompressionMethod::Stored)],
+        );
+
+        let plan = plan(&path, &RecompressOptions::default(), |_, _| {}).unwrap();
+        assert_eq!(plan.changes.len(), 2);
+        let spool = plan.spool.path.clone();
+        assert!(spool.exists());
+        apply(plan, false).unwrap();
+        assert!(!spool.exists());
+
+        let mut backend = archive::open(&path).unwrap();
+        assert_eq!(backend.entry_names(), ["001.webp", "002.webp"]);
+        for index in 0..2 {
+            let data = backend.read_entry(index).unwrap();
+            let image = decoder::decode_image_bytes("page.webp", &data).unwrap();
+            assert_eq!((image.width(), image.height()), (64, 64));
+        }
+    }
+}
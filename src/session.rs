@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::progress::VolumeKey;
+use crate::FitMode;
+
+// Number of entries kept in the Recent menu.
+pub const RECENT_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub position: Option<[f32; 2]>,
+    pub size: Option<[f32; 2]>,
+    pub maximized: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentEntry {
+    pub path: PathBuf,
+    // Key into the progress store, used to show how far the volume was read.
+    pub volume_key: Option<VolumeKey>,
+}
+
+// What was open when the app last closed, restored on the next start when
+// no file is given on the command line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub volume: Option<PathBuf>,
+    pub page: usize,
+    pub zoom: f32,
+    pub fit_mode: FitMode,
+    pub fullscreen: bool,
+    pub window: WindowGeometry,
+    pub recent: Vec<RecentEntry>,
+    // Keep a `.bak` copy of archives before pages are removed from them.
+    pub backup_archives: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            volume: None,
+            page: 0,
+            zoom: 1.0,
+            fit_mode: FitMode::default(),
+            fullscreen: false,
+            window: WindowGeometry {
+                maximized: true,
+                ..Default::default()
+            },
+            recent: Vec::new(),
+            backup_archives: true,
+        }
+    }
+}
+
+impl Session {
+    pub const FILE_NAME: &'static str = "session.json";
+
+    // Move `path` to the top of the Recent list.
+    pub fn add_recent(&mut self, path: &Path, volume_key: Option<VolumeKey>) {
+        self.recent.retain(|entry| entry.path != path);
+        self.recent.insert(
+            0,
+            RecentEntry {
+                path: path.to_path_buf(),
+                volume_key,
+            },
+        );
+        self.recent.truncate(RECENT_LIMIT);
+    }
+}
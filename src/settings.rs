@@ -0,0 +1,97 @@
+use anyhow::{Context as AnyhowContext, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::ReadingDirection;
+
+// Directory holding the reader's JSON settings files.
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("manga_reader"))
+}
+
+// Load a settings file, falling back to defaults if it is missing or invalid.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    config_dir()
+        .map(|dir| dir.join(name))
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+// Write a settings file through a temporary file so a crash never leaves a
+// half-written file behind.
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let dir = config_dir().ok_or_else(|| anyhow::anyhow!("No configuration directory available"))?;
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create settings directory: {}", dir.display()))?;
+
+    let path = dir.join(name);
+    let temp_path = dir.join(format!("{}.tmp", name));
+    let json = serde_json::to_vec_pretty(value)?;
+    let mut file = fs::File::create(&temp_path)
+        .with_context(|| format!("Failed to write settings: {}", temp_path.display()))?;
+    file.write_all(&json)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &path)
+        .with_context(|| format!("Failed to write settings: {}", path.display()))?;
+    Ok(())
+}
+
+// Reading direction chosen by the user, remembered per volume and per series
+// (the folder a volume lives in) so new volumes of a series inherit it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DirectionSettings {
+    #[serde(default)]
+    volumes: HashMap<PathBuf, ReadingDirection>,
+    #[serde(default)]
+    series: HashMap<PathBuf, ReadingDirection>,
+}
+
+impl DirectionSettings {
+    pub const FILE_NAME: &'static str = "reading_direction.json";
+
+    // A direction saved for this volume wins over the volume's own metadata,
+    // which in turn wins over the series setting.
+    pub fn resolve(&self, volume: &Path, hint: Option<ReadingDirection>) -> ReadingDirection {
+        self.volumes
+            .get(volume)
+            .copied()
+            .or(hint)
+            .or_else(|| volume.parent().and_then(|series| self.series.get(series).copied()))
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, volume: &Path, direction: ReadingDirection) {
+        self.volumes.insert(volume.to_path_buf(), direction);
+        if let Some(series) = volume.parent() {
+            self.series.insert(series.to_path_buf(), direction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_direction_wins_over_metadata_and_series() {
+        let mut settings = DirectionSettings::default();
+        let volume = Path::new("/manga/series/01.cbz");
+        let sibling = Path::new("/manga/series/02.cbz");
+        let elsewhere = Path::new("/manga/other/01.cbz");
+
+        assert_eq!(settings.resolve(volume, None), ReadingDirection::LeftToRight);
+        assert_eq!(settings.resolve(volume, Some(ReadingDirection::RightToLeft)), ReadingDirection::RightToLeft);
+
+        settings.set(volume, ReadingDirection::RightToLeft);
+        assert_eq!(settings.resolve(volume, Some(ReadingDirection::LeftToRight)), ReadingDirection::RightToLeft);
+        // Other volumes of the series follow it unless their metadata says otherwise.
+        assert_eq!(settings.resolve(sibling, None), ReadingDirection::RightToLeft);
+        assert_eq!(settings.resolve(sibling, Some(ReadingDirection::LeftToRight)), ReadingDirection::LeftToRight);
+        assert_eq!(settings.resolve(elsewhere, None), ReadingDirection::LeftToRight);
+    }
+}
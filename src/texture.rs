@@ -0,0 +1,206 @@
+use eframe::egui::{self, Color32, ColorImage, Painter, Rect, TextureHandle, TextureOptions};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Side of the square tiles that oversized pages are split into.
+const TILE_SIZE: usize = 2048;
+
+// A page on the GPU: one texture, or tiles for pages larger than the GPU's
+// maximum texture size.
+#[derive(Clone)]
+pub enum PageTexture {
+    Single(TextureHandle),
+    Tiled(Arc<TiledImage>),
+}
+
+// Tiles are cut from the full-size image or a downsampled level and only
+// uploaded while they are on screen.
+pub struct TiledImage {
+    name: String,
+    levels: Vec<ColorImage>,
+    tile_size: usize,
+    resident: Mutex<HashMap<(usize, usize, usize), Tile>>,
+}
+
+struct Tile {
+    texture: TextureHandle,
+    last_used: u64,
+}
+
+impl PageTexture {
+    // `mips` are the downsampled levels from `build_mip_levels`, if any.
+    pub fn new(ctx: &egui::Context, name: String, image: ColorImage, mips: Vec<ColorImage>) -> Self {
+        let max_side = ctx.input(|i| i.max_texture_side);
+        let [width, height] = image.size;
+        if mips.is_empty() && width <= max_side && height <= max_side {
+            return Self::Single(ctx.load_texture(name, image, TextureOptions::default()));
+        }
+
+        let mut levels = vec![image];
+        levels.extend(mips);
+        Self::Tiled(Arc::new(TiledImage {
+            name,
+            levels,
+            tile_size: TILE_SIZE.min(max_side),
+            resident: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn size(&self) -> [usize; 2] {
+        match self {
+            Self::Single(texture) => texture.size(),
+            Self::Tiled(tiled) => tiled.levels[0].size,
+        }
+    }
+
+    pub fn size_vec2(&self) -> egui::Vec2 {
+        let [width, height] = self.size();
+        egui::vec2(width as f32, height as f32)
+    }
+
+    // Memory held for this page: the GPU texture, or the CPU copies that
+    // tiles are cut from (resident tiles are bounded by what is on screen).
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            Self::Single(texture) => {
+                let [width, height] = texture.size();
+                width * height * 4
+            }
+            Self::Tiled(tiled) => tiled.levels.iter().map(|level| level.pixels.len() * 4).sum(),
+        }
+    }
+
+    pub fn paint(&self, painter: &Painter, rect: Rect) {
+        match self {
+            Self::Single(texture) => {
+                let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                painter.image(texture.id(), rect, uv, Color32::WHITE);
+            }
+            Self::Tiled(tiled) => tiled.paint(painter, rect),
+        }
+    }
+
+    // Drop tiles that were not drawn in the current or previous pass.
+    pub fn release_unused_tiles(&self, pass: u64) {
+        if let Self::Tiled(tiled) = self {
+            tiled
+                .resident
+                .lock()
+                .unwrap()
+                .retain(|_, tile| tile.last_used + 1 >= pass);
+        }
+    }
+}
+
+impl PartialEq for PageTexture {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Single(a), Self::Single(b)) => a.id() == b.id(),
+            (Self::Tiled(a), Self::Tiled(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl TiledImage {
+    fn paint(&self, painter: &Painter, rect: Rect) {
+        let visible = rect.intersect(painter.clip_rect());
+        if !visible.is_positive() {
+            return;
+        }
+        let ctx = painter.ctx();
+
+        // Use the smallest level that still has at least one pixel per
+        // physical screen pixel.
+        let scale = rect.width() * ctx.pixels_per_point() / self.levels[0].size[0] as f32;
+        let mut level = 0;
+        while level + 1 < self.levels.len() && scale * (1 << (level + 1)) as f32 <= 1.0 {
+            level += 1;
+        }
+        let image = &self.levels[level];
+        let [width, height] = image.size;
+        let tile = self.tile_size;
+
+        let to_image_x = |x: f32| ((x - rect.min.x) / rect.width() * width as f32).clamp(0.0, width as f32);
+        let to_image_y = |y: f32| ((y - rect.min.y) / rect.height() * height as f32).clamp(0.0, height as f32);
+        let first_x = to_image_x(visible.min.x) as usize / tile;
+        let last_x = (to_image_x(visible.max.x).ceil() as usize).saturating_sub(1) / tile;
+        let first_y = to_image_y(visible.min.y) as usize / tile;
+        let last_y = (to_image_y(visible.max.y).ceil() as usize).saturating_sub(1) / tile;
+
+        let pass = ctx.cumulative_pass_nr();
+        let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        let mut resident = self.resident.lock().unwrap();
+
+        for tile_y in first_y..=last_y {
+            for tile_x in first_x..=last_x {
+                let x0 = tile_x * tile;
+                let y0 = tile_y * tile;
+                let x1 = (x0 + tile).min(width);
+                let y1 = (y0 + tile).min(height);
+
+                let entry = resident.entry((level, tile_x, tile_y)).or_insert_with(|| Tile {
+                    texture: ctx.load_texture(
+                        format!("{}@{}:{},{}", self.name, level, tile_x, tile_y),
+                        crop(image, x0, y0, x1 - x0, y1 - y0),
+                        TextureOptions::default(),
+                    ),
+                    last_used: pass,
+                });
+                entry.last_used = pass;
+
+                let tile_rect = Rect::from_min_max(
+                    egui::pos2(
+                        rect.min.x + x0 as f32 / width as f32 * rect.width(),
+                        rect.min.y + y0 as f32 / height as f32 * rect.height(),
+                    ),
+                    egui::pos2(
+                        rect.min.x + x1 as f32 / width as f32 * rect.width(),
+                        rect.min.y + y1 as f32 / height as f32 * rect.height(),
+                    ),
+                );
+                painter.image(entry.texture.id(), tile_rect, uv, Color32::WHITE);
+            }
+        }
+    }
+}
+
+fn crop(image: &ColorImage, x: usize, y: usize, width: usize, height: usize) -> ColorImage {
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in y..y + height {
+        let start = row * image.size[0] + x;
+        pixels.extend_from_slice(&image.pixels[start..start + width]);
+    }
+    ColorImage {
+        size: [width, height],
+        pixels,
+    }
+}
+
+pub fn color_image(image: &RgbaImage) -> ColorImage {
+    let size = [image.width() as usize, image.height() as usize];
+    ColorImage::from_rgba_unmultiplied(size, image.as_flat_samples().as_slice())
+}
+
+// Halve an oversized page repeatedly until a level fits in a single tile, so
+// zoomed out pages draw from a few small tiles instead of many large ones.
+pub fn build_mip_levels(image: &RgbaImage) -> Vec<ColorImage> {
+    let mut levels = Vec::new();
+    let mut current: Option<RgbaImage> = None;
+    loop {
+        let source = current.as_ref().unwrap_or(image);
+        if source.width().max(source.height()) as usize <= TILE_SIZE {
+            return levels;
+        }
+        let next = imageops::resize(
+            source,
+            (source.width() / 2).max(1),
+            (source.height() / 2).max(1),
+            FilterType::Triangle,
+        );
+        levels.push(color_image(&next));
+        current = Some(next);
+    }
+}
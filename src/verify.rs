@@ -0,0 +1,419 @@
+use anyhow::Result;
+use eframe::egui;
+use image::ImageFormat;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+use crate::archive::{self, ArchiveKind};
+use crate::{decoder, image_files_in_directory, natural_sort_paths, platform};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    // The archive or an entry ends early.
+    Truncated,
+    // The archive cannot be read or an entry fails its CRC check.
+    Corrupt,
+    ZeroByte,
+    Undecodable,
+    // The data is a different image format than the file name says.
+    MismatchedExtension,
+}
+
+impl IssueKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            IssueKind::Truncated => "truncated",
+            IssueKind::Corrupt => "corrupt",
+            IssueKind::ZeroByte => "zero-byte",
+            IssueKind::Undecodable => "undecodable",
+            IssueKind::MismatchedExtension => "mismatched extension",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    // None when the problem is with the archive as a whole.
+    pub entry: Option<String>,
+    pub kind: IssueKind,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeReport {
+    pub path: PathBuf,
+    pub pages: usize,
+    pub issues: Vec<Issue>,
+}
+
+// Archives under `path`, or `path` itself when it is a file. A folder that
+// holds images is checked as a volume too.
+pub fn volumes_under(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut volumes: Vec<PathBuf> = WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !platform::is_hidden_or_junk(entry.path()))
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file() && archive::has_archive_extension(entry.path()))
+        .map(|entry| entry.into_path())
+        .collect();
+    volumes.sort_by(|a, b| natural_sort_paths(a, b));
+    if !image_files_in_directory(path).is_empty() {
+        volumes.insert(0, path.to_path_buf());
+    }
+    volumes
+}
+
+pub fn verify_volume(path: &Path) -> VolumeReport {
+    let mut report = VolumeReport {
+        path: path.to_path_buf(),
+        pages: 0,
+        issues: Vec::new(),
+    };
+    if path.is_dir() {
+        verify_folder(path, &mut report);
+        return report;
+    }
+
+    match archive::detect_kind(path) {
+        Ok(ArchiveKind::Zip) => verify_zip(path, &mut report),
+        Ok(_) => verify_archive(path, &mut report),
+        Err(e) => report.issues.push(Issue {
+            entry: None,
+            kind: IssueKind::Corrupt,
+            detail: format!("{:#}", e),
+        }),
+    }
+    report
+}
+
+fn verify_folder(dir: &Path, report: &mut VolumeReport) {
+    for file in image_files_in_directory(dir) {
+        let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        report.pages += 1;
+        let issue = match fs::read(&file) {
+            Ok(data) => check_page(&name, &data),
+            Err(e) => Some(read_issue(&name, &e)),
+        };
+        report.issues.extend(issue);
+    }
+}
+
+// Read every entry of a ZIP, which also checks its CRC, and decode pages.
+fn verify_zip(path: &Path, report: &mut VolumeReport) {
+    let opened = File::open(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Ok(ZipArchive::new(BufReader::new(file))?));
+    let mut zip = match opened {
+        Ok(zip) => zip,
+        Err(e) => {
+            // The header was there, so a missing central directory means the
+            // download or copy was cut short.
+            report.issues.push(Issue {
+                entry: None,
+                kind: IssueKind::Truncated,
+                detail: format!("{:#}", e),
+            });
+            return;
+        }
+    };
+
+    for index in 0..zip.len() {
+        let mut entry = match zip.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.issues.push(Issue {
+                    entry: None,
+                    kind: IssueKind::Corrupt,
+                    detail: format!("Entry {}: {}", index, e),
+                });
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let is_page = archive::has_image_extension(&name) && !platform::is_junk_archive_entry(&name);
+        let size = entry.size();
+        let data = match archive::read_to_vec(&mut entry, size) {
+            Ok(data) => data,
+            Err(e) => {
+                report.pages += usize::from(is_page);
+                report.issues.push(read_issue(&name, &e));
+                continue;
+            }
+        };
+        if data.len() as u64 != size {
+            report.pages += usize::from(is_page);
+            report.issues.push(Issue {
+                entry: Some(name),
+                kind: IssueKind::Corrupt,
+                detail: format!("Holds {} bytes but its header says {}", data.len(), size),
+            });
+            continue;
+        }
+        if is_page {
+            report.pages += 1;
+            report.issues.extend(check_page(&name, &data));
+        }
+    }
+}
+
+// Other formats are checked through their backend, page by page.
+fn verify_archive(path: &Path, report: &mut VolumeReport) {
+    let mut backend = match archive::open(path) {
+        Ok(backend) => backend,
+        Err(e) => {
+            report.issues.push(Issue {
+                entry: None,
+                kind: IssueKind::Corrupt,
+                detail: format!("{:#}", e),
+            });
+            return;
+        }
+    };
+    for index in archive::page_entries(backend.as_ref()) {
+        let name = backend.entry_names()[index].clone();
+        report.pages += 1;
+        let issue = match backend.read_entry(index) {
+            Ok(data) => check_page(&name, &data),
+            Err(e) => Some(match e.downcast_ref::<io::Error>() {
+                Some(error) => read_issue(&name, error),
+                None => Issue {
+                    entry: Some(name),
+                    kind: IssueKind::Corrupt,
+                    detail: format!("{:#}", e),
+                },
+            }),
+        };
+        report.issues.extend(issue);
+    }
+}
+
+fn read_issue(name: &str, error: &io::Error) -> Issue {
+    let kind = match error.kind() {
+        io::ErrorKind::UnexpectedEof => IssueKind::Truncated,
+        _ => IssueKind::Corrupt,
+    };
+    Issue {
+        entry: Some(name.to_string()),
+        kind,
+        detail: error.to_string(),
+    }
+}
+
+// Decode a page the way the reader does, which picks the decoder from the
+// file extension.
+fn check_page(name: &str, data: &[u8]) -> Option<Issue> {
+    let issue = |kind, detail: String| {
+        Some(Issue {
+            entry: Some(name.to_string()),
+            kind,
+            detail,
+        })
+    };
+    if data.is_empty() {
+        return issue(IssueKind::ZeroByte, "Empty file".to_string());
+    }
+
+    let named = Path::new(name).extension().and_then(ImageFormat::from_extension);
+    match image::guess_format(data) {
+        Ok(actual) if Some(actual) != named => {
+            let detail = format!("Contains {:?} data; the reader cannot open it under this name", actual);
+            match image::load_from_memory_with_format(data, actual) {
+                Ok(_) => issue(IssueKind::MismatchedExtension, detail),
+                Err(e) => issue(IssueKind::Undecodable, e.to_string()),
+            }
+        }
+        _ => match decoder::decode_image_bytes(name, data) {
+            Ok(_) => None,
+            Err(e) => issue(IssueKind::Undecodable, format!("{:#}", e)),
+        },
+    }
+}
+
+enum VerifyEvent {
+    Found(usize),
+    Report(VolumeReport),
+}
+
+// Results of the last verification run, shown as a window.
+pub struct VerifyWindow {
+    pub open: bool,
+    root: Option<PathBuf>,
+    total: usize,
+    reports: Vec<VolumeReport>,
+    running: Option<Receiver<VerifyEvent>>,
+    show_clean: bool,
+}
+
+impl VerifyWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            root: None,
+            total: 0,
+            reports: Vec::new(),
+            running: None,
+            show_clean: false,
+        }
+    }
+
+    // Verify every volume under `root` in the background. A running check is
+    // abandoned.
+    pub fn start(&mut self, root: PathBuf, ctx: &egui::Context) {
+        let (sender, receiver) = mpsc::channel();
+        let ctx = ctx.clone();
+        let walk_root = root.clone();
+        thread::spawn(move || {
+            let volumes = volumes_under(&walk_root);
+            if sender.send(VerifyEvent::Found(volumes.len())).is_err() {
+                return;
+            }
+            for volume in volumes {
+                // Stop once the window has moved on to another run.
+                if sender.send(VerifyEvent::Report(verify_volume(&volume))).is_err() {
+                    return;
+                }
+                ctx.request_repaint();
+            }
+        });
+        self.root = Some(root);
+        self.total = 0;
+        self.reports.clear();
+        self.running = Some(receiver);
+        self.open = true;
+    }
+
+    fn poll(&mut self) {
+        let Some(receiver) = &self.running else {
+            return;
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(VerifyEvent::Found(total)) => self.total = total,
+                Ok(VerifyEvent::Report(report)) => self.reports.push(report),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.running = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    // Draw the report. Returns a volume the user chose to open.
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<PathBuf> {
+        self.poll();
+        let mut open = self.open;
+        let mut selected = None;
+
+        egui::Window::new("Verify Archives")
+            .open(&mut open)
+            .default_width(520.0)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                if let Some(root) = &self.root {
+                    ui.label(root.display().to_string());
+                }
+                let damaged = self.reports.iter().filter(|report| !report.issues.is_empty()).count();
+                ui.horizontal(|ui| {
+                    if self.running.is_some() {
+                        ui.spinner();
+                        ui.label(format!("Checked {} of {} volumes", self.reports.len(), self.total));
+                    } else {
+                        ui.label(format!("Checked {} volumes, {} with problems", self.reports.len(), damaged));
+                    }
+                    ui.checkbox(&mut self.show_clean, "Show volumes without problems");
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                    for (index, report) in self.reports.iter().enumerate() {
+                        if report.issues.is_empty() && !self.show_clean {
+                            continue;
+                        }
+                        ui.push_id(index, |ui| {
+                            let name = report
+                                .path
+                                .file_name()
+                                .map(|name| name.to_string_lossy().into_owned())
+                                .unwrap_or_else(|| report.path.display().to_string());
+                            let summary = match report.issues.len() {
+                                0 => format!("{} - {} pages, OK", name, report.pages),
+                                count => format!("{} - {} pages, {} problems", name, report.pages, count),
+                            };
+                            if ui.link(summary).on_hover_text(report.path.display().to_string()).clicked() {
+                                selected = Some(report.path.clone());
+                            }
+                            for issue in &report.issues {
+                                let entry = issue.entry.as_deref().unwrap_or("archive");
+                                ui.label(format!("    {} ({}): {}", entry, issue.kind.label(), issue.detail));
+                            }
+                        });
+                    }
+                });
+            });
+
+        self.open = open;
+        if !self.open {
+            // Closing the window cancels a running check.
+            self.running = None;
+        }
+        selected
+    }
+}
+
+// Verify from the command line. Returns whether every volume was clean.
+pub fn print_reports(path: &Path, json: bool) -> Result<bool> {
+    let reports: Vec<VolumeReport> = volumes_under(path).iter().map(|volume| verify_volume(volume)).collect();
+    let clean = reports.iter().all(|report| report.issues.is_empty());
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(clean);
+    }
+
+    for report in &reports {
+        let status = match report.issues.len() {
+            0 => "OK".to_string(),
+            count => format!("{} problems", count),
+        };
+        println!("{}: {} pages, {}", report.path.display(), report.pages, status);
+        for issue in &report.issues {
+            let entry = issue.entry.as_deref().unwrap_or("archive");
+            println!("  {} ({}): {}", entry, issue.kind.label(), issue.detail);
+        }
+    }
+    let damaged = reports.iter().filter(|report| !report.issues.is_empty()).count();
+    println!("{} volumes checked, {} with problems", reports.len(), damaged);
+    Ok(clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::testing;
+
+    #[test]
+    fn oversized_entry_header_is_reported_as_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.cbz");
+        testing::write_zip_with_oversized_entry(&path);
+
+        let report = verify_volume(&path);
+        assert_eq!(report.pages, 1);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].entry.as_deref(), Some("001.png"));
+        assert_eq!(report.issues[0].kind, IssueKind::Corrupt);
+    }
+}
@@ -0,0 +1,145 @@
+use eframe::egui;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+
+struct Queue<J> {
+    jobs: VecDeque<J>,
+    shutdown: bool,
+}
+
+struct Shared<J> {
+    queue: Mutex<Queue<J>>,
+    available: Condvar,
+    repaint: OnceLock<egui::Context>,
+}
+
+// Threads that take jobs from a shared queue and send back one result per
+// job, waking the UI after each. Dropping the pool stops the threads once
+// they finish their current job.
+pub struct WorkerPool<J, R> {
+    shared: Arc<Shared<J>>,
+    results: Receiver<R>,
+}
+
+impl<J: Send + 'static, R: Send + 'static> WorkerPool<J, R> {
+    // `work` is given the UI context once one has been set.
+    pub fn new<F>(name: &str, workers: usize, work: F) -> Self
+    where
+        F: Fn(J, Option<&egui::Context>) -> R + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                jobs: VecDeque::new(),
+                shutdown: false,
+            }),
+            available: Condvar::new(),
+            repaint: OnceLock::new(),
+        });
+        let (sender, results) = mpsc::channel();
+        let work = Arc::new(work);
+
+        for i in 0..workers.max(1) {
+            let shared = shared.clone();
+            let sender = sender.clone();
+            let work = work.clone();
+            thread::Builder::new()
+                .name(format!("{}-{}", name, i))
+                .spawn(move || worker_loop(&shared, &sender, work.as_ref()))
+                .unwrap_or_else(|e| panic!("Failed to spawn {} thread: {}", name, e));
+        }
+
+        Self { shared, results }
+    }
+
+    // Wake the UI whenever a job finishes.
+    pub fn set_repaint_context(&self, ctx: egui::Context) {
+        let _ = self.shared.repaint.set(ctx);
+    }
+
+    // Queue a job, ahead of the queued ones when `urgent`.
+    pub fn push(&self, job: J, urgent: bool) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if urgent {
+            queue.jobs.push_front(job);
+        } else {
+            queue.jobs.push_back(job);
+        }
+        self.shared.available.notify_one();
+    }
+
+    // Move the first queued job that matches to the front of the queue.
+    pub fn move_to_front(&self, matches: impl Fn(&J) -> bool) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if let Some(job) = queue.jobs.iter().position(matches).and_then(|i| queue.jobs.remove(i)) {
+            queue.jobs.push_front(job);
+        }
+    }
+
+    // Drop every queued job; jobs already running still finish.
+    pub fn clear(&self) {
+        self.shared.queue.lock().unwrap().jobs.clear();
+    }
+
+    pub fn try_recv(&self) -> Option<R> {
+        self.results.try_recv().ok()
+    }
+}
+
+impl<J, R> Drop for WorkerPool<J, R> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.shutdown = true;
+        queue.jobs.clear();
+        self.shared.available.notify_all();
+    }
+}
+
+fn worker_loop<J, R>(shared: &Shared<J>, sender: &Sender<R>, work: &dyn Fn(J, Option<&egui::Context>) -> R) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                if let Some(job) = queue.jobs.pop_front() {
+                    break job;
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+
+        if sender.send(work(job, shared.repaint.get())).is_err() {
+            return;
+        }
+        if let Some(ctx) = shared.repaint.get() {
+            ctx.request_repaint();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn runs_every_job() {
+        let pool = WorkerPool::new("test", 3, |job: u32, _| job * 2);
+        for job in 0..20 {
+            pool.push(job, job % 2 == 0);
+        }
+        let mut results = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while results.len() < 20 && Instant::now() < deadline {
+            match pool.try_recv() {
+                Some(result) => results.push(result),
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        results.sort();
+        assert_eq!(results, (0..20).map(|job| job * 2).collect::<Vec<_>>());
+    }
+}